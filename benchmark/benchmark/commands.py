@@ -34,16 +34,16 @@ class CommandMaker:
         assert isinstance(key, str)
         assert isinstance(debug, bool)
         #v = '-vvv' if debug else '-vv'
-        return (f'./node --config {key} --ip ip_file '
-                f'--sleep {delay} --vsstype hyb --batch 100 --epsilon {ep} --delta {delta} --val {val} --tri {tri} --syncer syncer')
+        return (f'./node run-hyb --config {key} --ip ip_file '
+                f'--sleep {delay} --epsilon {ep} --delta {delta} --val {val} --tri {tri}')
     
     @staticmethod
     def run_syncer(key,delay, debug=False):
         assert isinstance(key, str)
         assert isinstance(debug, bool)
         #v = '-vvv' if debug else '-vv'
-        return (f'./node --config {key} --ip ip_file '
-                f'--sleep {delay} --vsstype sync --batch 100 --epsilon 10 --delta 5000 --val 100 --tri 10 --syncer syncer')
+        return (f'./node syncer --config {key} --ip ip_file '
+                f'--epsilon 10 --delta 5000 --tri 10 --syncer syncer')
 
     @staticmethod
     def run_worker(keys, committee, store, parameters, id, debug=False):
@@ -0,0 +1,79 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+/// Splits `host:port`. The host is an IPv4 address, a DNS name, or an IPv6
+/// address in brackets, e.g. `[::1]:8500`.
+pub fn split_host_port(addr: &str) -> Option<(&str, u16)> {
+    let (host, port) = match addr.strip_prefix('[') {
+        Some(rest) => {
+            let (host, port) = rest.split_once("]:")?;
+            host.parse::<Ipv6Addr>().ok()?;
+            (host, port)
+        },
+        None => {
+            let (host, port) = addr.rsplit_once(':')?;
+            // An IPv6 address needs brackets to tell it from the port
+            if host.contains(':') {
+                return None;
+            }
+            (host, port)
+        },
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some((host, port.parse().ok()?))
+}
+
+/// `host:port`, with an IPv6 host in brackets
+pub fn join_host_port(host: &str, port: u16) -> String {
+    match host.contains(':') {
+        true => format!("[{}]:{}", host, port),
+        false => format!("{}:{}", host, port),
+    }
+}
+
+/// Resolves a `host:port` address, looking a DNS name up and taking the
+/// first address it has
+pub fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    let (host, port) = split_host_port(addr).ok_or_else(|| io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} is not a host:port address", addr),
+    ))?;
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, port));
+    }
+    (host, port).to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} has no address", host)))
+}
+
+/// The wildcard address of the family of `ip`
+pub fn unspecified(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_host_port() {
+        assert_eq!(split_host_port("10.0.0.1:8500"), Some(("10.0.0.1", 8500)));
+        assert_eq!(split_host_port("[fd00::1]:8500"), Some(("fd00::1", 8500)));
+        assert_eq!(split_host_port("node-3.lab:8500"), Some(("node-3.lab", 8500)));
+        assert_eq!(split_host_port("fd00::1:8500"), None);
+        assert_eq!(split_host_port("[node]:8500"), None);
+        assert_eq!(split_host_port(":8500"), None);
+        assert_eq!(split_host_port("10.0.0.1"), None);
+        assert_eq!(join_host_port("fd00::1", 8500), "[fd00::1]:8500");
+        assert_eq!(join_host_port("10.0.0.1", 8500), "10.0.0.1:8500");
+
+        assert_eq!(resolve("[::1]:8500").unwrap(), "[::1]:8500".parse().unwrap());
+        assert_eq!(resolve("localhost:8500").unwrap().port(), 8500);
+        assert!(resolve("localhost").is_err());
+    }
+}
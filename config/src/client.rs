@@ -7,12 +7,10 @@ use crypto::Algorithm;
 use fnv::FnvHashMap as HashMap;
 use super::{
     ParseError,
-    is_valid_replica
+    LoadError,
+    is_valid_replica,
+    load
 };
-use std::fs::File;
-use std::io::prelude::*;
-use serde_json::from_reader;
-use toml::from_str;
 
 #[derive(Debug, Serialize, Deserialize, Clone,PartialEq)]
 pub struct Client {
@@ -88,43 +86,26 @@ impl Client {
         }
     } 
 
-    pub fn from_json(filename:String) -> Client {
-        let f = File::open(filename)
-            .unwrap();
-        let c: Client = from_reader(f)
-            .unwrap();
-        return c;
+    /// Reads a config in json, binary (`.dat`), toml or yaml, by file
+    /// extension
+    pub fn load(filename:&str) -> Result<Client, LoadError> {
+        load::from_file(filename)
     }
 
-    pub fn from_toml(filename:String) -> Client {
-        let mut buf = String::new();
-        let mut f = File::open(filename)
-            .unwrap();
-        f.read_to_string(&mut buf)
-            .unwrap();
-        let c:Client = from_str(&buf)
-            .unwrap();
-        return c;
+    pub fn from_json(filename:String) -> Result<Client, LoadError> {
+        load::from_json(&filename)
     }
 
-    pub fn from_yaml(filename:String) -> Client {
-        let f = File::open(filename)
-            .unwrap();
-        let c:Client = serde_yaml::from_reader(f)
-            .unwrap();
-        return c;
+    pub fn from_toml(filename:String) -> Result<Client, LoadError> {
+        load::from_toml(&filename)
     }
 
-    pub fn from_bin(filename:String) -> Client {
-        let mut buf = Vec::new();
-        let mut f = File::open(filename)
-            .unwrap();
-        f.read_to_end(&mut buf)
-            .unwrap();
-        let bytes:&[u8] = &buf;
-        let c:Client = bincode::deserialize(bytes)
-            .unwrap();
-        return c;
+    pub fn from_yaml(filename:String) -> Result<Client, LoadError> {
+        load::from_yaml(&filename)
+    }
+
+    pub fn from_bin(filename:String) -> Result<Client, LoadError> {
+        load::from_bin(&filename)
     }
 
     pub fn update_config(&mut self, ips: Vec<String>) {
@@ -1,3 +1,5 @@
+use types::{ProtocolId, Replica};
+
 #[derive(Debug)]
 pub enum ParseError {
     // actual, expected
@@ -12,11 +14,49 @@ pub enum ParseError {
     InvalidSkSize(usize),
     // feature name that is not implemented
     Unimplemented(&'static str),
+    // multicast group address
+    InvalidMulticastGroup(std::net::Ipv4Addr),
+    // multicast datagram size
+    InvalidMulticastMtu(usize),
+    // protocol, f, n
+    InsufficientResilience(ProtocolId, usize, usize),
+    // map name, replicas without an entry
+    IncompleteKeyMap(&'static str, Vec<Replica>),
+    // the two replicas whose shared keys differ
+    AsymmetricKeys(Replica, Replica),
+    // replica, address that does not parse
+    InvalidAddress(Replica, String),
+    // the two replicas listening on the same address
+    DuplicateAddress(Replica, Replica, String),
+    // replica, field that differs from the other configs
+    MismatchedConfig(Replica, &'static str),
+    // replica id given to more than one config
+    DuplicateId(Replica),
+    // replicas without a config in the set
+    MissingConfigs(Vec<Replica>),
+    // parameter, what is wrong with it
+    InvalidProtocolParam(&'static str, &'static str),
+    // protocol, parameter neither the config nor the command line gives
+    MissingProtocolParam(ProtocolId, &'static str),
+    // protocol to run, protocol of the config's section
+    WrongProtocol(ProtocolId, ProtocolId),
+    // revoked replicas, f
+    TooManyRevoked(usize, usize),
+    // map name, revoked replica it still has a key for
+    RevokedKey(&'static str, Replica),
+    // replica whose config is revoked
+    Revoked(Replica),
+    // replica whose secrets are still encrypted
+    Sealed(Replica),
+    // link emulation parameter, what is wrong with it
+    InvalidLinkParam(&'static str, &'static str),
+    // replica, address, why it does not resolve
+    UnresolvedAddress(Replica, String, String),
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match *self {
+        match self {
             ParseError::InvalidMapLen(exp, actual) => 
             write!(f, "invalid map length: expected {}, got {}", exp, actual),
             ParseError::IncorrectFaults(fault, n) => 
@@ -29,6 +69,44 @@ impl std::fmt::Display for ParseError {
             write!(f, "{} feature is not yet implemented", feature),
             ParseError::InvalidSkSize(s) =>
             write!(f, "invalid secret key size ({})", s),
+            ParseError::InvalidMulticastGroup(ip) =>
+            write!(f, "{} is not a multicast address", ip),
+            ParseError::InvalidMulticastMtu(mtu) =>
+            write!(f, "invalid multicast datagram size ({})", mtu),
+            ParseError::InsufficientResilience(protocol, fault, n) =>
+            write!(f, "{:?} needs n > 3f, but n = {} and f = {}", protocol, n, fault),
+            ParseError::IncompleteKeyMap(map, missing) =>
+            write!(f, "{} has no key for replicas {:?}", map, missing),
+            ParseError::AsymmetricKeys(i, j) =>
+            write!(f, "sk_map of replicas {} and {} disagree on the key they share", i, j),
+            ParseError::InvalidAddress(r, addr) =>
+            write!(f, "net_map entry of replica {} is not a host:port address with IPv6 hosts in brackets ({})", r, addr),
+            ParseError::DuplicateAddress(i, j, addr) =>
+            write!(f, "net_map gives replicas {} and {} the same address {}", i, j, addr),
+            ParseError::MismatchedConfig(r, field) =>
+            write!(f, "{} of replica {} differs from the other configs", field, r),
+            ParseError::DuplicateId(r) =>
+            write!(f, "more than one config has id {}", r),
+            ParseError::MissingConfigs(missing) =>
+            write!(f, "no config for replicas {:?}", missing),
+            ParseError::InvalidProtocolParam(param, why) =>
+            write!(f, "protocol parameter {} {}", param, why),
+            ParseError::MissingProtocolParam(protocol, param) =>
+            write!(f, "{:?} needs {}, in the protocol section or on the command line", protocol, param),
+            ParseError::WrongProtocol(expected, found) =>
+            write!(f, "running {:?} but the protocol section is for {:?}", expected, found),
+            ParseError::TooManyRevoked(revoked, fault) =>
+            write!(f, "{} replicas are revoked but only f = {} faults are tolerated", revoked, fault),
+            ParseError::RevokedKey(map, r) =>
+            write!(f, "{} still holds a key of revoked replica {}", map, r),
+            ParseError::Revoked(r) =>
+            write!(f, "replica {} is revoked", r),
+            ParseError::Sealed(r) =>
+            write!(f, "the secrets of replica {} are still encrypted", r),
+            ParseError::InvalidLinkParam(param, why) =>
+            write!(f, "link emulation {} {}", param, why),
+            ParseError::UnresolvedAddress(r, addr, e) =>
+            write!(f, "unable to resolve the address {} of replica {}: {}", addr, r, e),
         }
     }
 }
@@ -42,6 +120,25 @@ impl std::error::Error for ParseError {
             ParseError::InvalidPkSize(_) => "invalid public key size",
             ParseError::InvalidSkSize(_) => "invalid secret key size",
             ParseError::Unimplemented(_) => "feature unimplemented",
+            ParseError::InvalidMulticastGroup(_) => "invalid multicast group",
+            ParseError::InvalidMulticastMtu(_) => "invalid multicast datagram size",
+            ParseError::InsufficientResilience(_,_,_) => "too many faults for the protocol",
+            ParseError::IncompleteKeyMap(_,_) => "incomplete key map",
+            ParseError::AsymmetricKeys(_,_) => "asymmetric shared keys",
+            ParseError::InvalidAddress(_,_) => "invalid address",
+            ParseError::DuplicateAddress(_,_,_) => "duplicate address",
+            ParseError::MismatchedConfig(_,_) => "mismatched configs",
+            ParseError::DuplicateId(_) => "duplicate replica id",
+            ParseError::MissingConfigs(_) => "missing configs",
+            ParseError::InvalidProtocolParam(_,_) => "invalid protocol parameter",
+            ParseError::MissingProtocolParam(_,_) => "missing protocol parameter",
+            ParseError::WrongProtocol(_,_) => "protocol section for another protocol",
+            ParseError::TooManyRevoked(_,_) => "too many revoked replicas",
+            ParseError::RevokedKey(_,_) => "key of a revoked replica",
+            ParseError::Revoked(_) => "revoked replica",
+            ParseError::Sealed(_) => "encrypted secrets",
+            ParseError::InvalidLinkParam(_,_) => "invalid link emulation parameter",
+            ParseError::UnresolvedAddress(_,_,_) => "unresolved address",
         }
     }
 
@@ -50,4 +147,86 @@ impl std::error::Error for ParseError {
             _ => None,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Failure to load a config file. Every variant names the file.
+#[derive(Debug)]
+pub enum LoadError {
+    // path, cause
+    Io(String, std::io::Error),
+    // path, format, field the decoder stopped at, cause
+    Decode(String, &'static str, String, String),
+    // path, extension
+    UnknownFormat(String, String),
+    // path, why its secrets could not be decrypted
+    Sealed(String, SealError),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::Io(path, e) =>
+            write!(f, "unable to read {}: {}", path, e),
+            LoadError::Decode(path, format, field, e) if field.is_empty() =>
+            write!(f, "{} is not a valid {} config: {}", path, format, e),
+            LoadError::Decode(path, format, field, e) =>
+            write!(f, "{} is not a valid {} config: field `{}`: {}", path, format, field, e),
+            LoadError::UnknownFormat(path, ext) =>
+            write!(f, "{} has unknown config extension `{}`; expected json, dat, toml, yaml or yml", path, ext),
+            LoadError::Sealed(path, e) =>
+            write!(f, "unable to decrypt the secrets of {}: {}", path, e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(_, e) => Some(e),
+            LoadError::Sealed(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Failure to encrypt or decrypt the secrets of a config
+#[derive(Debug)]
+pub enum SealError {
+    // where the passphrase was to come from, cause
+    Passphrase(String, String),
+    EmptyPassphrase,
+    // the passphrase is wrong or the ciphertext was altered
+    Decrypt,
+    Encrypt,
+    Random,
+    // field of the sealed section that is invalid
+    Malformed(&'static str),
+    // the secrets do not encode or decode
+    Encoding(String),
+    AlreadySealed,
+}
+
+impl std::fmt::Display for SealError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SealError::Passphrase(from, e) =>
+            write!(f, "unable to read the passphrase from {}: {}", from, e),
+            SealError::EmptyPassphrase =>
+            write!(f, "the passphrase is empty"),
+            SealError::Decrypt =>
+            write!(f, "wrong passphrase, or the encrypted secrets were altered"),
+            SealError::Encrypt =>
+            write!(f, "encryption failed"),
+            SealError::Random =>
+            write!(f, "no randomness for the salt and nonce"),
+            SealError::Malformed(field) =>
+            write!(f, "invalid {} in the encrypted secrets", field),
+            SealError::Encoding(e) =>
+            write!(f, "unable to encode the secrets: {}", e),
+            SealError::AlreadySealed =>
+            write!(f, "the secrets are already encrypted"),
+        }
+    }
+}
+
+impl std::error::Error for SealError {}
@@ -7,6 +7,23 @@ pub use node::*;
 mod error;
 pub use error::*;
 
+mod multicast;
+pub use multicast::*;
+
+mod protocol;
+pub use protocol::*;
+
+mod links;
+pub use links::*;
+
+mod address;
+pub use address::*;
+
+mod secrets;
+pub use secrets::*;
+
+mod load;
+
 fn is_valid_replica(r:types::Replica, n:usize) -> bool {
     n>r as usize
 }
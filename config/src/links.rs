@@ -0,0 +1,141 @@
+use serde::{
+    Serialize,
+    Deserialize
+};
+use types::Replica;
+use super::{ParseError, LoadError, is_valid_replica, load};
+
+/// Emulates a wide area network on the links from this node: every
+/// message is delayed, dropped or held back by a partition before it goes
+/// out. Meant for running geo-distributed setups on one host.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct LinkEmulation {
+    /// Applies to the links no rule matches
+    #[serde(default)]
+    pub default: LinkProfile,
+    /// The first rule matching a link decides its profile
+    #[serde(default)]
+    pub links: Vec<LinkRule>,
+    #[serde(default)]
+    pub partitions: Vec<Partition>,
+    /// Seed for the delays and drops; each node mixes in its id
+    #[serde(default)]
+    pub seed: u64,
+}
+
+/// The profile of the links from `from` to `to`; an unset end matches
+/// every replica
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LinkRule {
+    #[serde(default)]
+    pub from: Option<Replica>,
+    #[serde(default)]
+    pub to: Option<Replica>,
+    pub profile: LinkProfile,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct LinkProfile {
+    #[serde(default)]
+    pub delay: Delay,
+    /// Milliseconds of extra delay, uniform in [0, jitter_ms]
+    #[serde(default)]
+    pub jitter_ms: u64,
+    /// Probability that a message is lost
+    #[serde(default)]
+    pub drop: f64,
+}
+
+/// One-way delay of a message, in milliseconds
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Delay {
+    Fixed(u64),
+    Uniform { min_ms: u64, max_ms: u64 },
+    /// Cut off at zero
+    Normal { mean_ms: u64, std_ms: u64 },
+    Exponential { mean_ms: u64 },
+}
+
+impl Default for Delay {
+    fn default() -> Delay {
+        Delay::Fixed(0)
+    }
+}
+
+/// Splits the replicas into groups that cannot reach each other from
+/// `start_ms` to `end_ms` after the syncer starts a run. Replicas in no
+/// group form one more group. Messages across groups are held until the
+/// partition heals, as TCP would retransmit them.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Partition {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub groups: Vec<Vec<Replica>>,
+}
+
+impl Partition {
+    /// Whether the partition separates `a` and `b`
+    pub fn separates(&self, a: Replica, b: Replica) -> bool {
+        let group = |r: Replica| self.groups.iter().position(|g| g.contains(&r));
+        group(a) != group(b)
+    }
+}
+
+impl LinkEmulation {
+    /// Reads the emulation in json, toml or yaml, by file extension
+    pub fn load(filename:&str) -> Result<LinkEmulation, LoadError> {
+        load::from_file(filename)
+    }
+
+    /// The profile of the link from `from` to `to`
+    pub fn profile(&self, from: Replica, to: Replica) -> &LinkProfile {
+        self.links.iter()
+            .find(|rule| rule.from.unwrap_or(from) == from && rule.to.unwrap_or(to) == to)
+            .map(|rule| &rule.profile)
+            .unwrap_or(&self.default)
+    }
+
+    pub fn validate(&self, num_nodes: usize) -> Result<(), ParseError> {
+        for rule in self.links.iter() {
+            for rep in rule.from.iter().chain(rule.to.iter()) {
+                if !is_valid_replica(*rep, num_nodes) {
+                    return Err(ParseError::InvalidMapEntry(*rep));
+                }
+            }
+        }
+        for profile in std::iter::once(&self.default).chain(self.links.iter().map(|r| &r.profile)) {
+            profile.validate()?;
+        }
+        for partition in self.partitions.iter() {
+            if partition.end_ms <= partition.start_ms {
+                return Err(ParseError::InvalidLinkParam("partition", "has to end after it starts"));
+            }
+            let mut seen = Vec::new();
+            for rep in partition.groups.iter().flatten() {
+                if !is_valid_replica(*rep, num_nodes) {
+                    return Err(ParseError::InvalidMapEntry(*rep));
+                }
+                if seen.contains(rep) {
+                    return Err(ParseError::InvalidLinkParam("partition", "lists a replica in more than one group"));
+                }
+                seen.push(*rep);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LinkProfile {
+    fn validate(&self) -> Result<(), ParseError> {
+        if !(0.0..=1.0).contains(&self.drop) {
+            return Err(ParseError::InvalidLinkParam("drop", "has to be a probability in [0, 1]"));
+        }
+        if let Delay::Uniform { min_ms, max_ms } = self.delay {
+            if max_ms < min_ms {
+                return Err(ParseError::InvalidLinkParam("delay", "has max_ms below min_ms"));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use bincode::Options;
+use serde::de::{Deserializer, DeserializeOwned};
+
+use super::LoadError;
+
+fn read(path: &str) -> Result<Vec<u8>, LoadError> {
+    let mut buf = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut buf))
+        .map_err(|e| LoadError::Io(path.to_string(), e))?;
+    Ok(buf)
+}
+
+fn read_string(path: &str) -> Result<String, LoadError> {
+    String::from_utf8(read(path)?)
+        .map_err(|e| LoadError::Decode(path.to_string(), "text", String::new(), e.to_string()))
+}
+
+/// Decodes with `de`, recording the field the decoder was in when it failed
+fn decode<'de, D, T>(path: &str, format: &'static str, de: D) -> Result<T, LoadError>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let field = e.path().to_string();
+        // An empty path means the failure was at the top level
+        let field = if field == "." { String::new() } else { field };
+        LoadError::Decode(path.to_string(), format, field, e.into_inner().to_string())
+    })
+}
+
+pub(crate) fn from_json<T: DeserializeOwned>(path: &str) -> Result<T, LoadError> {
+    let buf = read(path)?;
+    decode(path, "json", &mut serde_json::Deserializer::from_slice(&buf))
+}
+
+pub(crate) fn from_toml<T: DeserializeOwned>(path: &str) -> Result<T, LoadError> {
+    let buf = read_string(path)?;
+    decode(path, "toml", &mut toml::Deserializer::new(&buf))
+}
+
+pub(crate) fn from_yaml<T: DeserializeOwned>(path: &str) -> Result<T, LoadError> {
+    let buf = read_string(path)?;
+    decode(path, "yaml", serde_yaml::Deserializer::from_str(&buf))
+}
+
+pub(crate) fn from_bin<T: DeserializeOwned>(path: &str) -> Result<T, LoadError> {
+    let buf = read(path)?;
+    // The options of bincode::serialize, which genconfig writes with
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes();
+    decode(path, "binary", &mut bincode::Deserializer::from_slice(&buf, options))
+}
+
+/// Picks the decoder by the file extension: json, dat, toml, yaml or yml
+pub(crate) fn from_file<T: DeserializeOwned>(path: &str) -> Result<T, LoadError> {
+    let ext = Path::new(path).extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+    match ext {
+        "json" => from_json(path),
+        "dat" => from_bin(path),
+        "toml" => from_toml(path),
+        "yaml" | "yml" => from_yaml(path),
+        _ => Err(LoadError::UnknownFormat(path.to_string(), ext.to_string())),
+    }
+}
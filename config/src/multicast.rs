@@ -0,0 +1,67 @@
+use serde::{
+    Serialize,
+    Deserialize
+};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use super::ParseError;
+
+/// Sends broadcasts as UDP multicast datagrams instead of n TCP messages.
+/// Meant for nodes sharing a LAN segment.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MulticastConfig {
+    /// Group address and port, e.g. 239.255.0.1:7000
+    pub group: SocketAddrV4,
+    /// Local interface to join the group on; 127.0.0.1 for loopback runs
+    #[serde(default = "default_interface")]
+    pub interface: Ipv4Addr,
+    /// Largest datagram we send; larger messages are fragmented
+    #[serde(default = "default_mtu")]
+    pub mtu: usize,
+    /// How often (in milliseconds) to announce our sequence number and
+    /// request lost messages
+    #[serde(default = "default_nack_interval")]
+    pub nack_interval_ms: u64,
+    #[serde(default = "default_ttl")]
+    pub ttl: u32,
+}
+
+fn default_interface() -> Ipv4Addr {
+    Ipv4Addr::UNSPECIFIED
+}
+
+fn default_mtu() -> usize {
+    1400
+}
+
+fn default_nack_interval() -> u64 {
+    20
+}
+
+fn default_ttl() -> u32 {
+    1
+}
+
+/// Fragment headers and MACs need some room in every datagram
+pub const MIN_MULTICAST_MTU: usize = 256;
+
+impl MulticastConfig {
+    pub fn new(group: SocketAddrV4) -> MulticastConfig {
+        MulticastConfig {
+            group,
+            interface: default_interface(),
+            mtu: default_mtu(),
+            nack_interval_ms: default_nack_interval(),
+            ttl: default_ttl(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ParseError> {
+        if !self.group.ip().is_multicast() {
+            return Err(ParseError::InvalidMulticastGroup(*self.group.ip()));
+        }
+        if self.mtu < MIN_MULTICAST_MTU || self.mtu > u16::MAX as usize {
+            return Err(ParseError::InvalidMulticastMtu(self.mtu));
+        }
+        Ok(())
+    }
+}
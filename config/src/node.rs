@@ -2,18 +2,28 @@ use serde::{
     Serialize, 
     Deserialize
 };
-use types::Replica;
+use types::{ProtocolId, Replica};
 use crypto::Algorithm;
 use fnv::FnvHashMap as HashMap;
 use super::{
     ParseError,
-    is_valid_replica
+    LoadError,
+    SealError,
+    SealedSecrets,
+    Passphrase,
+    MulticastConfig,
+    LinkEmulation,
+    ProtocolConfig,
+    is_valid_replica,
+    join_host_port,
+    resolve,
+    split_host_port,
+    unspecified,
+    load
 };
-use std::fs::File;
-use std::io::prelude::*;
-use std::net::{SocketAddr, SocketAddrV4};
-use serde_json::from_reader;
-use toml::from_str;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use zeroize::{Zeroize, Zeroizing};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Node {
@@ -30,28 +40,126 @@ pub struct Node {
     pub client_addr: SocketAddr,
     pub payload: usize,
     
+    /// Legacy comma separated protocol parameters, superseded by `protocol`
+    #[serde(default)]
     pub prot_payload: String,
+    /// The protocol to run and its inputs; flags given to the node override
+    /// the values here
+    #[serde(default)]
+    pub protocol: Option<ProtocolConfig>,
+    /// Run nonce carried in every message header; nodes drop messages from
+    /// other sessions
+    #[serde(default)]
+    pub session: u64,
+    /// Broadcast over UDP multicast instead of TCP when set
+    #[serde(default)]
+    pub multicast: Option<MulticastConfig>,
+    /// Address the listeners bind to; by default the wildcard address of
+    /// the family of the node's own address
+    #[serde(default)]
+    pub bind_addr: Option<IpAddr>,
+    /// Delays, drops and partitions applied to the messages this node sends
+    #[serde(default)]
+    pub links: Option<LinkEmulation>,
+    /// Serve Prometheus metrics on this localhost port when set
+    #[serde(default)]
+    pub metrics_port: Option<u16>,
+    /// Milliseconds a round may go without progress before the node dumps
+    /// where it is stuck; unset means the default, zero turns it off
+    #[serde(default)]
+    pub stall_timeout_ms: Option<u64>,
     /// Crypto primitives
     pub crypto_alg: Algorithm,
     pub pk_map: HashMap<Replica, Vec<u8>>,
     pub secret_key_bytes: Vec<u8>,
     /// For authenticated channels
     pub sk_map: HashMap<Replica,Vec<u8>>,
+    /// `secret_key_bytes` and `sk_map`, encrypted with a passphrase; both
+    /// are empty while it is set
+    #[serde(default)]
+    pub sealed: Option<SealedSecrets>,
+    /// Replicas whose keys were revoked. No one holds a key for them, so
+    /// their messages fail authentication; they count against num_faults.
+    #[serde(default)]
+    pub revoked: Vec<Replica>,
 
     /// OpenSSL Certificate Details
     pub my_cert: Vec<u8>,
     pub my_cert_key: Vec<u8>,
     pub root_cert: Vec<u8>,
+
+    /// The file the config was loaded from, reread when the membership
+    /// changes
+    #[serde(skip)]
+    pub source: Option<String>,
+    /// Where the passphrase that decrypted the secrets came from, to reread
+    /// the config with
+    #[serde(skip)]
+    pub passphrase: Option<Passphrase>,
+}
+
+/// The plaintext of `Node::sealed`
+#[derive(Serialize, Deserialize, Default)]
+struct Secrets {
+    secret_key_bytes: Vec<u8>,
+    sk_map: HashMap<Replica, Vec<u8>>,
+}
+
+impl Drop for Secrets {
+    fn drop(&mut self) {
+        zeroize_keys(&mut self.secret_key_bytes, &mut self.sk_map);
+    }
+}
+
+/// Key buffers are wiped when a config is dropped
+impl Drop for Node {
+    fn drop(&mut self) {
+        zeroize_keys(&mut self.secret_key_bytes, &mut self.sk_map);
+        self.my_cert_key.zeroize();
+    }
 }
 
+fn zeroize_keys(secret_key_bytes: &mut Vec<u8>, sk_map: &mut HashMap<Replica, Vec<u8>>) {
+    secret_key_bytes.zeroize();
+    for key in sk_map.values_mut() {
+        key.zeroize();
+    }
+}
+
+/// Keys shared with each replica, for the processes that outlive the
+/// config they were loaded from; every key is wiped when dropped
+pub type KeyMap = HashMap<Replica, Zeroizing<Vec<u8>>>;
+
 impl Node {
+    /// A copy of `sk_map` that is wiped when dropped
+    pub fn key_map(&self) -> KeyMap {
+        self.sk_map.iter()
+            .map(|(rep, sk)| (*rep, Zeroizing::new(sk.clone())))
+            .collect()
+    }
+
     pub fn validate(&self) -> Result<(), ParseError> {
+        if self.is_sealed() {
+            return Err(ParseError::Sealed(self.id));
+        }
         if self.net_map.len() != self.num_nodes+1 {
             return Err(ParseError::InvalidMapLen(self.num_nodes+1, self.net_map.len()));
         }
         if 2*self.num_faults >= self.num_nodes {
             return Err(ParseError::IncorrectFaults(self.num_faults, self.num_nodes));
         }
+        if let Some(multicast) = &self.multicast {
+            multicast.validate()?;
+        }
+        if let Some(links) = &self.links {
+            if self.multicast.is_some() {
+                return Err(ParseError::InvalidLinkParam("links", "cannot be combined with multicast, which bypasses the emulated links"));
+            }
+            links.validate(self.num_nodes)?;
+        }
+        if let Some(protocol) = &self.protocol {
+            protocol.validate()?;
+        }
         // for repl in &self.net_map {
         //     if !is_valid_replica(*repl.0, self.num_nodes) {
         //         return Err(ParseError::InvalidMapEntry(*repl.0));
@@ -100,6 +208,131 @@ impl Node {
                 }
             }
         }
+        self.validate_revoked()?;
+        self.validate_keys_complete()?;
+        self.validate_addresses()?;
+        Ok(())
+    }
+
+    pub fn is_revoked(&self, rep: Replica) -> bool {
+        self.revoked.contains(&rep)
+    }
+
+    fn validate_revoked(&self) -> Result<(), ParseError> {
+        for rep in &self.revoked {
+            if !is_valid_replica(*rep, self.num_nodes) {
+                return Err(ParseError::InvalidMapEntry(*rep));
+            }
+        }
+        if self.is_revoked(self.id) {
+            return Err(ParseError::Revoked(self.id));
+        }
+        if self.revoked.len() > self.num_faults {
+            return Err(ParseError::TooManyRevoked(self.revoked.len(), self.num_faults));
+        }
+        Ok(())
+    }
+
+    /// Everything `validate` checks, plus the resilience bound of `protocol`
+    /// and that the protocol section is for it
+    pub fn validate_for(&self, protocol: ProtocolId) -> Result<(), ParseError> {
+        self.validate()?;
+        if let Some(section) = &self.protocol {
+            if section.id() != protocol {
+                return Err(ParseError::WrongProtocol(protocol, section.id()));
+            }
+        }
+        if self.num_nodes <= resilience(protocol)*self.num_faults {
+            return Err(ParseError::InsufficientResilience(protocol, self.num_faults, self.num_nodes));
+        }
+        Ok(())
+    }
+
+    /// Every replica needs a public key, or a shared key without a PKI,
+    /// unless it is revoked
+    fn validate_keys_complete(&self) -> Result<(), ParseError> {
+        let (name, map) = match self.crypto_alg {
+            Algorithm::NOPKI => ("sk_map", &self.sk_map),
+            _ => ("pk_map", &self.pk_map),
+        };
+        if let Some(rep) = self.revoked.iter().find(|r| map.contains_key(r)) {
+            return Err(ParseError::RevokedKey(name, *rep));
+        }
+        let missing: Vec<Replica> = (0..self.num_nodes)
+            .filter(|r| !map.contains_key(r) && !self.is_revoked(*r))
+            .collect();
+        if !missing.is_empty() {
+            return Err(ParseError::IncompleteKeyMap(name, missing));
+        }
+        Ok(())
+    }
+
+    /// No two entries of net_map, the syncer included, may listen on the
+    /// same address. The wildcard and loopback addresses count as one host,
+    /// since that is what they mean on a single machine.
+    fn validate_addresses(&self) -> Result<(), ParseError> {
+        let mut seen: HashMap<(String, u16), Replica> = HashMap::default();
+        let entries: BTreeMap<&Replica, &String> = self.net_map.iter().collect();
+        for (rep, addr) in entries {
+            let (host, port) = split_addr(addr)
+                .ok_or_else(|| ParseError::InvalidAddress(*rep, addr.clone()))?;
+            if let Some(other) = seen.insert((host, port), *rep) {
+                return Err(ParseError::DuplicateAddress(other, *rep, addr.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the configs of a deployment agree with each other: one
+    /// config per replica that is not revoked, the same view of the system
+    /// in each, and without a PKI, both ends of a link holding the same key
+    pub fn validate_set(nodes: &[Node]) -> Result<(), ParseError> {
+        let first = match nodes.first() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        let mut by_id: BTreeMap<Replica, &Node> = BTreeMap::new();
+        for node in nodes {
+            if !is_valid_replica(node.id, first.num_nodes) {
+                return Err(ParseError::InvalidMapEntry(node.id));
+            }
+            if by_id.insert(node.id, node).is_some() {
+                return Err(ParseError::DuplicateId(node.id));
+            }
+            if first.is_revoked(node.id) {
+                return Err(ParseError::Revoked(node.id));
+            }
+            if node.num_nodes != first.num_nodes {
+                return Err(ParseError::MismatchedConfig(node.id, "num_nodes"));
+            }
+            if node.num_faults != first.num_faults {
+                return Err(ParseError::MismatchedConfig(node.id, "num_faults"));
+            }
+            if node.crypto_alg != first.crypto_alg {
+                return Err(ParseError::MismatchedConfig(node.id, "crypto_alg"));
+            }
+            if node.pk_map != first.pk_map {
+                return Err(ParseError::MismatchedConfig(node.id, "pk_map"));
+            }
+            if node.revoked != first.revoked {
+                return Err(ParseError::MismatchedConfig(node.id, "revoked"));
+            }
+        }
+        let missing: Vec<Replica> = (0..first.num_nodes)
+            .filter(|r| !by_id.contains_key(r) && !first.is_revoked(*r))
+            .collect();
+        if !missing.is_empty() {
+            return Err(ParseError::MissingConfigs(missing));
+        }
+        if first.crypto_alg == Algorithm::NOPKI {
+            for (i, node) in by_id.iter() {
+                for (j, other) in by_id.range(i..) {
+                    if node.sk_map.get(j) != other.sk_map.get(i) {
+                        return Err(ParseError::AsymmetricKeys(*i, *j));
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
@@ -117,80 +350,138 @@ impl Node {
             pk_map: HashMap::default(),
             secret_key_bytes: Vec::new(),
             sk_map: HashMap::default(),
+            sealed: None,
+            revoked: Vec::new(),
             payload: 0,
             prot_payload: String::new(),
+            protocol: None,
+            session: 0,
+            multicast: None,
+            links: None,
+            bind_addr: None,
+            metrics_port: None,
+            stall_timeout_ms: None,
             my_cert: Vec::new(),
             root_cert:Vec::new(),
             my_cert_key: Vec::new(),
+            source: None,
+            passphrase: None,
+        }
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.is_some()
+    }
+
+    /// Encrypts `secret_key_bytes` and `sk_map` into `sealed` and clears them
+    pub fn seal(&mut self, passphrase: &[u8]) -> Result<(), SealError> {
+        if self.is_sealed() {
+            return Err(SealError::AlreadySealed);
+        }
+        let secrets = Secrets {
+            secret_key_bytes: std::mem::take(&mut self.secret_key_bytes),
+            sk_map: std::mem::take(&mut self.sk_map),
+        };
+        let mut plaintext = bincode::serialize(&secrets)
+            .map_err(|e| SealError::Encoding(e.to_string()))?;
+        let sealed = SealedSecrets::seal(&plaintext, passphrase, &self.seal_aad());
+        plaintext.zeroize();
+        self.sealed = Some(sealed?);
+        Ok(())
+    }
+
+    /// Decrypts `sealed` back into `secret_key_bytes` and `sk_map`
+    pub fn unseal(&mut self, passphrase: &[u8]) -> Result<(), SealError> {
+        let sealed = match &self.sealed {
+            Some(sealed) => sealed,
+            None => return Ok(()),
+        };
+        let plaintext = sealed.open(passphrase, &self.seal_aad())?;
+        let mut secrets: Secrets = bincode::deserialize(&plaintext)
+            .map_err(|e| SealError::Encoding(e.to_string()))?;
+        self.secret_key_bytes = std::mem::take(&mut secrets.secret_key_bytes);
+        self.sk_map = std::mem::take(&mut secrets.sk_map);
+        self.sealed = None;
+        Ok(())
+    }
+
+    /// Binds the ciphertext to the replica, so that it cannot be moved into
+    /// another config
+    fn seal_aad(&self) -> Vec<u8> {
+        format!("sensorbft-node-{}", self.id).into_bytes()
+    }
+
+    /// Reads a config in json, binary (`.dat`), toml or yaml, by file
+    /// extension, decrypting its secrets with the passphrase of
+    /// `Passphrase::from_env` if they are encrypted. The config still has
+    /// to be validated.
+    pub fn load(filename:&str) -> Result<Node, LoadError> {
+        Node::load_with(filename, &Passphrase::from_env())
+    }
+
+    /// `load`, with the passphrase from `passphrase`
+    pub fn load_with(filename:&str, passphrase: &Passphrase) -> Result<Node, LoadError> {
+        let node = Node::load_sealed(filename)?;
+        node.unsealed(filename, passphrase)
+    }
+
+    /// Reads a config without decrypting its secrets
+    pub fn load_sealed(filename:&str) -> Result<Node, LoadError> {
+        let mut node: Node = load::from_file(filename)?;
+        node.source = Some(filename.to_string());
+        Ok(node)
+    }
+
+    fn unsealed(mut self, filename:&str, passphrase: &Passphrase) -> Result<Node, LoadError> {
+        if self.is_sealed() {
+            let secret = passphrase.read()
+                .map_err(|e| LoadError::Sealed(filename.to_string(), e))?;
+            self.unseal(&secret)
+                .map_err(|e| LoadError::Sealed(filename.to_string(), e))?;
+            self.passphrase = Some(passphrase.clone());
         }
+        Ok(self)
     }
 
-    pub fn from_json(filename:String) -> Node {
-        let f = File::open(filename)
-            .unwrap();
-        let c: Node = from_reader(f)
-            .unwrap();
-        return c;
+    pub fn from_json(filename:String) -> Result<Node, LoadError> {
+        load::from_json::<Node>(&filename)?.unsealed(&filename, &Passphrase::from_env())
     }
 
-    pub fn from_toml(filename:String) -> Node {
-        let mut buf = String::new();
-        let mut f = File::open(filename)
-            .unwrap();
-        f.read_to_string(&mut buf)
-            .unwrap();
-        let c:Node = from_str(&buf)
-            .unwrap();
-        return c;
+    pub fn from_toml(filename:String) -> Result<Node, LoadError> {
+        load::from_toml::<Node>(&filename)?.unsealed(&filename, &Passphrase::from_env())
     }
 
-    pub fn from_yaml(filename:String) -> Node {
-        let f = File::open(filename)
-            .unwrap();
-        let c:Node = serde_yaml::from_reader(f)
-            .unwrap();
-        return c;
+    pub fn from_yaml(filename:String) -> Result<Node, LoadError> {
+        load::from_yaml::<Node>(&filename)?.unsealed(&filename, &Passphrase::from_env())
     }
 
-    pub fn from_bin(filename:String) -> Node {
-        let mut buf = Vec::new();
-        let mut f = File::open(filename)
-            .unwrap();
-        f.read_to_end(&mut buf)
-            .unwrap();
-        let bytes:&[u8] = &buf;
-        let c:Node = bincode::deserialize(bytes)
-            .unwrap();
-        return c;
+    pub fn from_bin(filename:String) -> Result<Node, LoadError> {
+        load::from_bin::<Node>(&filename)?.unsealed(&filename, &Passphrase::from_env())
     }
 
-    pub fn update_config(&mut self, ips: Vec<String>) {
-        let mut idx = 0;
+    /// Takes the addresses of the nodes and then the syncer from the lines
+    /// of an ip file. The syncer's is resolved now, a DNS name included.
+    pub fn update_config(&mut self, ips: Vec<String>) -> Result<(), ParseError> {
         let max_nodes = self.num_nodes;
-        for ip in ips {
-            // For self ip, put 0.0.0.0 with the same port
+        for (idx, ip) in ips.into_iter().enumerate() {
+            let addr = resolve(&ip)
+                .map_err(|e| ParseError::UnresolvedAddress(idx, ip.clone(), e.to_string()))?;
             if idx == max_nodes{
                 // Syncer address
-                let ip_a:Vec<&str> = ip.split(":").collect();
-                let port:u16 = ip_a.last().expect("invalid ip found").parse().expect("failed to parse port number");
-                let sock_addr = SocketAddrV4::new(ip_a.get(0).unwrap().parse().unwrap(), port);
-                self.client_addr = sock_addr.into();
+                self.client_addr = addr;
             }
             if idx == self.id {
-                let port:u16 = ip.split(":")
-                    .last()
-                    .expect("invalid ip found; unable to split at :")
-                    .parse()
-                    .expect("failed to parse the port after :");
-                self.net_map.insert(idx, format!("0.0.0.0:{}", port));
-                idx += 1;
+                // We listen on the wildcard of the family of our address,
+                // unless a bind address is set
+                let wildcard = unspecified(addr.ip()).to_string();
+                self.net_map.insert(idx, join_host_port(&wildcard, addr.port()));
                 continue;
             }
             // Put others ips in the config
             self.net_map.insert(idx, ip);
-            idx += 1;
         }
         log::info!("Talking to servers: {:?}", self.net_map);
+        Ok(())
     }
 
     pub fn my_ip(&self) -> String {
@@ -200,9 +491,113 @@ impl Node {
             .clone()
     }
 
+    /// The address to listen on at `port`: the bind address if one is set,
+    /// or else the wildcard of the family of our net_map address
+    pub fn listen_addr(&self, port: u16) -> SocketAddr {
+        let ip = match self.bind_addr {
+            Some(ip) => ip,
+            None => match self.net_map.get(&self.id).and_then(|a| resolve(a).ok()) {
+                Some(addr) => unspecified(addr.ip()),
+                None => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            },
+        };
+        SocketAddr::new(ip, port)
+    }
+
+    /// The address the syncer listens on: the bind address if one is set,
+    /// or else the wildcard of the family of `client_addr`
+    pub fn syncer_listen_addr(&self) -> SocketAddr {
+        let ip = self.bind_addr.unwrap_or_else(|| unspecified(self.client_addr.ip()));
+        SocketAddr::new(ip, self.client_addr.port())
+    }
+
     /// Returns the address at which a server should listen to incoming client
     /// connections
     pub fn client_ip(&self) -> String {
-        format!("0.0.0.0:{}", self.client_port)
+        self.listen_addr(self.client_port).to_string()
+    }
+}
+
+/// Smallest multiple of f that n must exceed for the protocol to be safe
+fn resilience(protocol: ProtocolId) -> usize {
+    match protocol {
+        // Bracha's RBC, which both approximate agreement protocols build on,
+        // and the CT RBC need n > 3f
+        ProtocolId::AppxCon | ProtocolId::HybAppxCon | ProtocolId::CTRBC => 3,
+    }
+}
+
+/// Splits `host:port`, folding the wildcard and loopback hosts into one
+fn split_addr(addr: &str) -> Option<(String, u16)> {
+    let (host, port) = split_host_port(addr)?;
+    match host.parse::<IpAddr>() {
+        Ok(ip) if ip.is_unspecified() || ip.is_loopback() => Some(("localhost".to_string(), port)),
+        _ => Some((host.to_string(), port)),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nopki_set(n: usize, f: usize) -> Vec<Node> {
+        (0..n).map(|i| {
+            let mut node = Node::new();
+            node.id = i;
+            node.num_nodes = n;
+            node.num_faults = f;
+            node.crypto_alg = Algorithm::NOPKI;
+            for j in 0..=n {
+                node.net_map.insert(j, format!("127.0.0.1:{}", 8500+j));
+            }
+            for j in 0..n {
+                let (lo, hi) = (i.min(j) as u8, i.max(j) as u8);
+                node.sk_map.insert(j, vec![lo*16+hi; crypto::SECRET_KEY_SIZE]);
+            }
+            node
+        }).collect()
+    }
+
+    #[test]
+    fn rejects_unsafe_configs() {
+        let mut nodes = nopki_set(4, 1);
+        nodes[0].validate_for(ProtocolId::AppxCon).unwrap();
+        Node::validate_set(&nodes).unwrap();
+
+        // 2f < n passes the old check but breaks Bracha's RBC
+        let unsafe_node = nopki_set(5, 2).remove(0);
+        unsafe_node.validate().unwrap();
+        assert!(matches!(unsafe_node.validate_for(ProtocolId::HybAppxCon),
+            Err(ParseError::InsufficientResilience(ProtocolId::HybAppxCon, 2, 5))));
+
+        // The wildcard address of a node collides with a loopback peer
+        let mut node = nodes[1].clone();
+        node.net_map.insert(1, "0.0.0.0:8502".to_string());
+        assert!(matches!(node.validate(), Err(ParseError::DuplicateAddress(1, 2, _))));
+
+        nodes[1].sk_map.remove(&3);
+        assert!(matches!(nodes[1].validate(), Err(ParseError::IncompleteKeyMap("sk_map", _))));
+        nodes[1].sk_map.insert(3, vec![0; crypto::SECRET_KEY_SIZE]);
+        assert!(matches!(Node::validate_set(&nodes), Err(ParseError::AsymmetricKeys(1, 3))));
+    }
+
+    #[test]
+    fn seals_the_secrets() {
+        let mut node = nopki_set(4, 1).remove(2);
+        let sk_map = node.sk_map.clone();
+        node.seal(b"correct horse").unwrap();
+        assert!(node.sk_map.is_empty() && node.secret_key_bytes.is_empty());
+        assert!(matches!(node.validate(), Err(ParseError::Sealed(2))));
+        assert!(matches!(node.seal(b"correct horse"), Err(SealError::AlreadySealed)));
+
+        assert!(matches!(node.clone().unseal(b"wrong horse"), Err(SealError::Decrypt)));
+        // The ciphertext only opens in the config it was made for
+        let mut other = node.clone();
+        other.id = 1;
+        assert!(matches!(other.unseal(b"correct horse"), Err(SealError::Decrypt)));
+
+        node.unseal(b"correct horse").unwrap();
+        assert_eq!(node.sk_map, sk_map);
+        node.validate().unwrap();
+    }
+}
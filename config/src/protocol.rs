@@ -0,0 +1,147 @@
+use serde::{
+    Serialize,
+    Deserialize
+};
+use types::ProtocolId;
+use super::ParseError;
+
+/// The protocol a node runs and its inputs, e.g. in json
+/// `"protocol": {"hyb": {"value": 10, "delta": 50, "epsilon": 1, "tri": 1000}}`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolConfig {
+    /// Asynchronous approximate agreement
+    Appx {
+        /// The node's input
+        value: u64,
+        /// Agreement the run stops at
+        epsilon: u64,
+    },
+    /// Approximate agreement down to delta, then binary agreement down to
+    /// epsilon
+    Hyb {
+        value: u64,
+        /// Level of allowed overshoot before switching to binary agreement
+        delta: u64,
+        epsilon: u64,
+        /// Bound on how far apart the honest inputs are
+        tri: u64,
+    },
+}
+
+impl ProtocolConfig {
+    pub fn id(&self) -> ProtocolId {
+        match self {
+            ProtocolConfig::Appx { .. } => ProtocolId::AppxCon,
+            ProtocolConfig::Hyb { .. } => ProtocolId::HybAppxCon,
+        }
+    }
+
+    pub fn value(&self) -> u64 {
+        match self {
+            ProtocolConfig::Appx { value, .. } | ProtocolConfig::Hyb { value, .. } => *value,
+        }
+    }
+
+    /// The same parameters with `value` as the input
+    pub fn with_value(mut self, input: u64) -> ProtocolConfig {
+        match &mut self {
+            ProtocolConfig::Appx { value, .. } | ProtocolConfig::Hyb { value, .. } => *value = input,
+        }
+        self
+    }
+
+    pub fn epsilon(&self) -> u64 {
+        match self {
+            ProtocolConfig::Appx { epsilon, .. } | ProtocolConfig::Hyb { epsilon, .. } => *epsilon,
+        }
+    }
+
+    pub fn delta(&self) -> Option<u64> {
+        match self {
+            ProtocolConfig::Appx { .. } => None,
+            ProtocolConfig::Hyb { delta, .. } => Some(*delta),
+        }
+    }
+
+    pub fn tri(&self) -> Option<u64> {
+        match self {
+            ProtocolConfig::Appx { .. } => None,
+            ProtocolConfig::Hyb { tri, .. } => Some(*tri),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ParseError> {
+        if self.epsilon() == 0 {
+            return Err(ParseError::InvalidProtocolParam("epsilon", "must be positive"));
+        }
+        if let ProtocolConfig::Hyb { delta, epsilon, .. } = *self {
+            if delta < epsilon {
+                return Err(ParseError::InvalidProtocolParam("delta", "must be at least epsilon"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Protocol inputs given on the command line. Each one that is set replaces
+/// the value in the config's protocol section.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProtocolOverrides {
+    pub value: Option<u64>,
+    pub epsilon: Option<u64>,
+    pub delta: Option<u64>,
+    pub tri: Option<u64>,
+}
+
+impl ProtocolOverrides {
+    /// The inputs to run `protocol` with: `section` with the overrides
+    /// applied, or the overrides alone when the config has no section
+    pub fn apply(&self, protocol: ProtocolId, section: Option<&ProtocolConfig>) -> Result<ProtocolConfig, ParseError> {
+        if let Some(section) = section {
+            if section.id() != protocol {
+                return Err(ParseError::WrongProtocol(protocol, section.id()));
+            }
+        }
+        let pick = |name: &'static str, flag: Option<u64>, base: Option<u64>| {
+            flag.or(base).ok_or(ParseError::MissingProtocolParam(protocol, name))
+        };
+        let merged = match protocol {
+            ProtocolId::AppxCon => ProtocolConfig::Appx {
+                value: pick("value", self.value, section.map(|s| s.value()))?,
+                epsilon: pick("epsilon", self.epsilon, section.map(|s| s.epsilon()))?,
+            },
+            ProtocolId::HybAppxCon => ProtocolConfig::Hyb {
+                value: pick("value", self.value, section.map(|s| s.value()))?,
+                delta: pick("delta", self.delta, section.and_then(|s| s.delta()))?,
+                epsilon: pick("epsilon", self.epsilon, section.map(|s| s.epsilon()))?,
+                tri: pick("tri", self.tri, section.and_then(|s| s.tri()))?,
+            },
+            ProtocolId::CTRBC => return Err(ParseError::Unimplemented("CTRBC protocol section")),
+        };
+        merged.validate()?;
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_the_section() {
+        let section: ProtocolConfig = serde_json::from_str(
+            r#"{"hyb": {"value": 10, "delta": 50, "epsilon": 1, "tri": 1000}}"#).unwrap();
+        let overrides = ProtocolOverrides { value: Some(7), ..ProtocolOverrides::default() };
+        assert_eq!(overrides.apply(ProtocolId::HybAppxCon, Some(&section)).unwrap(),
+            ProtocolConfig::Hyb { value: 7, delta: 50, epsilon: 1, tri: 1000 });
+        assert!(matches!(overrides.apply(ProtocolId::AppxCon, Some(&section)),
+            Err(ParseError::WrongProtocol(ProtocolId::AppxCon, ProtocolId::HybAppxCon))));
+        // Without a section every parameter must come from the flags
+        assert!(matches!(overrides.apply(ProtocolId::AppxCon, None),
+            Err(ParseError::MissingProtocolParam(ProtocolId::AppxCon, "epsilon"))));
+        let overrides = ProtocolOverrides { epsilon: Some(0), ..overrides };
+        assert!(matches!(overrides.apply(ProtocolId::AppxCon, None),
+            Err(ParseError::InvalidProtocolParam("epsilon", _))));
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::num::NonZeroU32;
+use std::process::{Command, Stdio};
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::SealError;
+
+/// PBKDF2 rounds for newly sealed configs; the count is stored with them
+pub const SEAL_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// Secret key material encrypted under a key derived from a passphrase
+/// with PBKDF2-HMAC-SHA256, using AES-256-GCM
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SealedSecrets {
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub nonce: Vec<u8>,
+    /// The plaintext followed by the tag
+    pub ciphertext: Vec<u8>,
+}
+
+impl SealedSecrets {
+    /// Encrypts `plaintext`, binding it to `aad`
+    pub fn seal(plaintext: &[u8], passphrase: &[u8], aad: &[u8]) -> Result<SealedSecrets, SealError> {
+        let rng = SystemRandom::new();
+        let mut salt = vec![0; SALT_LEN];
+        let mut nonce = vec![0; NONCE_LEN];
+        rng.fill(&mut salt).map_err(|_| SealError::Random)?;
+        rng.fill(&mut nonce).map_err(|_| SealError::Random)?;
+        let key = derive_key(passphrase, &salt, SEAL_ITERATIONS)?;
+        let mut ciphertext = plaintext.to_vec();
+        key.seal_in_place_append_tag(to_nonce(&nonce)?, Aad::from(aad), &mut ciphertext)
+            .map_err(|_| SealError::Encrypt)?;
+        Ok(SealedSecrets {
+            iterations: SEAL_ITERATIONS,
+            salt,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    /// Decrypts the secrets; the buffer is zeroized when dropped
+    pub fn open(&self, passphrase: &[u8], aad: &[u8]) -> Result<Zeroizing<Vec<u8>>, SealError> {
+        let key = derive_key(passphrase, &self.salt, self.iterations)?;
+        let mut buf = Zeroizing::new(self.ciphertext.clone());
+        let len = key.open_in_place(to_nonce(&self.nonce)?, Aad::from(aad), &mut buf)
+            .map_err(|_| SealError::Decrypt)?
+            .len();
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8], iterations: u32) -> Result<LessSafeKey, SealError> {
+    let iterations = NonZeroU32::new(iterations)
+        .ok_or(SealError::Malformed("iterations"))?;
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase, &mut key[..]);
+    let key = UnboundKey::new(&AES_256_GCM, &key[..])
+        .map_err(|_| SealError::Malformed("key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn to_nonce(nonce: &[u8]) -> Result<Nonce, SealError> {
+    Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| SealError::Malformed("nonce"))
+}
+
+/// Where the passphrase of sealed configs comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Passphrase {
+    /// The value of an environment variable
+    Env(String),
+    /// The first line of a file
+    File(String),
+    /// Asked for on the terminal
+    Prompt,
+}
+
+impl Passphrase {
+    /// The variable `from_env` reads the passphrase from
+    pub const ENV_VAR: &'static str = "SENSORBFT_PASSPHRASE";
+
+    /// `SENSORBFT_PASSPHRASE` if it is set, or else a prompt
+    pub fn from_env() -> Passphrase {
+        match std::env::var_os(Passphrase::ENV_VAR) {
+            Some(_) => Passphrase::Env(Passphrase::ENV_VAR.to_string()),
+            None => Passphrase::Prompt,
+        }
+    }
+
+    /// A `--passphrase_file` if one is given, or else `from_env`
+    pub fn from_file_or_env(file: Option<&str>) -> Passphrase {
+        match file {
+            Some(path) => Passphrase::File(path.to_string()),
+            None => Passphrase::from_env(),
+        }
+    }
+
+    /// Reads the passphrase; the buffer is zeroized when dropped
+    pub fn read(&self) -> Result<Zeroizing<Vec<u8>>, SealError> {
+        let mut line = match self {
+            Passphrase::Env(var) => std::env::var(var)
+                .map(String::into_bytes)
+                .map_err(|e| SealError::Passphrase(format!("${}", var), e.to_string()))?,
+            Passphrase::File(path) => std::fs::read(path)
+                .map_err(|e| SealError::Passphrase(path.clone(), e.to_string()))?,
+            Passphrase::Prompt => prompt()
+                .map_err(|e| SealError::Passphrase("the terminal".to_string(), e.to_string()))?,
+        };
+        let end = line.iter().position(|b| *b == b'\n').unwrap_or(line.len());
+        let mut passphrase = Zeroizing::new(line[..end].to_vec());
+        line.zeroize();
+        if passphrase.last() == Some(&b'\r') {
+            passphrase.pop();
+        }
+        if passphrase.is_empty() {
+            return Err(SealError::EmptyPassphrase);
+        }
+        Ok(passphrase)
+    }
+}
+
+/// Reads a line from the terminal with echo turned off, or from stdin when
+/// there is no terminal
+fn prompt() -> io::Result<Vec<u8>> {
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty");
+    let mut line = Vec::new();
+    match tty {
+        Ok(mut tty) => {
+            write!(tty, "Passphrase: ")?;
+            tty.flush()?;
+            let echo_off = stty(&tty, "-echo");
+            let read = BufReader::new(&tty).read_until(b'\n', &mut line);
+            if echo_off {
+                stty(&tty, "echo");
+                writeln!(tty)?;
+            }
+            read?;
+        }
+        Err(_) => {
+            io::stdin().lock().read_until(b'\n', &mut line)?;
+        }
+    }
+    Ok(line)
+}
+
+fn stty(tty: &File, mode: &str) -> bool {
+    let tty = match tty.try_clone() {
+        Ok(tty) => tty,
+        Err(_) => return false,
+    };
+    Command::new("stty")
+        .arg(mode)
+        .stdin(tty)
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
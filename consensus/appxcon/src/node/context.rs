@@ -2,30 +2,64 @@ use anyhow::{Result, anyhow};
 use network::{plaintcp::{TcpReceiver, TcpReliableSender, CancelHandler}, Acknowledgement};
 use tokio::sync::{oneshot, mpsc::{unbounded_channel, UnboundedReceiver}};
 use tokio_util::time::DelayQueue;
-use types::{appxcon::{WrapperMsg, Replica, ProtMsg}, Round, SyncMsg, SyncState};
-use config::Node;
+use types::{appxcon::{WrapperMsg, Replica, ProtMsg}, Round, SyncMsg, SyncState, EnvelopeHeader, Frame, ProtocolId, RunSpec, Membership};
+use config::{Node, ProtocolConfig, Passphrase, KeyMap};
+use consensus::{PeerBlame, TrafficStats, Direction, AgreementStats, AgreementSummary, CompletionReport, now_millis, ProtocolSpans, ProgressWatchdog, Progress, StallReport, WitnessProgress, STALL_TIMEOUT_MS, Metrics, SharedMetrics, spawn_metrics_server, Offence, LivenessTracker, SharedLiveness, HEARTBEAT_INTERVAL_MS, SUSPECT_AFTER_MS, Reconfiguration, LinkEmulator, Route};
+use multicast::{MulticastSender, MulticastTransport};
 use fnv::FnvHashMap;
-use std::{net::{SocketAddr, SocketAddrV4}, collections::HashMap, time::{SystemTime, UNIX_EPOCH}};
+use tracing::Instrument;
+use tokio_stream::StreamExt;
+use std::{net::SocketAddr, collections::HashMap, time::{SystemTime, UNIX_EPOCH, Duration}};
 
 use super::{RoundState, Handler, SyncHandler};
 
 pub struct Context {
     /// Networking context
-    pub net_send: TcpReliableSender<Replica,WrapperMsg,Acknowledgement>,
+    pub net_send: TcpReliableSender<Replica,Frame,Acknowledgement>,
     pub net_recv: UnboundedReceiver<WrapperMsg>,
     pub sync_send:TcpReliableSender<Replica,SyncMsg,Acknowledgement>,
     pub sync_recv: UnboundedReceiver<SyncMsg>,
+    /// Broadcasts go out over UDP multicast instead of `net_send` when set
+    pub mcast: Option<MulticastSender>,
     /// Coin invoke
     pub invoke_coin:DelayQueue<Replica>,
+    /// Link emulation of the messages we send, and the messages it holds
+    /// back until their delay is up
+    pub links: Option<LinkEmulator>,
+    pub delayed: DelayQueue<(Replica, WrapperMsg)>,
     /// Data context
     pub num_nodes: usize,
     pub myid: usize,
     pub num_faults: usize,
     pub payload:usize,
+    /// Header sent with every protocol message; received headers must match it
+    pub header: EnvelopeHeader,
+    /// Membership epoch; the syncer moves the nodes to a new one between runs
+    pub epoch: u64,
+    /// The config file, reread when the membership changes
+    pub config_source: Option<String>,
+    /// Where the passphrase of its encrypted secrets comes from
+    pub config_passphrase: Passphrase,
 
     /// PKI
     /// Replica map
-    pub sec_key_map:HashMap<Replica, Vec<u8>>,
+    pub sec_key_map:KeyMap,
+    /// Bad frames seen per peer, and the peers we stopped listening to
+    pub blame: PeerBlame,
+    /// Messages and bytes sent to and received from each peer
+    pub traffic: TrafficStats,
+    /// Round and phase timings of the current run
+    pub stats: AgreementStats,
+    /// Notices when the current round stops making progress
+    pub watchdog: ProgressWatchdog,
+    /// Tracing spans of the node, its rounds, RBCs and BAA instances
+    pub spans: ProtocolSpans,
+    /// When each peer was last heard from; shared so it can be queried during a run
+    pub liveness: SharedLiveness,
+    /// Protocol state served on the metrics endpoint, if one is configured
+    pub metrics: SharedMetrics,
+    /// Only the latest heartbeat to each peer is worth retrying
+    pub heartbeat_handlers: HashMap<Replica,CancelHandler<Acknowledgement>>,
 
     /// Round number and Approx Consensus related context
     pub round:u64,
@@ -49,22 +83,24 @@ impl Context {
     pub fn spawn(
         config: Node,
         sleep:u128,
-        val:u64,
-        epsilon:u64
     ) -> anyhow::Result<oneshot::Sender<()>> {
-        let prot_payload = &config.prot_payload;
-        let v:Vec<&str> = prot_payload.split(',').collect();
+        let (val, epsilon) = match config.protocol {
+            Some(ProtocolConfig::Appx { value, epsilon }) => (value, epsilon),
+            other => return Err(anyhow!("appxcon needs an appx protocol section, found {:?}", other)),
+        };
         let mut consensus_addrs :FnvHashMap<Replica,SocketAddr>= FnvHashMap::default();
         for (replica,address) in config.net_map.iter(){
-            let address:SocketAddr = address.parse().expect("Unable to parse address");
-            consensus_addrs.insert(*replica, SocketAddr::from(address.clone()));
+            // IPv4, bracketed IPv6 or a DNS name
+            let address = config::resolve(address)
+                .map_err(|e| anyhow!("Unable to resolve the address {} of replica {}: {}", address, replica, e))?;
+            consensus_addrs.insert(*replica, address);
         }
         let mut syncer_map:FnvHashMap<Replica,SocketAddr> = FnvHashMap::default();
         syncer_map.insert(0, config.client_addr);
         let my_port = consensus_addrs.get(&config.id).unwrap();
-        let my_address = to_socket_address("0.0.0.0", my_port.port());
+        let my_address = config.listen_addr(my_port.port());
         let syncer_listen_port = config.client_port;
-        let syncer_l_address = to_socket_address("0.0.0.0", syncer_listen_port);
+        let syncer_l_address = config.listen_addr(syncer_listen_port);
         // No clients needed
 
         // let prot_net_rt = tokio::runtime::Builder::new_multi_thread()
@@ -72,105 +108,160 @@ impl Context {
         // .build()
         // .unwrap();
 
+        let metrics = Metrics::new(config.id, "appxcon").shared();
+        if let Some(metrics_port) = config.metrics_port {
+            spawn_metrics_server(metrics_port, metrics.clone())
+                .map_err(|e| anyhow!("Unable to serve metrics on port {}: {}", metrics_port, e))?;
+        }
         // Setup networking
         let (tx_net_to_consensus, rx_net_to_consensus) = unbounded_channel();
-        TcpReceiver::<Acknowledgement, WrapperMsg, _>::spawn(
+        TcpReceiver::<Acknowledgement, Frame, _>::spawn(
             my_address,
-            Handler::new(tx_net_to_consensus),
+            Handler::new(tx_net_to_consensus.clone(), metrics.clone()),
         );
+        let mcast = match config.multicast.clone() {
+            Some(mcast_config) => Some(MulticastTransport::spawn(
+                mcast_config,
+                config.id,
+                config.key_map(),
+                tx_net_to_consensus,
+            )?),
+            None => None,
+        };
         // The server must listen to the client's messages on some port that is not being used to listen to other servers
         let (tx_net_to_client,rx_net_from_client) = unbounded_channel();
         TcpReceiver::<Acknowledgement,SyncMsg,_>::spawn(
             syncer_l_address, 
             SyncHandler::new(tx_net_to_client)
         );
-        let sleep_time = sleep - SystemTime::now().duration_since(UNIX_EPOCH)
+        // A start time in the past means start right away
+        let sleep_time = sleep.saturating_sub(SystemTime::now().duration_since(UNIX_EPOCH)
         .unwrap()
-        .as_millis();
-        log::debug!("Consensus addrs {:?}",consensus_addrs);
-        let consensus_net = TcpReliableSender::<Replica,WrapperMsg,Acknowledgement>::with_peers(
+        .as_millis());
+        tracing::debug!("Consensus addrs {:?}",consensus_addrs);
+        let consensus_net = TcpReliableSender::<Replica,Frame,Acknowledgement>::with_peers(
             consensus_addrs.clone()
         );
         let sync_net = TcpReliableSender::<Replica,SyncMsg,Acknowledgement>::with_peers(syncer_map);
-        if v[0] == "a" {
-            let (exit_tx, exit_rx) = oneshot::channel();
-            tokio::spawn( async move {
-                let prot_payload = &config.prot_payload;
-                let v:Vec<&str> = prot_payload.split(',').collect();
-                let init_value:u64 = v[1].parse::<u64>().unwrap();
-                //let epsilon:u64 = v[2].parse::<u64>().unwrap();
-                let mut c = Context {
-                    net_send: consensus_net,
-                    net_recv: rx_net_to_consensus,
-                    sync_send: sync_net,
-                    sync_recv: rx_net_from_client,
-                    num_nodes: config.num_nodes,
-                    sec_key_map: HashMap::default(),
-                    myid: config.id,
-                    num_faults: config.num_faults,
-                    payload: config.payload,
-                    round:0,
-                    value: val,
-                    epsilon: epsilon,
-        
-                    round_state: HashMap::default(),
-                    invoke_coin:tokio_util::time::DelayQueue::new(),
-                    //echos_ss: HashMap::default(),
-                    exit_rx:exit_rx,
-                    cancel_handlers:HashMap::default()
-                };
-                for (id, sk_data) in config.sk_map.clone() {
-                    c.sec_key_map.insert(id, sk_data.clone());
-                }
-                //c.invoke_coin.insert(100, Duration::from_millis(sleep_time.try_into().unwrap()));
-                if let Err(e) = c.run().await {
-                    log::error!("Consensus error: {}", e);
-                }
-                log::debug!("Started n-parallel RBC with value {} and epsilon {}",c.value,c.epsilon);
-                // Initialize storage
-            });
-            Ok(exit_tx)
-        }
-        else {
-            panic!("Invalid configuration for protocol");
-        }
+        let (exit_tx, exit_rx) = oneshot::channel();
+        tokio::spawn( async move {
+            //let epsilon:u64 = v[2].parse::<u64>().unwrap();
+            let mut c = Context {
+                net_send: consensus_net,
+                net_recv: rx_net_to_consensus,
+                sync_send: sync_net,
+                sync_recv: rx_net_from_client,
+                mcast: mcast,
+                num_nodes: config.num_nodes,
+                sec_key_map: config.key_map(),
+                blame: PeerBlame::default(),
+                traffic: TrafficStats::new(),
+                stats: AgreementStats::default(),
+                watchdog: ProgressWatchdog::new(config.stall_timeout_ms.unwrap_or(STALL_TIMEOUT_MS)),
+                spans: ProtocolSpans::new("appxcon", config.id, config.session),
+                liveness: LivenessTracker::new((0..config.num_nodes).filter(|rep| *rep != config.id && !config.is_revoked(*rep)), SUSPECT_AFTER_MS).shared(),
+                metrics: metrics,
+                heartbeat_handlers: HashMap::default(),
+                myid: config.id,
+                num_faults: config.num_faults,
+                payload: config.payload,
+                epoch: 0,
+                config_source: config.source.clone(),
+                config_passphrase: config.passphrase.clone().unwrap_or_else(Passphrase::from_env),
+                header: EnvelopeHeader::new(ProtocolId::AppxCon, config.session),
+                round:0,
+                value: val,
+                epsilon: epsilon,
+    
+                round_state: HashMap::default(),
+                invoke_coin:tokio_util::time::DelayQueue::new(),
+                links: config.links.clone().map(|links| LinkEmulator::new(links, config.id)),
+                delayed: DelayQueue::new(),
+                //echos_ss: HashMap::default(),
+                exit_rx:exit_rx,
+                cancel_handlers:HashMap::default()
+            };
+            //c.invoke_coin.insert(100, Duration::from_millis(sleep_time.try_into().unwrap()));
+            if let Err(e) = c.run().await {
+                tracing::error!("Consensus error: {}", e);
+            }
+            tracing::debug!("Started n-parallel RBC with value {} and epsilon {}",c.value,c.epsilon);
+            // Initialize storage
+        });
+        Ok(exit_tx)
     }
 
     pub async fn broadcast(&mut self, protmsg:ProtMsg){
-        let sec_key_map = self.sec_key_map.clone();
-        for (replica,sec_key) in sec_key_map.into_iter() {
-            if replica != self.myid{
-                let wrapper_msg = WrapperMsg::new(protmsg.clone(), self.myid, &sec_key.as_slice());
-                let cancel_handler:CancelHandler<Acknowledgement> = self.net_send.send(replica, wrapper_msg).await;
+        if let Some(mcast) = &self.mcast{
+            mcast.broadcast(self.header, protmsg.clone());
+            // Count one logical message per peer with the same envelope as
+            // on the TCP path, so the totals stay comparable
+            let bytes = WrapperMsg::wire_size(&self.header, &protmsg);
+            for replica in 0..self.num_nodes{
+                if replica != self.myid{
+                    self.traffic.record(replica, Direction::Sent, protmsg.phase(), protmsg.kind(), protmsg.round(), bytes);
+                }
+            }
+            return;
+        }
+        for (replica,wrapper_msg) in self.sign_for_peers(&protmsg) {
+            let bytes = bincode::serialized_size(&wrapper_msg).unwrap_or(0);
+            self.traffic.record(replica, Direction::Sent, protmsg.phase(), protmsg.kind(), protmsg.round(), bytes);
+            if let Some(cancel_handler) = self.send_emulated(replica, wrapper_msg).await{
                 self.add_cancel_handler(cancel_handler);
-                // let sent_msg = Arc::new(wrapper_msg);
-                // self.c_send(replica, sent_msg).await;
             }
         }
     }
 
+    /// MACs the message for every other member. Signed up front so that
+    /// the keys are borrowed, not copied, while sending.
+    fn sign_for_peers(&self, protmsg:&ProtMsg) -> Vec<(Replica, WrapperMsg)>{
+        self.sec_key_map.iter()
+            .filter(|(replica,_)| **replica != self.myid)
+            .map(|(replica,sec_key)| (*replica, WrapperMsg::new(self.header, protmsg.clone(), self.myid, sec_key)))
+            .collect()
+    }
+
     pub async fn run(&mut self)-> Result<()>{
         // Send the client message that we are alive and kicking
         let cancel_handler = self.sync_send.send(
     0,
-       SyncMsg { sender: self.myid, state: SyncState::ALIVE, value:0}).await;
+       SyncMsg { sender: self.myid, state: SyncState::ALIVE, value:0, payload:Vec::new()}).await;
         self.add_cancel_handler(cancel_handler);
+        let mut heartbeat = tokio::time::interval(Duration::from_millis(HEARTBEAT_INTERVAL_MS));
         loop {
             tokio::select! {
                 // Receive exit handlers
                 exit_val = &mut self.exit_rx => {
                     exit_val.map_err(anyhow::Error::new)?;
-                    log::info!("Termination signal received by the server. Exiting.");
+                    tracing::info!("Termination signal received by the server. Exiting.");
                     break
                 },
                 msg = self.net_recv.recv() => {
                     // Received a protocol message
                     // Received a protocol message
-                    log::debug!("Got a consensus message from the network: {:?}", msg);
+                    tracing::debug!("Got a consensus message from the network: {:?}", msg);
                     let msg = msg.ok_or_else(||
                         anyhow!("Networking layer has closed")
                     )?;
-                    self.process_msg(msg).await;
+                    let span = self.spans.node();
+                    self.process_msg(msg).instrument(span).await;
+                    self.spans.advance(self.round);
+                },
+                Some(expired) = self.delayed.next() => {
+                    match expired {
+                        Ok(expired) => {
+                            let (replica, wrapper_msg) = expired.into_inner();
+                            let cancel_handler:CancelHandler<Acknowledgement> = self.net_send.send(replica, Frame::encode(&wrapper_msg)).await;
+                            self.add_cancel_handler(cancel_handler);
+                        },
+                        Err(e) => tracing::error!("Link emulation timer failed: {}",e),
+                    }
+                },
+                _ = heartbeat.tick() => {
+                    self.send_heartbeats().await;
+                    self.publish_metrics();
+                    self.check_progress().await;
                 },
                 sync_msg = self.sync_recv.recv() =>{
                     let sync_msg = sync_msg.ok_or_else(||
@@ -178,20 +269,48 @@ impl Context {
                     )?;
                     match sync_msg.state {
                         SyncState::START =>{
-                            log::error!("Consensus Start time: {:?}", SystemTime::now()
+                            tracing::error!("Consensus Start time: {:?}", SystemTime::now()
                                 .duration_since(UNIX_EPOCH)
                                 .unwrap()
                                 .as_millis());
-                            self.start_rbc().await;
-                            let cancel_handler = self.sync_send.send(0, SyncMsg { sender: self.myid, state: SyncState::STARTED,value:0}).await;
+                            self.stats = AgreementStats::new(now_millis());
+                            self.watchdog.arm(now_millis());
+                            if let Some(links) = &mut self.links{
+                                links.start_run(now_millis());
+                            }
+                            let span = self.spans.round(self.round);
+                            self.start_rbc().instrument(span).await;
+                            let cancel_handler = self.sync_send.send(0, SyncMsg { sender: self.myid, state: SyncState::STARTED,value:0, payload:Vec::new()}).await;
                             self.add_cancel_handler(cancel_handler);
                         },
+                        SyncState::RESET =>{
+                            match RunSpec::from_bytes(&sync_msg.payload){
+                                Ok(spec) => {
+                                    if let Some(membership) = spec.membership.clone(){
+                                        if !membership.is_member(self.myid){
+                                            tracing::info!("Not a member of epoch {}. Exiting.",membership.epoch);
+                                            break
+                                        }
+                                        if let Err(e) = self.reconfigure(&membership){
+                                            tracing::error!("Unable to move to epoch {}: {}",membership.epoch,e);
+                                            let cancel_handler = self.sync_send.send(0, SyncMsg { sender: self.myid, state: SyncState::ReconfigFailed, value:membership.epoch, payload:e.to_string().into_bytes()}).await;
+                                            self.add_cancel_handler(cancel_handler);
+                                            continue;
+                                        }
+                                    }
+                                    self.reset(spec);
+                                    let cancel_handler = self.sync_send.send(0, SyncMsg { sender: self.myid, state: SyncState::ALIVE, value:0, payload:Vec::new()}).await;
+                                    self.add_cancel_handler(cancel_handler);
+                                },
+                                Err(e) => tracing::error!("Malformed RESET from the syncer: {}",e),
+                            }
+                        },
                         SyncState::STOP =>{
-                            log::error!("Consensus Stop time: {:?}", SystemTime::now()
+                            tracing::error!("Consensus Stop time: {:?}", SystemTime::now()
                                 .duration_since(UNIX_EPOCH)
                                 .unwrap()
                                 .as_millis());
-                            log::info!("Termination signal received by the server. Exiting.");
+                            tracing::info!("Termination signal received by the server. Exiting.");
                             break
                         },
                         _=>{}
@@ -201,6 +320,217 @@ impl Context {
         }
         Ok(())
     }
+    /// Moves to the membership of a new epoch: rereads the config, checks
+    /// it agrees with what the syncer announced, and connects to the new
+    /// members only
+    pub fn reconfigure(&mut self, membership:&Membership) -> Result<()>{
+        let path = self.config_source.clone()
+            .ok_or_else(|| anyhow!("the config was not loaded from a file and cannot be reread"))?;
+        let reconf = Reconfiguration::load(&path, &self.config_passphrase, self.myid, self.header.protocol, membership)
+            .map_err(|e| anyhow!("{}: {}", path, e))?;
+        tracing::info!("Moving to epoch {} with {} nodes tolerating {} faults, revoked {:?}",reconf.epoch,reconf.num_nodes,reconf.num_faults,membership.revoked);
+        if self.mcast.take().is_some(){
+            tracing::warn!("Multicast keys are fixed at startup; broadcasting over TCP from epoch {}",reconf.epoch);
+        }
+        // Dropping the old sender closes the connections to the replicas that left
+        self.net_send = TcpReliableSender::<Replica,Frame,Acknowledgement>::with_peers(reconf.peers.clone());
+        self.heartbeat_handlers.clear();
+        let timeout = self.liveness.read().unwrap().timeout;
+        *self.liveness.write().unwrap() = LivenessTracker::new(reconf.others(self.myid), timeout);
+        self.num_nodes = reconf.num_nodes;
+        self.num_faults = reconf.num_faults;
+        self.sec_key_map = reconf.sec_key_map;
+        self.epoch = reconf.epoch;
+        Ok(())
+    }
+
+    /// Drops all protocol state and re-arms the node for the next run of an experiment
+    pub fn reset(&mut self, spec:RunSpec){
+        tracing::info!("Resetting for run {} with input {} in session {}",spec.run,spec.input,spec.session);
+        self.round = 0;
+        self.value = spec.input;
+        self.header.session = spec.session;
+        self.spans.reset("appxcon", self.myid, spec.session);
+        self.round_state.clear();
+        self.cancel_handlers.clear();
+        self.traffic = TrafficStats::new();
+        self.stats = AgreementStats::default();
+        self.watchdog.disarm();
+        if let Some(links) = &mut self.links{
+            links.reset();
+        }
+        self.delayed.clear();
+    }
+
+    /// Sends a heartbeat to every peer and marks down the ones that went silent
+    pub async fn send_heartbeats(&mut self){
+        let protmsg = ProtMsg::Heartbeat(self.myid, self.round);
+        for (replica,wrapper_msg) in self.sign_for_peers(&protmsg) {
+            let bytes = bincode::serialized_size(&wrapper_msg).unwrap_or(0);
+            self.traffic.record(replica, Direction::Sent, protmsg.phase(), protmsg.kind(), protmsg.round(), bytes);
+            // Dropping the previous handler stops the sender retrying a stale heartbeat
+            if let Some(cancel_handler) = self.send_emulated(replica, wrapper_msg).await{
+                self.heartbeat_handlers.insert(replica, cancel_handler);
+            }
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let mut liveness = self.liveness.write().unwrap();
+        let transitions = liveness.check(now);
+        for transition in transitions.iter(){
+            tracing::warn!("Liveness: {}",transition);
+        }
+        if !transitions.is_empty(){
+            tracing::warn!("Liveness: peers down {:?}",liveness.down_peers());
+        }
+    }
+
+    /// Notes an authenticated message from `peer`
+    pub fn mark_alive(&mut self, peer:Replica){
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        if let Some(transition) = self.liveness.write().unwrap().seen(peer, now){
+            tracing::info!("Liveness: {}",transition);
+        }
+    }
+
+    /// Writes this node's traffic counters to a file and sends them to the syncer
+    pub async fn report_traffic(&mut self){
+        let path = format!("traffic-{}.json", self.myid);
+        if let Err(e) = self.traffic.dump(self.myid, self.header.session, &path){
+            tracing::error!("Failed to write traffic counters to {}: {}", path, e);
+        }
+        let report = self.traffic.report(self.myid, self.header.session);
+        let cancel_handler = self.sync_send.send(0, SyncMsg { sender: self.myid, state: SyncState::TrafficReport, value:0, payload:report.to_bytes()}).await;
+        self.add_cancel_handler(cancel_handler);
+    }
+
+    /// Copies the current protocol state into the metrics endpoint's view
+    pub fn publish_metrics(&self){
+        let (terminated_rbcs, witnesses_accepted) = match self.round_state.get(&self.round){
+            Some(rnd_state) => (
+                rnd_state.terminated_rbcs.len(),
+                rnd_state.witnesses.values()
+                    .filter(|rbcs| rbcs.iter().all(|rbc| rnd_state.terminated_rbcs.contains(rbc)))
+                    .count(),
+            ),
+            None => (0, 0),
+        };
+        // Only the current members are tracked, revoked ids are not
+        let peers = self.liveness.read().unwrap().snapshot();
+        let mut metrics = self.metrics.write().unwrap();
+        metrics.round = self.round;
+        metrics.value = self.value;
+        metrics.phase = self.stats.current_phase();
+        metrics.terminated_rbcs = terminated_rbcs;
+        metrics.witnesses_accepted = witnesses_accepted;
+        metrics.mac_failures = self.blame.total(Offence::BadMac);
+        metrics.malformed_payloads = self.blame.total(Offence::MalformedPayload);
+        metrics.net_queue = self.net_recv.len();
+        metrics.sync_queue = self.sync_recv.len();
+        metrics.peers_up = peers.iter().filter(|s| s.up).count();
+        metrics.peers_down = peers.len() - metrics.peers_up;
+        metrics.peers = peers;
+        metrics.traffic = self.traffic.report(self.myid, self.header.session).totals();
+    }
+
+    /// What the node has to show for the current round
+    pub fn progress(&self) -> Progress{
+        let steps = match self.round_state.get(&self.round){
+            Some(rnd_state) => rnd_state.node_msgs.len()
+                + rnd_state.echos.values().map(|echoes| echoes.len()).sum::<usize>()
+                + rnd_state.readys.values().map(|readys| readys.len()).sum::<usize>()
+                + rnd_state.terminated_rbcs.len()
+                + rnd_state.witnesses.len(),
+            None => 0,
+        };
+        Progress{round: self.round, steps}
+    }
+
+    /// Logs where the current round is stuck and tells the syncer, once the
+    /// round has made no progress for longer than the stall timeout
+    pub async fn check_progress(&mut self){
+        let progress = self.progress();
+        if let Some(stalled_ms) = self.watchdog.check(progress, now_millis()){
+            let report = self.stall_report(stalled_ms);
+            report.log();
+            let cancel_handler = self.sync_send.send(0, SyncMsg { sender: self.myid, state: SyncState::Stalled, value:self.round, payload:report.to_bytes()}).await;
+            self.add_cancel_handler(cancel_handler);
+        }
+    }
+
+    /// The RBCs and witnesses of the current round that are still open
+    pub fn stall_report(&self, stalled_ms:u128) -> StallReport{
+        let mut report = StallReport::new(self.myid, self.header.session, self.round, stalled_ms, self.num_nodes, self.num_faults);
+        self.add_rbc_progress(&mut report);
+        report.sort();
+        report
+    }
+
+    fn add_rbc_progress(&self, report:&mut StallReport){
+        let empty = RoundState::new();
+        let rnd_state = self.round_state.get(&self.round).unwrap_or(&empty);
+        for origin in 0..self.num_nodes{
+            if rnd_state.terminated_rbcs.contains(&origin){
+                continue;
+            }
+            report.add_rbc(origin, rnd_state.node_msgs.contains_key(&origin),
+                rnd_state.echos.get(&origin).into_iter().flatten(),
+                rnd_state.readys.get(&origin).into_iter().flatten());
+        }
+        let witnesses = &rnd_state.witnesses;
+        for (sender, rbcs) in witnesses.iter(){
+            let unterminated:Vec<Replica> = rbcs.iter()
+                .filter(|rbc| !rnd_state.terminated_rbcs.contains(rbc))
+                .cloned()
+                .collect();
+            if !unterminated.is_empty(){
+                report.witnesses.push(WitnessProgress{sender: *sender, unterminated});
+            }
+        }
+        report.missing_witnesses = report.missing(witnesses.keys());
+    }
+
+    /// Round and phase timings and message counts of the run so far
+    pub fn agreement_summary(&self) -> AgreementSummary{
+        self.stats.summary(self.myid, now_millis(), &self.traffic)
+    }
+
+    /// Logs this node's round and phase statistics and sends them to the
+    /// syncer. Called right before reporting completion, so the syncer has
+    /// the report by the time it counts the node as done.
+    pub async fn report_completion(&mut self){
+        let report = CompletionReport{
+            node: self.myid,
+            session: self.header.session,
+            value: self.value,
+            summary: self.agreement_summary(),
+        };
+        report.summary.log();
+        self.publish_metrics();
+        self.watchdog.disarm();
+        let cancel_handler = self.sync_send.send(0, SyncMsg { sender: self.myid, state: SyncState::CompletionReport, value:self.value, payload:report.to_bytes()}).await;
+        self.add_cancel_handler(cancel_handler);
+    }
+
+    /// Sends through the link emulation, if there is one. Only a message
+    /// sent right away has a cancel handler yet.
+    pub async fn send_emulated(&mut self, replica:Replica, wrapper_msg:WrapperMsg) -> Option<CancelHandler<Acknowledgement>>{
+        let route = match &mut self.links{
+            Some(links) => links.route(replica, now_millis()),
+            None => Route::Now,
+        };
+        match route{
+            Route::Now => Some(self.net_send.send(replica, Frame::encode(&wrapper_msg)).await),
+            Route::After(ms) => {
+                self.delayed.insert((replica, wrapper_msg), Duration::from_millis(ms));
+                None
+            },
+            Route::Drop => {
+                tracing::trace!("Link emulation dropped a message to {}",replica);
+                None
+            },
+        }
+    }
+
     pub fn add_cancel_handler(&mut self, canc: CancelHandler<Acknowledgement>){
         self.cancel_handlers
             .entry(self.round)
@@ -208,11 +538,3 @@ impl Context {
             .push(canc);
     }
 }
-
-pub fn to_socket_address(
-    ip_str: &str,
-    port: u16,
-) -> SocketAddr {
-    let addr = SocketAddrV4::new(ip_str.parse().unwrap(), port);
-    addr.into()
-}
\ No newline at end of file
@@ -11,7 +11,7 @@ impl Context{
         let rbc_originator = main_msg.origin;
         let round_state_map = &mut self.round_state;
         // Highly unlikely that the node will get an echo before rbc_init message
-        log::info!("Received ECHO message {:?}",main_msg.clone());
+        tracing::info!("Received ECHO message {:?}",main_msg.clone());
         if round_state_map.contains_key(&main_msg.round){
             // 1. Add echos to the round state object
             let rnd_state = round_state_map.get_mut(&main_msg.round).unwrap();
@@ -31,7 +31,7 @@ impl Context{
             }
             let echos = rnd_state.echos.get_mut(&rbc_originator).unwrap();
             // 2. Check if echos reached the threshold, init already received, and round number is matching
-            log::debug!("ECHO check: Round equals: {}, echos.len {}, contains key: {}"
+            tracing::debug!("ECHO check: Round equals: {}, echos.len {}, contains key: {}"
             ,self.round == main_msg.round,echos.len(),rnd_state.node_msgs.contains_key(&rbc_originator));
             if echos.len() == self.num_nodes-self.num_faults && 
                 rnd_state.node_msgs.contains_key(&rbc_originator){
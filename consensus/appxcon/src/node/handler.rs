@@ -1,39 +1,56 @@
-
 use async_trait::async_trait;
+use consensus::SharedMetrics;
 use futures_util::SinkExt;
 use network::{Acknowledgement};
 use tokio::sync::mpsc::UnboundedSender;
-use types::appxcon::WrapperMsg;
+use types::{Frame, appxcon::WrapperMsg};
 
 #[derive(Debug, Clone)]
 pub struct Handler {
     consensus_tx: UnboundedSender<WrapperMsg>,
+    metrics: SharedMetrics,
 }
 
 impl Handler {
-    pub fn new(consensus_tx: UnboundedSender<WrapperMsg>) -> Self {
-        Self { consensus_tx }
+    pub fn new(consensus_tx: UnboundedSender<WrapperMsg>, metrics: SharedMetrics) -> Self {
+        Self { consensus_tx, metrics }
     }
 }
 
 #[async_trait]
-impl network::Handler<Acknowledgement, WrapperMsg>
+impl network::Handler<Acknowledgement, Frame>
     for Handler
 {
     async fn dispatch(
         &self,
-        msg: WrapperMsg,
+        frame: Frame,
         writer: &mut network::Writer<Acknowledgement>,
     ) {
-        // Forward the message
-        self.consensus_tx
-            .send(msg)
-            .expect("Failed to send message to the consensus channel");
+        // libnet hands us the frame without the connection it came in on,
+        // so a frame that does not decode is counted for the node as a
+        // whole and never feeds the per-peer blame. Taking the sender id
+        // from the frame itself would let anyone get an honest peer blocked.
+        // Frames that decode are blamed on their authenticated sender in
+        // process.rs.
+        match frame.decode::<WrapperMsg>() {
+            Ok(msg) => {
+                // Forward the message
+                if let Err(e) = self.consensus_tx.send(msg) {
+                    // The consensus loop is gone, most likely shutting down
+                    tracing::error!("Failed to send message to the consensus channel: {}", e);
+                    return;
+                }
+            }
+            Err(e) => {
+                let mut metrics = self.metrics.write().unwrap();
+                metrics.malformed_frames += 1;
+                tracing::warn!("Dropping malformed frame ({} dropped so far): {}", metrics.malformed_frames, e);
+            }
+        }
 
-        // Acknowledge
-        writer
-            .send(Acknowledgement::Pong)
-            .await
-            .expect("Failed to send an acknowledgement");
+        // Acknowledge, also a dropped frame so that the sender does not resend it
+        if let Err(e) = writer.send(Acknowledgement::Pong).await {
+            tracing::warn!("Failed to send an acknowledgement: {:?}", e);
+        }
     }
 }
@@ -1,12 +1,14 @@
 use std::{sync::Arc, collections::HashSet};
 
 use crypto::hash::{verf_mac};
-use types::{appxcon::{WrapperMsg, ProtMsg,Msg}};
+use consensus::{Offence, Direction};
+use types::{appxcon::{WrapperMsg, ProtMsg,Msg, Replica}, WireError};
 use crate::node::{
     context::Context
 };
 use super::echo::{create_roundstate};
 use async_recursion::async_recursion;
+use tracing::Instrument;
 
 
 /*
@@ -28,56 +30,142 @@ use async_recursion::async_recursion;
 impl Context{
     pub fn check_proposal(&self,wrapper_msg: Arc<WrapperMsg>) -> bool {
         // validate MAC
-        let byte_val = bincode::serialize(&wrapper_msg.protmsg).expect("Failed to serialize object");
+        let byte_val = WrapperMsg::mac_bytes(&wrapper_msg.header, &wrapper_msg.protmsg);
         let sec_key = match self.sec_key_map.get(&wrapper_msg.clone().sender) {
             Some(val) => {val},
-            None => {panic!("Secret key not available, this shouldn't happen")},
+            None => {
+                tracing::warn!("No secret key shared with claimed sender {}", wrapper_msg.sender);
+                return false;
+            },
         };
         if !verf_mac(&byte_val,&sec_key.as_slice(),&wrapper_msg.mac){
-            log::warn!("MAC Verification failed.");
+            tracing::warn!("MAC Verification failed.");
             return false;
         }
         true
     }
     
     pub(crate) async fn process_msg(&mut self, wrapper_msg: WrapperMsg){
-        log::debug!("Received protocol msg: {:?}",wrapper_msg);
+        tracing::debug!("Received protocol msg: {:?}",wrapper_msg);
+        if self.blame.is_blocked(wrapper_msg.sender){
+            tracing::debug!("Ignoring message from blocked peer {}",wrapper_msg.sender);
+            return;
+        }
+        // Left over from another run or build; not the sender's fault, so no blame
+        if let Err(e) = wrapper_msg.header.check(&self.header){
+            tracing::warn!("Dropping message from node {}: {}",wrapper_msg.sender,e);
+            return;
+        }
         let msg = Arc::new(wrapper_msg.clone());
         if self.check_proposal(msg){
-            match wrapper_msg.clone().protmsg {
-                ProtMsg::RBCInit(main_msg,_rep)=> {
-                    // RBC initialized
-                    log::debug!("Received RBC init : {:?}",main_msg);
-                    // Reject all messages from older rounds
-                    if self.round <= main_msg.round{
-                        self.process_rbc_init(main_msg.clone()).await;
-                    }
-                },
-                ProtMsg::ECHO(main_msg, _orig, sender) =>{
-                    // ECHO for main_msg: RBC originated by orig, echo sent by sender
-                    // Reject all messages from older rounds and accepted RBCs
-                    if self.round <= main_msg.round{
-                        self.process_echo(main_msg.clone(), sender).await;
-                    }
-                },
-                ProtMsg::READY(main_msg, _orig, sender) =>{
-                    // READY for main_msg: RBC originated by orig, echo sent by sender
-                    if self.round <= main_msg.round{
-                        self.process_ready(main_msg.clone(), sender).await;
-                    }
-                },
-                ProtMsg::WITNESS(vec_rbc_indices,witness_sender, round) => {
-                    // WITNESS for main_msg: RBC originated by orig, echo sent by sender
-                    if self.round <= round{
-                        self.handle_witness( vec_rbc_indices, round, witness_sender).await;
-                    }
-                }
-                _=>{}
+            self.mark_alive(wrapper_msg.sender);
+            let bytes = bincode::serialized_size(&wrapper_msg).unwrap_or(0);
+            let protmsg = &wrapper_msg.protmsg;
+            self.traffic.record(wrapper_msg.sender, Direction::Received, protmsg.phase(), protmsg.kind(), protmsg.round(), bytes);
+            if let Err(e) = self.check_payload(&wrapper_msg){
+                tracing::warn!("Malformed message from node {}: {}",wrapper_msg.sender,e);
+                self.blame.blame(wrapper_msg.sender, Offence::MalformedPayload);
+                return;
             }
+            // Only authenticated, well-formed messages open spans, and
+            // only for rounds near ours
+            let span = self.spans.for_msg(protmsg.phase(), protmsg.round(), protmsg.origin());
+            self.handle_msg(wrapper_msg.protmsg).instrument(span).await;
         }
         else {
-            log::warn!("MAC Verification failed for message {:?}",wrapper_msg.protmsg);
+            tracing::warn!("MAC Verification failed for message {:?}",wrapper_msg.protmsg);
+            // Frames claiming an id we share no key with cannot be pinned on anyone
+            if self.sec_key_map.contains_key(&wrapper_msg.sender){
+                self.blame.blame(wrapper_msg.sender, Offence::BadMac);
+            }
+        }
+    }
+
+    /// Hands an authenticated, well-formed message to its protocol stage
+    async fn handle_msg(&mut self, protmsg: ProtMsg){
+        match protmsg {
+            ProtMsg::RBCInit(main_msg,_rep)=> {
+                // RBC initialized
+                tracing::debug!("Received RBC init : {:?}",main_msg);
+                // Reject all messages from older rounds
+                if self.round <= main_msg.round{
+                    self.process_rbc_init(main_msg.clone()).await;
+                }
+            },
+            ProtMsg::ECHO(main_msg, _orig, sender) =>{
+                // ECHO for main_msg: RBC originated by orig, echo sent by sender
+                // Reject all messages from older rounds and accepted RBCs
+                if self.round <= main_msg.round{
+                    self.process_echo(main_msg.clone(), sender).await;
+                }
+            },
+            ProtMsg::READY(main_msg, _orig, sender) =>{
+                // READY for main_msg: RBC originated by orig, echo sent by sender
+                if self.round <= main_msg.round{
+                    self.process_ready(main_msg.clone(), sender).await;
+                }
+            },
+            ProtMsg::WITNESS(vec_rbc_indices,witness_sender, round) => {
+                // WITNESS for main_msg: RBC originated by orig, echo sent by sender
+                if self.round <= round{
+                    self.handle_witness( vec_rbc_indices, round, witness_sender).await;
+                }
+            }
+            _=>{}
+        }
+    }
+
+    /// Structural checks on an authenticated message. The MAC only tells us
+    /// who sent the message; a Byzantine sender can still put anything in it.
+    pub fn check_payload(&self, wrapper_msg: &WrapperMsg) -> Result<(), WireError>{
+        let sender = wrapper_msg.sender;
+        match &wrapper_msg.protmsg {
+            ProtMsg::RBCInit(main_msg, _) => {
+                if main_msg.origin != sender{
+                    return Err(WireError::Malformed("RBCInit origin differs from sender"));
+                }
+                self.check_rbc_msg(main_msg)?;
+            },
+            ProtMsg::ECHO(main_msg, _, echo_sender) | ProtMsg::READY(main_msg, _, echo_sender) => {
+                if *echo_sender != sender{
+                    return Err(WireError::Malformed("vote sender differs from sender"));
+                }
+                self.check_rbc_msg(main_msg)?;
+            },
+            ProtMsg::WITNESS(vec_rbc_indices, witness_sender, _) | ProtMsg::WITNESS2(vec_rbc_indices, witness_sender, _) => {
+                if *witness_sender != sender{
+                    return Err(WireError::Malformed("witness sender differs from sender"));
+                }
+                self.check_witness_list(vec_rbc_indices)?;
+            },
+            ProtMsg::Heartbeat(hb_sender, _) => {
+                if *hb_sender != sender{
+                    return Err(WireError::Malformed("heartbeat sender differs from sender"));
+                }
+            },
+            _=>{}
+        }
+        Ok(())
+    }
+
+    fn check_rbc_msg(&self, main_msg: &Msg) -> Result<(), WireError>{
+        if main_msg.origin >= self.num_nodes{
+            return Err(WireError::Malformed("RBC origin out of range"));
+        }
+        Ok(())
+    }
+
+    fn check_witness_list(&self, vec_rbc_indices: &Vec<Replica>) -> Result<(), WireError>{
+        if vec_rbc_indices.len() < self.num_nodes-self.num_faults{
+            return Err(WireError::Malformed("witness lists fewer than n-f RBCs"));
+        }
+        let mut seen = HashSet::new();
+        for rep in vec_rbc_indices.iter(){
+            if *rep >= self.num_nodes || !seen.insert(*rep){
+                return Err(WireError::Malformed("witness list entry out of range or repeated"));
+            }
         }
+        Ok(())
     }
     
     pub async fn process_rbc_init(self:&mut Context,main_msg: Msg){
@@ -85,7 +173,7 @@ impl Context{
         let round_state_map = &mut self.round_state;
         // 1. Check if the protocol reached the round for this node
         let mut msgs_to_be_sent:Vec<ProtMsg> = Vec::new();
-        log::info!("Received RBC Init from node {} in round {}",main_msg.origin,main_msg.round);
+        tracing::info!("Received RBC Init from node {} in round {}",main_msg.origin,main_msg.round);
         if round_state_map.contains_key(&main_msg.round){
             let rnd_state = round_state_map.get_mut(&main_msg.round).unwrap();
             rnd_state.node_msgs.insert(sender, main_msg.clone());
@@ -121,7 +209,7 @@ impl Context{
             msgs_to_be_sent.push(ProtMsg::ECHO(main_msg.clone(), main_msg.origin, self.myid));
         }
         // Inserting send message block here to not borrow self as mutable again
-        log::debug!("Sending echos for RBC from origin {}",main_msg.origin);
+        tracing::debug!("Sending echos for RBC from origin {}",main_msg.origin);
         for prot_msg in msgs_to_be_sent.iter(){
             self.broadcast(prot_msg.clone()).await;
             self.process_echo(main_msg.clone(), self.myid).await;
@@ -138,5 +226,5 @@ impl Context{
 //             self.c_send(replica,sent_msg).await;
 //         }
 //     }
-//     log::info!("Broadcasted message {:?}",mm.clone());
+//     tracing::info!("Broadcasted message {:?}",mm.clone());
 // }
\ No newline at end of file
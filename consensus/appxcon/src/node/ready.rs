@@ -11,7 +11,7 @@ impl Context{
         let rbc_originator = main_msg.origin;
         let round_state_map = &mut self.round_state;
         let mut msgs_to_be_sent:Vec<ProtMsg> = Vec::new();
-        log::info!("Received READY message {:?}",main_msg.clone());
+        tracing::info!("Received READY message {:?}",main_msg.clone());
         // Highly unlikely that the node will get an echo before rbc_init message
         if round_state_map.contains_key(&main_msg.round){
             // 1. Add readys to the round state object
@@ -31,7 +31,7 @@ impl Context{
             }
             let readys = rnd_state.readys.get_mut(&rbc_originator).unwrap();
             // 2. Check if readys reached the threshold, init already received, and round number is matching
-            log::debug!("READY check: Round equals: {}, echos.len {}, contains key: {}"
+            tracing::debug!("READY check: Round equals: {}, echos.len {}, contains key: {}"
             ,self.round == main_msg.round,readys.len(),rnd_state.node_msgs.contains_key(&rbc_originator));
             if  readys.len() == self.num_faults+1 &&
                 rnd_state.node_msgs.contains_key(&rbc_originator){
@@ -44,14 +44,17 @@ impl Context{
                 rnd_state.node_msgs.contains_key(&rbc_originator){
                 // Terminate RBC, RAccept the value
                 // Add value to value list, add rbc to rbc list
-                log::info!("Terminated RBC of node {} with value {}",main_msg.origin,main_msg.value);
+                tracing::info!(origin = main_msg.origin, value = main_msg.value, "Terminated RBC of node {} with value {}",main_msg.origin,main_msg.value);
                 rnd_state.terminated_rbcs.insert(rbc_originator);
                 rnd_state.accepted_vals.push(main_msg.value);
                 if rnd_state.terminated_rbcs.len() >= self.num_nodes - self.num_faults{
                     // Has a witness message been sent already? If not, send it. 
                     if !rnd_state.witness_sent{
-                        log::info!("Terminated n-f RBCs, sending list of first n-f RBCs to other nodes");
-                        log::info!("Round state: {:?}",rnd_state.terminated_rbcs);
+                        let now = consensus::now_millis();
+                        self.stats.phase_finished(main_msg.round, "rbc", now);
+                        self.stats.phase_started(main_msg.round, "witness", now);
+                        tracing::info!("Terminated n-f RBCs, sending list of first n-f RBCs to other nodes");
+                        tracing::info!("Round state: {:?}",rnd_state.terminated_rbcs);
                         let vec_rbcs = Vec::from_iter(rnd_state.terminated_rbcs.clone().into_iter());
                         let witness_msg = ProtMsg::WITNESS(
                             vec_rbcs.clone(), 
@@ -25,14 +25,15 @@ impl network::Handler<Acknowledgement, SyncMsg>
         writer: &mut network::Writer<Acknowledgement>,
     ) {
         // Forward the message
-        self.consensus_tx
-            .send(msg)
-            .expect("Failed to send message to the consensus channel");
+        if let Err(e) = self.consensus_tx.send(msg) {
+            // The consensus loop is gone, most likely shutting down
+            tracing::error!("Failed to send message to the consensus channel: {}", e);
+            return;
+        }
 
         // Acknowledge
-        writer
-            .send(Acknowledgement::Pong)
-            .await
-            .expect("Failed to send an acknowledgement");
+        if let Err(e) = writer.send(Acknowledgement::Pong).await {
+            tracing::warn!("Failed to send an acknowledgement: {:?}", e);
+        }
     }
 }
@@ -5,11 +5,12 @@ use crate::node::{RoundState};
 use super::Context;
 
 use async_recursion::async_recursion;
+use tracing::Instrument;
 
 impl Context{
     pub async fn handle_witness(&mut self,vec_rbc_indices:Vec<Replica>, round: u64, witness_sender:Replica){
         let round_state_map = &mut self.round_state;
-        log::info!("Received witness message {:?} from node {} for round {}",vec_rbc_indices.clone(),witness_sender,round);
+        tracing::info!("Received witness message {:?} from node {} for round {}",vec_rbc_indices.clone(),witness_sender,round);
         if round_state_map.contains_key(&round){
             let rnd_state = round_state_map.get_mut(&round).unwrap();
             rnd_state.witnesses.insert(witness_sender,vec_rbc_indices.clone());
@@ -41,6 +42,9 @@ impl Context{
             }
         }
         if i >= self.num_nodes-self.num_faults{
+            let now = consensus::now_millis();
+            self.stats.phase_finished(round, "witness", now);
+            self.stats.round_finished(round, now);
             // Update value for next round
             rnd_state.accepted_vals.sort();
             let nr_val = (rnd_state.accepted_vals.get(min_threshold).unwrap() 
@@ -51,12 +55,16 @@ impl Context{
             // write round estimation protocol
             if self.round <= 10{
                 // Initiate next RBCInit now
-                log::info!("Protocol completed round {} with new round value {} ",self.round,self.value);
-                self.start_rbc().await;
+                tracing::info!(value = self.value, "Protocol completed round {} with new round value {}",self.round,self.value);
+                let span = self.spans.round(self.round);
+                self.start_rbc().instrument(span).await;
             }
             else {
-                log::info!("Protocol terminated value {} ",self.value);
-                let cancel_handler = self.sync_send.send(0, SyncMsg{sender:self.myid,state:types::SyncState::COMPLETED,value:self.value}).await;
+                tracing::info!(value = self.value, "Protocol terminated value {}",self.value);
+                // Ahead of COMPLETED, so the syncer has it before it stops the run
+                self.report_traffic().await;
+                self.report_completion().await;
+                let cancel_handler = self.sync_send.send(0, SyncMsg{sender:self.myid,state:types::SyncState::COMPLETED,value:self.value, payload:Vec::new()}).await;
                 self.add_cancel_handler(cancel_handler);
             }
         }
@@ -70,7 +78,10 @@ impl Context{
             rnd_estm: false,
             message: Vec::new()
         };
-        log::info!("Send RBCInit messages from node {:?} for round {}",self.myid,self.round);
+        tracing::info!("Send RBCInit messages from node {:?} for round {}",self.myid,self.round);
+        let now = consensus::now_millis();
+        self.stats.round_started(self.round, self.value, now);
+        self.stats.phase_started(self.round, "rbc", now);
         // Add roundstate for round zero
         // if self.round_state.contains_key(&self.round){
         //     process_rbc_init(self,msg.clone()).await;
@@ -1,4 +1,5 @@
 use async_recursion::async_recursion;
+use tracing::Instrument;
 use types::{Replica, SyncMsg, SyncState, appxcon::ProtMsg};
 
 use crate::node::{Context, RoundStateBin};
@@ -10,14 +11,15 @@ impl Context{
         if self.round > round{
             return;
         }
-        log::info!("Received ECHO1 message from node {} with content {:?} for round {}",echo_sender,msgs,round);
+        tracing::info!("Received ECHO1 message from node {} with content {:?} for round {}",echo_sender,msgs,round);
         if round_state_map.contains_key(&round){
             let rnd_state = round_state_map.get_mut(&round).unwrap();
             let (echo1_msgs,echo2_msgs) = rnd_state.add_echo(msgs, echo_sender, self.num_nodes, self.num_faults,self.myid);
             if rnd_state.term_vals.len() == 1 {
-                log::info!("Binary AA terminated for round {}, starting round {}",round,round+1);
+                tracing::info!("Binary AA terminated for round {}, starting round {}",round,round+1);
                 let vec_vals:Vec<(Replica,u64)> = rnd_state.term_vals.clone().into_iter().map(|(rep,val)| (rep,val)).collect();
-                self.start_baa( vec_vals, round+1).await;
+                let span = self.spans.baa(round+1);
+                self.start_baa( vec_vals, round+1).instrument(span).await;
                 return;
             }
             if echo1_msgs.len() > 0{
@@ -38,7 +40,7 @@ impl Context{
 
     pub async fn process_baa_echo2(self: &mut Context, msgs: Vec<(Replica,Vec<u8>)>, echo2_sender:Replica, round:u64){
         let round_state_map = &mut self.bin_round_state;
-        log::info!("Received ECHO2 message from node {} with content {:?} for round {}",echo2_sender,msgs,round);
+        tracing::info!("Received ECHO2 message from node {} with content {:?} for round {}",echo2_sender,msgs,round);
         if self.round > round{
             return;
         }
@@ -46,10 +48,11 @@ impl Context{
             let rnd_state = round_state_map.get_mut(&round).unwrap();
             rnd_state.add_echo2(msgs, echo2_sender, self.num_nodes, self.num_faults);
             if rnd_state.term_vals.len() == 1 {
-                log::info!("Binary AA terminated for round {}, starting round {}",round,round+1);
+                tracing::info!("Binary AA terminated for round {}, starting round {}",round,round+1);
                 let vec_vals:Vec<(Replica,u64)> = rnd_state.term_vals.clone().into_iter().map(|(rep,val)| (rep,val)).collect();
                 //self.add_benchmark(String::from("process_baa_echo2"), now.elapsed().unwrap().as_nanos());
-                self.start_baa( vec_vals, round+1).await;
+                let span = self.spans.baa(round+1);
+                self.start_baa( vec_vals, round+1).instrument(span).await;
                 return;
             }
         }
@@ -62,9 +65,13 @@ impl Context{
     #[async_recursion::async_recursion]
     pub async fn start_baa(self: &mut Context, round_vecs: Vec<(Replica,u64)>, round:u64){
         self.round = round;
+        let now = consensus::now_millis();
+        if round > 0{
+            self.stats.round_finished(round-1, now);
+        }
         if self.round > self.rounds_bin{
             // let appxcon_map = &mut self.batchvss_state.nz_appxcon_rs;
-            // log::info!("Approximate Agreement Protocol terminated with values {:?}",round_vecs.clone());
+            // tracing::info!("Approximate Agreement Protocol terminated with values {:?}",round_vecs.clone());
             // // Reconstruct values
             // let mapped_rvecs:Vec<(Replica,u64)> = 
             //     round_vecs.clone().into_iter()
@@ -73,19 +80,24 @@ impl Context{
             // for (rep,val) in mapped_rvecs.into_iter(){
             //     appxcon_map.insert(rep, (val,false,0));
             // }
-            log::info!("Terminated appx_consensus protocol with value {}, sending message to syncer",self.value);
-            let cancel_handler = self.sync_send.send(0, SyncMsg { sender: self.myid, state: SyncState::CompletedSharing, value: self.value }).await;
+            tracing::info!(value = self.value, "Terminated appx_consensus protocol with value {}, sending message to syncer",self.value);
+            self.stage_transition("baa", None, round-1);
+            // Ahead of CompletedSharing, so the syncer has it before it stops the run
+            self.report_traffic().await;
+            self.report_completion().await;
+            let cancel_handler = self.sync_send.send(0, SyncMsg { sender: self.myid, state: SyncState::CompletedSharing, value: self.value, payload:Vec::new() }).await;
             self.add_cancel_handler(cancel_handler);
             // for i in 0..10{
             //     self.invoke_coin.insert(i, Duration::from_millis((1000*i).try_into().unwrap()));
             // }
             return;
         }
+        self.stats.round_started(round, self.value, now);
         let transmit_vec:Vec<(Replica,Vec<u8>)> = round_vecs.into_iter().map(|(rep,val)| (rep,val.to_be_bytes().to_vec())).collect();
         let prot_msg = ProtMsg::BinaryAAEcho(transmit_vec.clone(), self.myid,round);
         //self.add_benchmark(String::from("start_baa"), now.elapsed().unwrap().as_nanos());
         self.broadcast(prot_msg.clone()).await;
         self.process_baa_echo(transmit_vec.clone(), self.myid, round).await;
-        log::info!("Broadcasted message {:?}",prot_msg);
+        tracing::info!("Broadcasted message {:?}",prot_msg);
     }
 }
\ No newline at end of file
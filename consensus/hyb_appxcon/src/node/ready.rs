@@ -11,7 +11,7 @@ impl Context{
         let rbc_originator = main_msg.origin;
         let round_state_map = &mut self.round_state;
         let mut msgs_to_be_sent:Vec<ProtMsg> = Vec::new();
-        log::info!("Received READY message {:?}",main_msg.clone());
+        tracing::info!("Received READY message {:?}",main_msg.clone());
         // Highly unlikely that the node will get an echo before rbc_init message
         if round_state_map.contains_key(&main_msg.round){
             // 1. Add readys to the round state object
@@ -37,7 +37,7 @@ impl Context{
             }
             let readys = rnd_state.readys.get_mut(&rbc_originator).unwrap();
             // 2. Check if readys reached the threshold, init already received, and round number is matching
-            log::debug!("READY check: Round equals: {}, echos.len {}, contains key: {}"
+            tracing::debug!("READY check: Round equals: {}, echos.len {}, contains key: {}"
             ,self.round == main_msg.round,readys.len(),rnd_state.node_msgs.contains_key(&rbc_originator));
             if  readys.len() == self.num_faults+1 &&
                 rnd_state.node_msgs.contains_key(&rbc_originator){
@@ -50,7 +50,7 @@ impl Context{
                 rnd_state.node_msgs.contains_key(&rbc_originator){
                 // Terminate RBC, RAccept the value
                 // Add value to value list, add rbc to rbc list
-                log::info!("Terminated RBC of node {} with value {}",main_msg.origin,main_msg.value);
+                tracing::info!(origin = main_msg.origin, value = main_msg.value, "Terminated RBC of node {} with value {}",main_msg.origin,main_msg.value);
                 if main_msg.rnd_estm{
                     self.handle_witness(main_msg.message, main_msg.round, main_msg.origin, 1).await;
                     self.check_for_ht_witnesses(main_msg.round).await;
@@ -61,9 +61,9 @@ impl Context{
                     if rnd_state.terminated_rbcs.len() >= self.num_nodes - self.num_faults{
                         // Has a witness message been sent already? If not, send it. 
                         if !rnd_state.witness_sent{
-                            log::info!("Terminated n-f RBCs, sending list of first n-f RBCs to other nodes");
+                            tracing::info!("Terminated n-f RBCs, sending list of first n-f RBCs to other nodes");
                             // If the round is round zero, reliable broadcast the list of RBCs to other nodes
-                            log::info!("Round state: {:?}",rnd_state.terminated_rbcs);
+                            tracing::info!("Round state: {:?}",rnd_state.terminated_rbcs);
                             if self.round == 0{
                                 // start reliable broadcast of list of witnesses
                                 let vec_rbcs = Vec::from_iter(rnd_state.terminated_rbcs.clone().into_iter());
@@ -7,11 +7,12 @@ use crate::node::{RoundState};
 use super::Context;
 
 use async_recursion::async_recursion;
+use tracing::Instrument;
 
 impl Context{
     pub async fn handle_witness(&mut self,vec_rbc_indices:Vec<Replica>, round: u64, witness_sender:Replica, wround: u32){
         let round_state_map = &mut self.round_state;
-        log::info!("Received witness message{} {:?} from node {} for round {}",wround,vec_rbc_indices.clone(),witness_sender,round);
+        tracing::info!("Received witness message{} {:?} from node {} for round {}",wround,vec_rbc_indices.clone(),witness_sender,round);
         if round_state_map.contains_key(&round){
             let rnd_state = round_state_map.get_mut(&round).unwrap();
             if wround == 1{
@@ -19,7 +20,7 @@ impl Context{
                 self.check_for_ht_witnesses(round).await;    
             }
             else{
-                //log::info!("Received witness2 message {:?} from node {} for round {}",vec_rbc_indices.clone(),witness_sender,round);
+                //tracing::info!("Received witness2 message {:?} from node {} for round {}",vec_rbc_indices.clone(),witness_sender,round);
                 rnd_state.witnesses2.insert(witness_sender,vec_rbc_indices.clone());
                 self.check_for_ht_witnesses(round).await;
             }
@@ -64,12 +65,13 @@ impl Context{
                     // if node is a witness2, add value to the set of accepted w2 values
                     let vec_node_values:Vec<u64> = rbc_sets.iter().map(|x| rnd_state.node_msgs.get(x).unwrap().value.clone()).collect();
                     node_r_values.insert(replica, (vec_node_values.get(min_threshold).unwrap()+vec_node_values.get(high_threshold).unwrap())/2);
-                    log::info!("Witness 2 message from node {}, with indices: {:?}, round {}, value {:?}",replica,rbc_sets,round,node_r_values);
+                    tracing::info!("Witness 2 message from node {}, with indices: {:?}, round {}, value {:?}",replica,rbc_sets,round,node_r_values);
                     accepted_witnesses.insert(replica);
                 }
             }
         }
         if accepted_witnesses.len() >= self.num_nodes-self.num_faults{
+            self.stats.round_finished(round, consensus::now_millis());
             if self.round == 0{
                 // Round estimation protocol to be run, skip the rest of the protocol
                 let mut value_set = Vec::new();
@@ -79,12 +81,12 @@ impl Context{
                         vals_witness.push(rnd_state.node_msgs.get(acc_rep).unwrap().value);
                     }
                     vals_witness.sort();
-                    log::info!("First n-f values accepted by node {} are {:?}",replica,vals_witness.clone());
+                    tracing::info!("First n-f values accepted by node {} are {:?}",replica,vals_witness.clone());
                     let median_index = (vals_witness.len()-1)/2;
                     value_set.push(vals_witness[median_index]);
                 }
                 value_set.sort();
-                log::info!("Medians of n-f accepted values by each node {:?}",value_set);
+                tracing::info!("Medians of n-f accepted values by each node {:?}",value_set);
                 //let rounds_to_run = (((value_set.last().unwrap() - value_set.first().unwrap())/self.delta) as f64).log2().ceil().round() as u64; 
                 // if rounds_to_run == 0{
                 //     self.rounds_delta= 1;
@@ -93,9 +95,11 @@ impl Context{
                 //     self.rounds_delta= rounds_to_run;
                 // }
                 self.value = value_set.get((value_set.len()-1)/2).unwrap().clone();
-                //log::info!("Number of rounds to run: {} with starting value: {}",rounds_to_run,self.value);
+                //tracing::info!("Number of rounds to run: {} with starting value: {}",rounds_to_run,self.value);
+                self.stage_transition("estimation", Some("appx"), round);
                 self.round += 1;
-                self.start_rbc(false).await;
+                let span = self.spans.round(self.round);
+                self.start_rbc(false).instrument(span).await;
                 return;
             }
             // Update value for next round
@@ -111,22 +115,25 @@ impl Context{
                     // term_values.sort();
                     // let val_nxt_rnd = term_values[term_values.len()/2];
                     //self.value = val_nxt_rnd;
-                    log::info!("Sub-protocol terminated, starting BAA");
+                    tracing::info!("Sub-protocol terminated, starting BAA");
                     let mut transmit_vector = Vec::new();
                     transmit_vector.push((0,self.value));
+                    self.stage_transition("appx", Some("baa"), round);
                     self.rounds_bin = self.round + self.rounds_bin;
                     self.round = round+1;
-                    self.start_baa(transmit_vector,self.round).await;
+                    let span = self.spans.baa(self.round);
+                    self.start_baa(transmit_vector,self.round).instrument(span).await;
                 }
                 else{
-                    log::info!("Protocol completed round {} with new round value {} ",self.round,self.value);
+                    tracing::info!(value = self.value, "Protocol completed round {} with new round value {}",self.round,self.value);
                     self.round = round+1;
-                    self.start_rbc(false).await;
+                    let span = self.spans.round(self.round);
+                    self.start_rbc(false).instrument(span).await;
                 }
                 // broadcast witness2 message
                 // rnd_state.wround = 2;
                 // // Send WITNESS2 Message
-                // log::info!("Sent WITNESS2 message for round {}",self.round);
+                // tracing::info!("Sent WITNESS2 message for round {}",self.round);
                 // let vec_rbcs = Vec::from_iter(rnd_state.terminated_rbcs.clone().into_iter());
                 // let witness_msg = ProtMsg::WITNESS2(
                 //     vec_rbcs.clone(), 
@@ -140,7 +147,7 @@ impl Context{
                 self.round = self.round+1;
                 let mut range:Vec<u64> = node_r_values.values().into_iter().map(|x| x.clone()).collect();
                 range.sort();
-                log::info!("Sorted range in witness2 for round {} is {:?}",round,range);
+                tracing::info!("Sorted range in witness2 for round {} is {:?}",round,range);
                 let delta = range.last().unwrap() - range.first().unwrap();
                 if rnd_state.term_values.len() >= min_threshold+1{
                     // Sub-protocol terminated, start BAA
@@ -148,22 +155,26 @@ impl Context{
                     term_values.sort();
                     let val_nxt_rnd = term_values[term_values.len()/2];
                     self.value = val_nxt_rnd;
-                    log::info!("Sub-protocol terminated, starting BAA");
+                    tracing::info!("Sub-protocol terminated, starting BAA");
                     let mut transmit_vector = Vec::new();
                     transmit_vector.push((0,self.value));
+                    self.stage_transition("appx", Some("baa"), self.round-1);
                     self.rounds_bin = self.round + self.rounds_bin;
-                    self.start_baa(transmit_vector,self.round).await;
+                    let span = self.spans.baa(self.round);
+                    self.start_baa(transmit_vector,self.round).instrument(span).await;
                 }
                 else{
                     if delta > self.delta/2{
                         // Initiate next RBCInit now
-                        log::info!("Protocol completed round {} with new round value {} ",self.round,self.value);
-                        self.start_rbc(false).await;
+                        tracing::info!(value = self.value, "Protocol completed round {} with new round value {}",self.round,self.value);
+                        let span = self.spans.round(self.round);
+                        self.start_rbc(false).instrument(span).await;
                     }
                     else{
                         // Initiate next RBCInit now
-                        log::info!("Protocol completed round {} with new round value {} ",self.round,self.value);
-                        self.start_rbc(true).await;
+                        tracing::info!(value = self.value, "Protocol completed round {} with new round value {}",self.round,self.value);
+                        let span = self.spans.round(self.round);
+                        self.start_rbc(true).instrument(span).await;
                     }
                 }
                 //let cancel_handler = self.sync_send.send(0, SyncMsg{sender:self.myid,state:types::SyncState::COMPLETED}).await;
@@ -180,7 +191,13 @@ impl Context{
             rnd_estm: term,
             message: Vec::new()
         };
-        log::info!("Send RBCInit messages from node {:?} for round {}",self.myid,self.round);
+        tracing::info!("Send RBCInit messages from node {:?} for round {}",self.myid,self.round);
+        let now = consensus::now_millis();
+        self.stats.round_started(self.round, self.value, now);
+        if self.round == 0{
+            tracing::info!("Stage estimation started at round 0");
+            self.stats.stage_started("estimation", 0, now);
+        }
         // Add roundstate for round zero
         // if self.round_state.contains_key(&self.round){
         //     process_rbc_init(self,msg.clone()).await;
@@ -206,7 +223,7 @@ impl Context{
             message: ls_rbcs
         };
         //self.rnd_estm_state.witness_sent = true;
-        log::info!("Send RBCInit messages from node {:?} for round {}",self.myid,self.round);
+        tracing::info!("Send RBCInit messages from node {:?} for round {}",self.myid,self.round);
         self.broadcast(ProtMsg::RBCInit(msg.clone(), self.myid)).await;
         self.process_rbc_init(msg.clone()).await;
     }
@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+use types::Replica;
+
+use crate::{Direction, TrafficStats};
+
+/// Traffic of this phase is overhead, not part of the agreement
+const LIVENESS_PHASE: &str = "liveness";
+
+/// Milliseconds since the epoch
+pub fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time differencing errors")
+        .as_millis()
+}
+
+/// When a round started and finished, and the protocol messages of it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoundTiming {
+    pub round: u64,
+    /// The value the node entered the round with
+    pub value: Option<u64>,
+    pub start: u128,
+    pub finish: Option<u128>,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+}
+
+/// When one phase of a round started and finished
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseTiming {
+    pub round: u64,
+    pub phase: String,
+    pub start: u128,
+    pub finish: Option<u128>,
+}
+
+/// A stage of the protocol spanning one or more rounds, such as the
+/// estimation, approximate agreement and binary agreement stages of hyb
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageTiming {
+    pub stage: String,
+    pub first_round: u64,
+    /// None while the stage is running
+    pub last_round: Option<u64>,
+    pub start: u128,
+    pub finish: Option<u128>,
+    /// Protocol messages of the rounds the stage spans
+    pub messages_sent: u64,
+    pub messages_received: u64,
+}
+
+impl RoundTiming {
+    pub fn duration(&self) -> Option<u128> {
+        self.finish.map(|finish| finish.saturating_sub(self.start))
+    }
+}
+
+impl PhaseTiming {
+    pub fn duration(&self) -> Option<u128> {
+        self.finish.map(|finish| finish.saturating_sub(self.start))
+    }
+}
+
+impl StageTiming {
+    pub fn duration(&self) -> Option<u128> {
+        self.finish.map(|finish| finish.saturating_sub(self.start))
+    }
+
+    fn spans(&self, round: u64) -> bool {
+        self.first_round <= round && self.last_round.is_none_or(|last| round <= last)
+    }
+}
+
+/// Round and phase timings a node records over one agreement run. Times
+/// are milliseconds since the epoch; only the first start and the first
+/// finish of a round or phase count.
+#[derive(Debug, Clone, Default)]
+pub struct AgreementStats {
+    start: u128,
+    rounds: BTreeMap<u64, RoundTiming>,
+    phases: Vec<PhaseTiming>,
+    stages: Vec<StageTiming>,
+}
+
+impl AgreementStats {
+    pub fn new(start: u128) -> AgreementStats {
+        AgreementStats {
+            start,
+            rounds: BTreeMap::new(),
+            phases: Vec::new(),
+            stages: Vec::new(),
+        }
+    }
+
+    fn round_entry(&mut self, round: u64, now: u128) -> &mut RoundTiming {
+        self.rounds.entry(round).or_insert(RoundTiming {
+            round,
+            value: None,
+            start: now,
+            finish: None,
+            messages_sent: 0,
+            messages_received: 0,
+        })
+    }
+
+    pub fn round_started(&mut self, round: u64, value: u64, now: u128) {
+        self.round_entry(round, now).value.get_or_insert(value);
+    }
+
+    pub fn round_finished(&mut self, round: u64, now: u128) {
+        self.round_entry(round, now).finish.get_or_insert(now);
+    }
+
+    pub fn phase_started(&mut self, round: u64, phase: &str, now: u128) {
+        if self.phase(round, phase).is_none() {
+            self.phases.push(PhaseTiming {
+                round,
+                phase: phase.to_string(),
+                start: now,
+                finish: None,
+            });
+        }
+    }
+
+    pub fn phase_finished(&mut self, round: u64, phase: &str, now: u128) {
+        self.phase_started(round, phase, now);
+        let timing = self.phases.iter_mut()
+            .find(|p| p.round == round && p.phase == phase)
+            .unwrap();
+        timing.finish.get_or_insert(now);
+    }
+
+    pub fn phase(&self, round: u64, phase: &str) -> Option<&PhaseTiming> {
+        self.phases.iter().find(|p| p.round == round && p.phase == phase)
+    }
+
+    pub fn stage_started(&mut self, stage: &str, first_round: u64, now: u128) {
+        if self.stage(stage).is_none() {
+            self.stages.push(StageTiming {
+                stage: stage.to_string(),
+                first_round,
+                last_round: None,
+                start: now,
+                finish: None,
+                messages_sent: 0,
+                messages_received: 0,
+            });
+        }
+    }
+
+    /// Ends a stage started earlier and returns how long it took. Stages
+    /// never started, or already finished, are left alone.
+    pub fn stage_finished(&mut self, stage: &str, last_round: u64, now: u128) -> Option<u128> {
+        let timing = self.stages.iter_mut()
+            .find(|s| s.stage == stage && s.finish.is_none())?;
+        timing.last_round = Some(last_round);
+        timing.finish = Some(now);
+        timing.duration()
+    }
+
+    pub fn stage(&self, stage: &str) -> Option<&StageTiming> {
+        self.stages.iter().find(|s| s.stage == stage)
+    }
+
+    /// The stage the node is in or, when no stage is running, the latest
+    /// unfinished phase; `idle` before the first round and `done` once
+    /// everything started has finished
+    pub fn current_phase(&self) -> String {
+        if let Some(stage) = self.stages.iter().rev().find(|s| s.finish.is_none()) {
+            return stage.stage.clone();
+        }
+        if let Some(phase) = self.phases.iter().rev().find(|p| p.finish.is_none()) {
+            return phase.phase.clone();
+        }
+        if self.rounds.is_empty() { "idle".to_string() } else { "done".to_string() }
+    }
+
+    /// Everything recorded so far, with message counts from `traffic`, for
+    /// a run that terminated at `finish`
+    pub fn summary(&self, node: Replica, finish: u128, traffic: &TrafficStats) -> AgreementSummary {
+        let mut rounds = self.rounds.clone();
+        let mut stages = self.stages.clone();
+        let (mut messages_sent, mut messages_received) = (0, 0);
+        let (mut bytes_sent, mut bytes_received) = (0, 0);
+        for rec in traffic.records().iter().filter(|rec| rec.phase != LIVENESS_PHASE) {
+            let timing = rounds.get_mut(&rec.round);
+            let stages = stages.iter_mut().filter(|s| s.spans(rec.round));
+            match rec.direction {
+                Direction::Sent => {
+                    messages_sent += rec.messages;
+                    bytes_sent += rec.bytes;
+                    if let Some(timing) = timing {
+                        timing.messages_sent += rec.messages;
+                    }
+                    stages.for_each(|s| s.messages_sent += rec.messages);
+                },
+                Direction::Received => {
+                    messages_received += rec.messages;
+                    bytes_received += rec.bytes;
+                    if let Some(timing) = timing {
+                        timing.messages_received += rec.messages;
+                    }
+                    stages.for_each(|s| s.messages_received += rec.messages);
+                },
+            }
+        }
+        let mut phases = self.phases.clone();
+        phases.sort_by_key(|p| (p.round, p.start));
+        AgreementSummary {
+            node,
+            start: self.start,
+            finish,
+            total_ms: finish.saturating_sub(self.start),
+            rounds_to_termination: rounds.values().filter(|r| r.finish.is_some()).count() as u64,
+            rounds: rounds.into_values().collect(),
+            phases,
+            stages,
+            messages_sent,
+            messages_received,
+            bytes_sent,
+            bytes_received,
+        }
+    }
+}
+
+/// What one node measured over an agreement run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgreementSummary {
+    pub node: Replica,
+    pub start: u128,
+    pub finish: u128,
+    pub total_ms: u128,
+    /// Rounds the node finished before terminating
+    pub rounds_to_termination: u64,
+    pub rounds: Vec<RoundTiming>,
+    pub phases: Vec<PhaseTiming>,
+    pub stages: Vec<StageTiming>,
+    /// Protocol messages only; heartbeats are left out
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl AgreementSummary {
+    /// Logs the summary as `DP[...]` lines. Round lines are
+    /// `round,start,duration,value`, phase lines `round,phase,start,duration`
+    /// and stage lines `stage,first_round,last_round,start,duration,sent,received`,
+    /// with the start relative to the start of the run. Unfinished rounds,
+    /// phases and stages have an empty duration.
+    pub fn log(&self) {
+        tracing::info!("DP[Rounds]: {}", self.rounds_to_termination);
+        tracing::info!("DP[Termination]: {}", self.total_ms);
+        for round in self.rounds.iter() {
+            tracing::info!("DP[Round]: {},{},{},{}", round.round,
+                round.start.saturating_sub(self.start),
+                round.duration().map(|d| d.to_string()).unwrap_or_default(),
+                round.value.map(|v| v.to_string()).unwrap_or_default());
+        }
+        for phase in self.phases.iter() {
+            tracing::info!("DP[Phase]: {},{},{},{}", phase.round, phase.phase,
+                phase.start.saturating_sub(self.start),
+                phase.duration().map(|d| d.to_string()).unwrap_or_default());
+        }
+        for stage in self.stages.iter() {
+            tracing::info!("DP[Stage]: {},{},{},{},{},{},{}", stage.stage, stage.first_round,
+                stage.last_round.map(|r| r.to_string()).unwrap_or_default(),
+                stage.start.saturating_sub(self.start),
+                stage.duration().map(|d| d.to_string()).unwrap_or_default(),
+                stage.messages_sent, stage.messages_received);
+        }
+        tracing::info!("DP[Messages]: {},{}", self.messages_sent, self.messages_received);
+        tracing::info!("DP[Bytes]: {},{}", self.bytes_sent, self.bytes_received);
+    }
+}
+
+/// What a node tells the syncer about its run when it completes, sent with
+/// `SyncState::CompletionReport` ahead of the plain completion message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletionReport {
+    pub node: Replica,
+    pub session: u64,
+    /// The value the node terminated with
+    pub value: u64,
+    pub summary: AgreementSummary,
+}
+
+impl CompletionReport {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize completion report")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<CompletionReport, bincode::Error> {
+        bincode::deserialize(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_counts_rounds_phases_stages_and_messages() {
+        let mut stats = AgreementStats::new(100);
+        stats.round_started(0, 7, 100);
+        stats.phase_started(0, "rbc", 100);
+        stats.phase_finished(0, "rbc", 130);
+        // Only the first finish counts
+        stats.phase_finished(0, "rbc", 150);
+        stats.round_finished(0, 160);
+        stats.round_started(1, 9, 160);
+        stats.stage_started("estimation", 0, 100);
+        assert_eq!(stats.stage_finished("estimation", 0, 160), Some(60));
+        assert_eq!(stats.stage_finished("estimation", 0, 170), None);
+        stats.stage_started("appx", 1, 160);
+        let mut traffic = TrafficStats::new();
+        traffic.record(1, Direction::Sent, "rbc", "RBCInit", 0, 10);
+        traffic.record(1, Direction::Received, "witness", "WITNESS", 1, 20);
+        traffic.record(1, Direction::Sent, "liveness", "Heartbeat", 0, 5);
+
+        let summary = stats.summary(0, 200, &traffic);
+        assert_eq!((summary.total_ms, summary.rounds_to_termination), (100, 1));
+        assert_eq!(summary.rounds[0].duration(), Some(60));
+        assert_eq!((summary.rounds[0].messages_sent, summary.rounds[1].messages_received), (1, 1));
+        assert_eq!((summary.rounds[1].duration(), summary.rounds[1].value), (None, Some(9)));
+        assert_eq!(summary.phases[0].duration(), Some(30));
+        assert_eq!((summary.messages_sent, summary.bytes_sent), (1, 10));
+        // Stages count the messages of the rounds they span
+        assert_eq!((summary.stages[0].messages_sent, summary.stages[0].messages_received), (1, 0));
+        assert_eq!((summary.stages[1].messages_sent, summary.stages[1].messages_received), (0, 1));
+    }
+}
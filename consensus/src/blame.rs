@@ -0,0 +1,109 @@
+use fnv::{FnvHashMap as HashMap, FnvHashSet as HashSet};
+use types::Replica;
+
+/// Number of authenticated offences after which we stop listening to a peer
+pub const BLAME_THRESHOLD: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Offence {
+    // The MAC did not verify under the key shared with the claimed sender
+    BadMac,
+    // The MAC verified, but the payload is malformed
+    MalformedPayload,
+}
+
+impl Offence {
+    /// Only offences on authenticated messages can be pinned on a peer.
+    /// Anyone can put another replica's id on a frame with a garbage MAC, so
+    /// counting those towards a block would let a Byzantine node get an
+    /// honest one ignored.
+    pub fn is_attributable(&self) -> bool {
+        matches!(self, Offence::MalformedPayload)
+    }
+}
+
+/// Per-peer tally of bad messages, and the set of peers whose messages we
+/// drop. Frames that do not decode never get here; see `Metrics::malformed_frames`.
+#[derive(Debug, Clone)]
+pub struct PeerBlame {
+    pub threshold: usize,
+    pub counts: HashMap<Replica, HashMap<Offence, usize>>,
+    pub blocked: HashSet<Replica>,
+}
+
+impl PeerBlame {
+    pub fn new(threshold: usize) -> PeerBlame {
+        PeerBlame {
+            threshold,
+            counts: HashMap::default(),
+            blocked: HashSet::default(),
+        }
+    }
+
+    /// Record an offence by `peer`. Returns true if this offence got the peer
+    /// blocked.
+    pub fn blame(&mut self, peer: Replica, offence: Offence) -> bool {
+        let counts = self.counts.entry(peer).or_default();
+        *counts.entry(offence).or_default() += 1;
+        let attributable: usize = counts.iter()
+            .filter(|(off, _)| off.is_attributable())
+            .map(|(_, c)| *c)
+            .sum();
+        tracing::warn!("Dropped bad message from peer {}: {:?} ({} attributable offences)", peer, offence, attributable);
+        if attributable >= self.threshold && self.blocked.insert(peer) {
+            tracing::error!("Peer {} crossed the blame threshold of {}, ignoring all further messages from it", peer, self.threshold);
+            return true;
+        }
+        false
+    }
+
+    pub fn is_blocked(&self, peer: Replica) -> bool {
+        self.blocked.contains(&peer)
+    }
+
+    /// Total bad frames seen from `peer`, attributable or not
+    pub fn bad_frames(&self, peer: Replica) -> usize {
+        self.counts.get(&peer)
+            .map(|c| c.values().sum())
+            .unwrap_or(0)
+    }
+
+    /// Offences of one kind seen from all peers
+    pub fn total(&self, offence: Offence) -> usize {
+        self.counts.values()
+            .filter_map(|c| c.get(&offence))
+            .sum()
+    }
+}
+
+impl Default for PeerBlame {
+    fn default() -> Self {
+        PeerBlame::new(BLAME_THRESHOLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unauthenticated_offences_never_block() {
+        let mut blame = PeerBlame::new(2);
+        for _ in 0..10 {
+            assert!(!blame.blame(1, Offence::BadMac));
+        }
+        assert!(!blame.is_blocked(1));
+        assert_eq!(blame.bad_frames(1), 10);
+    }
+
+    #[test]
+    fn repeated_malformed_payloads_block() {
+        let mut blame = PeerBlame::new(2);
+        assert!(!blame.blame(3, Offence::MalformedPayload));
+        assert!(blame.blame(3, Offence::MalformedPayload));
+        // Only the crossing offence reports the transition
+        assert!(!blame.blame(3, Offence::MalformedPayload));
+        assert!(blame.is_blocked(3));
+        assert!(!blame.is_blocked(2));
+    }
+}
@@ -2,6 +2,33 @@ use std::time::SystemTime;
 use fnv::FnvHashMap as HashMap;
 use crypto::hash::Hash;
 
+mod blame;
+pub use blame::*;
+
+mod traffic;
+pub use traffic::*;
+
+mod liveness;
+pub use liveness::*;
+
+mod agreement;
+pub use agreement::*;
+
+mod metrics;
+pub use metrics::*;
+
+mod spans;
+pub use spans::*;
+
+mod watchdog;
+pub use watchdog::*;
+
+mod membership;
+pub use membership::*;
+
+mod links;
+pub use links::*;
+
 pub fn statistics(
     now: SystemTime, 
     start:SystemTime, 
@@ -10,21 +37,21 @@ pub fn statistics(
 {
     let mut idx = 0 ;
     let mut total_time = 0;
-    log::info!("DP[Start]: {:?}", start);
-    log::info!("DP[End]: {:?}", now);
+    tracing::info!("DP[Start]: {:?}", start);
+    tracing::info!("DP[End]: {:?}", now);
     for (_hash, (begin, end)) in latency_map {
         let time = end.duration_since(begin)
             .expect("time differencing errors")
             .as_millis();
-        log::trace!("{}: {}", idx, time);
+        tracing::trace!("{}: {}", idx, time);
         idx += 1;
         total_time += time;
     }
-    log::info!("DP[Throughput]: {}", 
+    tracing::info!("DP[Throughput]: {}", 
         (idx as f64)/(now.duration_since(start)
             .expect("time differencing errors")
             .as_secs_f64())
     );
-    log::info!("DP[Latency]: {}", 
+    tracing::info!("DP[Latency]: {}", 
                 (total_time as f64)/(idx as f64));
 }
\ No newline at end of file
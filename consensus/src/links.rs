@@ -0,0 +1,138 @@
+use config::{Delay, LinkEmulation};
+use fnv::FnvHashMap as HashMap;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use rand::distributions::{Distribution, Exp, Normal};
+use types::Replica;
+
+/// What the link emulation does with a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Now,
+    /// Send it this many milliseconds from now
+    After(u64),
+    Drop,
+}
+
+/// Applies a node's `LinkEmulation` to the messages it sends
+#[derive(Debug)]
+pub struct LinkEmulator {
+    config: LinkEmulation,
+    myid: Replica,
+    rng: StdRng,
+    /// When the syncer started the current run; partitions count from it
+    run_start: Option<u128>,
+    /// When the last message on each link goes out. Links stay FIFO, as
+    /// TCP connections are, so a message never overtakes an earlier one.
+    last_release: HashMap<Replica, u128>,
+}
+
+impl LinkEmulator {
+    pub fn new(config: LinkEmulation, myid: Replica) -> LinkEmulator {
+        let seed = config.seed ^ (myid as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        LinkEmulator {
+            config,
+            myid,
+            rng: StdRng::seed_from_u64(seed),
+            run_start: None,
+            last_release: HashMap::default(),
+        }
+    }
+
+    pub fn start_run(&mut self, now: u128) {
+        self.run_start = Some(now);
+    }
+
+    /// Forgets the run; no partition applies until the next one starts
+    pub fn reset(&mut self) {
+        self.run_start = None;
+        self.last_release.clear();
+    }
+
+    /// Decides the fate of a message to `to` sent at `now`
+    pub fn route(&mut self, to: Replica, now: u128) -> Route {
+        let profile = self.config.profile(self.myid, to);
+        if profile.drop > 0.0 && self.rng.gen::<f64>() < profile.drop {
+            return Route::Drop;
+        }
+        let mut delay = sample(profile.delay, &mut self.rng);
+        if profile.jitter_ms > 0 {
+            delay += self.rng.gen_range(0, profile.jitter_ms+1);
+        }
+        let mut release = now + delay as u128;
+        if let Some(start) = self.run_start {
+            // Held until the partitions it would arrive in heal
+            let mut moved = true;
+            while moved {
+                moved = false;
+                for partition in self.config.partitions.iter().filter(|p| p.separates(self.myid, to)) {
+                    let (begin, heal) = (start + partition.start_ms as u128, start + partition.end_ms as u128);
+                    if release >= begin && release < heal {
+                        release = heal;
+                        moved = true;
+                    }
+                }
+            }
+        }
+        let last = self.last_release.entry(to).or_insert(0);
+        release = release.max(*last);
+        *last = release;
+        match release.saturating_sub(now) {
+            0 => Route::Now,
+            after => Route::After(after as u64),
+        }
+    }
+}
+
+fn sample(delay: Delay, rng: &mut StdRng) -> u64 {
+    match delay {
+        Delay::Fixed(ms) => ms,
+        Delay::Uniform { min_ms, max_ms } => rng.gen_range(min_ms, max_ms+1),
+        Delay::Normal { mean_ms, std_ms } => {
+            Normal::new(mean_ms as f64, std_ms as f64).sample(rng).max(0.0) as u64
+        },
+        Delay::Exponential { mean_ms: 0 } => 0,
+        Delay::Exponential { mean_ms } => Exp::new(1.0 / mean_ms as f64).sample(rng) as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{LinkProfile, LinkRule, Partition};
+
+    #[test]
+    fn delays_drops_and_partitions() {
+        let fixed = |ms| LinkProfile { delay: Delay::Fixed(ms), jitter_ms: 0, drop: 0.0 };
+        let config = LinkEmulation {
+            default: fixed(10),
+            links: vec![
+                LinkRule { from: Some(0), to: Some(2), profile: LinkProfile { drop: 1.0, ..fixed(0) } },
+                LinkRule { from: None, to: Some(3), profile: LinkProfile { delay: Delay::Uniform { min_ms: 0, max_ms: 50 }, jitter_ms: 5, drop: 0.0 } },
+            ],
+            partitions: vec![Partition { start_ms: 100, end_ms: 200, groups: vec![vec![0], vec![1]] }],
+            seed: 7,
+        };
+        let mut links = LinkEmulator::new(config, 0);
+        assert_eq!(links.route(1, 1000), Route::After(10));
+        assert_eq!(links.route(2, 1000), Route::Drop);
+        // Whatever the delays drawn, messages on a link stay in order
+        let mut last = 0;
+        for now in 1000..1100 {
+            let release = match links.route(3, now) {
+                Route::Now => now,
+                Route::After(ms) => now + ms as u128,
+                Route::Drop => unreachable!(),
+            };
+            assert!(release >= last && release <= now + 55);
+            last = release;
+        }
+
+        // Partitions count from the start of the run
+        links.start_run(2000);
+        assert_eq!(links.route(1, 2050), Route::After(10));
+        assert_eq!(links.route(1, 2095), Route::After(105));
+        assert_eq!(links.route(1, 2150), Route::After(50));
+        links.reset();
+        assert_eq!(links.route(1, 2150), Route::After(10));
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::{Arc, RwLock};
+
+use fnv::FnvHashMap as HashMap;
+use serde::{Serialize, Deserialize};
+use types::Replica;
+
+/// How often (in milliseconds) every node sends a heartbeat to each peer
+pub const HEARTBEAT_INTERVAL_MS: u64 = 500;
+/// A peer we have not heard from for this long (in milliseconds) is down
+pub const SUSPECT_AFTER_MS: u128 = 4 * HEARTBEAT_INTERVAL_MS as u128;
+
+/// What we know about one peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerStatus {
+    pub peer: Replica,
+    pub up: bool,
+    /// Milliseconds since the epoch of the last authenticated message, if any
+    pub last_seen: Option<u128>,
+    /// Milliseconds since the epoch at which the peer was last marked down
+    pub down_since: Option<u128>,
+    /// Times the peer came back after being marked down
+    pub reconnects: usize,
+}
+
+/// A change in a peer's status, worth telling the operator about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    // First message from the peer
+    Up(Replica),
+    // Silent for longer than the timeout
+    Down(Replica),
+    // Heard from again after being marked down
+    Reconnected(Replica),
+}
+
+/// Tracks when each peer was last heard from
+#[derive(Debug, Clone)]
+pub struct LivenessTracker {
+    pub timeout: u128,
+    peers: HashMap<Replica, PeerStatus>,
+}
+
+/// The tracker as shared between a node's event loop and anything that
+/// wants to query it while the run is going
+pub type SharedLiveness = Arc<RwLock<LivenessTracker>>;
+
+impl LivenessTracker {
+    pub fn new(peers: impl IntoIterator<Item = Replica>, timeout: u128) -> LivenessTracker {
+        let peers = peers.into_iter()
+            .map(|peer| (peer, PeerStatus {
+                peer,
+                up: false,
+                last_seen: None,
+                down_since: None,
+                reconnects: 0,
+            }))
+            .collect();
+        LivenessTracker { timeout, peers }
+    }
+
+    pub fn shared(self) -> SharedLiveness {
+        Arc::new(RwLock::new(self))
+    }
+
+    /// Records a message from `peer` at time `now`
+    pub fn seen(&mut self, peer: Replica, now: u128) -> Option<Transition> {
+        let status = self.peers.get_mut(&peer)?;
+        status.last_seen = Some(now);
+        if status.up {
+            return None;
+        }
+        status.up = true;
+        if status.down_since.take().is_some() {
+            status.reconnects += 1;
+            Some(Transition::Reconnected(peer))
+        } else {
+            Some(Transition::Up(peer))
+        }
+    }
+
+    /// Marks down every peer that has been silent for longer than the
+    /// timeout. Peers never heard from are left alone until they are.
+    pub fn check(&mut self, now: u128) -> Vec<Transition> {
+        let mut transitions = Vec::new();
+        for status in self.peers.values_mut() {
+            match status.last_seen {
+                Some(last_seen) if status.up && now.saturating_sub(last_seen) > self.timeout => {
+                    status.up = false;
+                    status.down_since = Some(now);
+                    transitions.push(Transition::Down(status.peer));
+                },
+                _ => {}
+            }
+        }
+        transitions.sort_by_key(|t| match t {
+            Transition::Up(peer) | Transition::Down(peer) | Transition::Reconnected(peer) => *peer,
+        });
+        transitions
+    }
+
+    pub fn is_up(&self, peer: Replica) -> bool {
+        self.peers.get(&peer).map(|s| s.up).unwrap_or(false)
+    }
+
+    /// Peers currently marked down or never heard from
+    pub fn down_peers(&self) -> Vec<Replica> {
+        let mut down: Vec<Replica> = self.peers.values()
+            .filter(|s| !s.up)
+            .map(|s| s.peer)
+            .collect();
+        down.sort();
+        down
+    }
+
+    /// Status of every peer, ordered by id
+    pub fn snapshot(&self) -> Vec<PeerStatus> {
+        let mut statuses: Vec<PeerStatus> = self.peers.values().cloned().collect();
+        statuses.sort_by_key(|s| s.peer);
+        statuses
+    }
+}
+
+impl std::fmt::Display for Transition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Transition::Up(peer) => write!(f, "peer {} is up", peer),
+            Transition::Down(peer) => write!(f, "peer {} is down", peer),
+            Transition::Reconnected(peer) => write!(f, "peer {} reconnected", peer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peers_go_down_and_count_reconnects() {
+        let mut tracker = LivenessTracker::new(vec![1, 2], 100);
+        assert_eq!(tracker.seen(1, 0), Some(Transition::Up(1)));
+        assert_eq!(tracker.seen(1, 50), None);
+        // Peer 2 was never heard from, so it is down but never transitions
+        assert_eq!(tracker.check(100), vec![]);
+        assert_eq!(tracker.down_peers(), vec![2]);
+        assert_eq!(tracker.check(151), vec![Transition::Down(1)]);
+        assert!(!tracker.is_up(1));
+        assert_eq!(tracker.seen(1, 200), Some(Transition::Reconnected(1)));
+        let status = &tracker.snapshot()[0];
+        assert_eq!((status.up, status.last_seen, status.reconnects), (true, Some(200), 1));
+        // Ids outside the tracked set are ignored
+        assert_eq!(tracker.seen(9, 200), None);
+    }
+}
@@ -0,0 +1,97 @@
+use std::net::SocketAddr;
+
+use config::{KeyMap, Node, Passphrase};
+use fnv::FnvHashMap;
+use types::{Membership, ProtocolId, Replica};
+
+/// What a node switches to at an epoch boundary: its config as reread
+/// from disk, checked against the membership the syncer announced
+#[derive(Debug, Clone)]
+pub struct Reconfiguration {
+    pub epoch: u64,
+    pub num_nodes: usize,
+    pub num_faults: usize,
+    /// Keys shared with every member
+    pub sec_key_map: KeyMap,
+    /// net_map without the revoked replicas
+    pub peers: FnvHashMap<Replica, SocketAddr>,
+}
+
+impl Reconfiguration {
+    /// Rereads the config of node `myid` from `path`, decrypting its secrets
+    /// with the passphrase from `passphrase` if they are encrypted
+    pub fn load(path: &str, passphrase: &Passphrase, myid: Replica, protocol: ProtocolId, membership: &Membership) -> Result<Reconfiguration, String> {
+        let config = Node::load_with(path, passphrase).map_err(|e| e.to_string())?;
+        Reconfiguration::from_config(&config, myid, protocol, membership)
+    }
+
+    pub fn from_config(config: &Node, myid: Replica, protocol: ProtocolId, membership: &Membership) -> Result<Reconfiguration, String> {
+        config.validate_for(protocol)
+            .map_err(|e| format!("invalid config for epoch {}: {}", membership.epoch, e))?;
+        if config.id != myid {
+            return Err(format!("the config is for node {}, not {}", config.id, myid));
+        }
+        let mut revoked = config.revoked.clone();
+        revoked.sort_unstable();
+        let mut announced = membership.revoked.clone();
+        announced.sort_unstable();
+        if (config.num_nodes, config.num_faults, &revoked) != (membership.num_nodes, membership.num_faults, &announced) {
+            return Err(format!("the config has n = {}, f = {} and revoked {:?}, but epoch {} has n = {}, f = {} and revoked {:?}",
+                config.num_nodes, config.num_faults, revoked,
+                membership.epoch, membership.num_nodes, membership.num_faults, announced));
+        }
+        let mut peers = FnvHashMap::default();
+        for (replica, address) in config.net_map.iter().filter(|(rep, _)| !config.is_revoked(**rep)) {
+            let address = config::resolve(address)
+                .map_err(|e| format!("unable to resolve the address {} of replica {}: {}", address, replica, e))?;
+            peers.insert(*replica, address);
+        }
+        Ok(Reconfiguration {
+            epoch: membership.epoch,
+            num_nodes: config.num_nodes,
+            num_faults: config.num_faults,
+            sec_key_map: config.key_map(),
+            peers,
+        })
+    }
+
+    /// The members other than `myid`
+    pub fn others(&self, myid: Replica) -> impl Iterator<Item = Replica> + '_ {
+        (0..self.num_nodes).filter(move |rep| *rep != myid && self.sec_key_map.contains_key(rep))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::Algorithm;
+
+    fn config(id: Replica, n: usize, f: usize, revoked: Vec<Replica>) -> Node {
+        let mut node = Node::new();
+        node.id = id;
+        node.num_nodes = n;
+        node.num_faults = f;
+        node.crypto_alg = Algorithm::NOPKI;
+        for j in 0..=n {
+            node.net_map.insert(j, format!("127.0.0.1:{}", 8500+j));
+        }
+        for j in (0..n).filter(|j| !revoked.contains(j)) {
+            node.sk_map.insert(j, vec![0; crypto::SECRET_KEY_SIZE]);
+        }
+        node.revoked = revoked;
+        node
+    }
+
+    #[test]
+    fn checks_the_config_against_the_epoch() {
+        let membership = Membership { epoch: 1, num_nodes: 5, num_faults: 1, revoked: vec![2] };
+        let reconf = Reconfiguration::from_config(&config(0, 5, 1, vec![2]), 0, ProtocolId::AppxCon, &membership).unwrap();
+        assert_eq!(reconf.others(0).collect::<Vec<_>>(), vec![1, 3, 4]);
+        // The syncer's entry stays, the revoked replica's goes
+        assert!(reconf.peers.contains_key(&5) && !reconf.peers.contains_key(&2));
+
+        // A stale config, from before node 4 joined
+        assert!(Reconfiguration::from_config(&config(0, 4, 1, vec![2]), 0, ProtocolId::AppxCon, &membership).is_err());
+        assert!(Reconfiguration::from_config(&config(1, 5, 1, vec![2]), 0, ProtocolId::AppxCon, &membership).is_err());
+    }
+}
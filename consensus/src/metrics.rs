@@ -0,0 +1,205 @@
+use std::fmt::Write as _;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::{Arc, RwLock};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use types::Replica;
+
+use crate::{Direction, PeerStatus, TrafficTotal};
+
+/// Largest request we read before answering
+const MAX_REQUEST: usize = 8192;
+
+/// A node's protocol state as served on the metrics endpoint. The node's
+/// event loop overwrites it periodically; the endpoint only reads it.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    pub node: Replica,
+    pub protocol: &'static str,
+    pub round: u64,
+    pub value: u64,
+    /// Stage or phase the node is in, `idle` before START
+    pub phase: String,
+    /// RBCs terminated in the current round
+    pub terminated_rbcs: usize,
+    /// Witnesses of the current round whose RBCs have all terminated here
+    pub witnesses_accepted: usize,
+    pub mac_failures: usize,
+    pub malformed_payloads: usize,
+    /// Frames dropped because they did not decode. The transport does not
+    /// say which peer sent them, so they are counted for the node as a whole
+    pub malformed_frames: usize,
+    /// Messages waiting in the network and syncer channels
+    pub net_queue: usize,
+    pub sync_queue: usize,
+    pub peers_up: usize,
+    pub peers_down: usize,
+    /// Liveness of every peer, so operators can see which ones are down
+    pub peers: Vec<PeerStatus>,
+    pub traffic: Vec<TrafficTotal>,
+}
+
+/// The metrics as shared between a node's event loop and the endpoint
+pub type SharedMetrics = Arc<RwLock<Metrics>>;
+
+impl Metrics {
+    pub fn new(node: Replica, protocol: &'static str) -> Metrics {
+        Metrics {
+            node,
+            protocol,
+            phase: "idle".to_string(),
+            ..Metrics::default()
+        }
+    }
+
+    pub fn shared(self) -> SharedMetrics {
+        Arc::new(RwLock::new(self))
+    }
+
+    /// The Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let labels = format!("node=\"{}\",protocol=\"{}\"", self.node, self.protocol);
+        let mut out = String::new();
+        let mut metric = |name: &str, help: &str, kind: &str, extra: &str, value: u64| {
+            let _ = writeln!(out, "# HELP sensorbft_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE sensorbft_{} {}", name, kind);
+            let _ = writeln!(out, "sensorbft_{}{{{}{}}} {}", name, labels, extra, value);
+        };
+        metric("round", "Current round", "gauge", "", self.round);
+        metric("value", "Current value", "gauge", "", self.value);
+        metric("phase", "Stage or phase the node is in", "gauge",
+            &format!(",phase=\"{}\"", escape(&self.phase)), 1);
+        metric("terminated_rbcs", "RBCs terminated in the current round", "gauge", "", self.terminated_rbcs as u64);
+        metric("witnesses_accepted", "Witnesses of the current round whose RBCs all terminated", "gauge", "", self.witnesses_accepted as u64);
+        metric("mac_failures_total", "Messages dropped for a bad MAC", "counter", "", self.mac_failures as u64);
+        metric("malformed_payloads_total", "Authenticated messages dropped as malformed", "counter", "", self.malformed_payloads as u64);
+        metric("malformed_frames_total", "Frames from any peer dropped because they did not decode", "counter", "", self.malformed_frames as u64);
+        metric("peers_up", "Peers heard from recently", "gauge", "", self.peers_up as u64);
+        metric("peers_down", "Peers silent for too long or never heard from", "gauge", "", self.peers_down as u64);
+        let _ = writeln!(out, "# HELP sensorbft_queue_depth Messages waiting to be processed");
+        let _ = writeln!(out, "# TYPE sensorbft_queue_depth gauge");
+        for (queue, depth) in [("net", self.net_queue), ("sync", self.sync_queue)] {
+            let _ = writeln!(out, "sensorbft_queue_depth{{{},queue=\"{}\"}} {}", labels, queue, depth);
+        }
+        for (name, help, kind) in [
+            ("peer_up", "Whether the peer was heard from recently", "gauge"),
+            ("peer_last_seen_ms", "Milliseconds since the epoch of the peer's last authenticated message", "gauge"),
+            ("peer_reconnects_total", "Times the peer came back after being marked down", "counter"),
+        ] {
+            let _ = writeln!(out, "# HELP sensorbft_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE sensorbft_{} {}", name, kind);
+            for status in self.peers.iter() {
+                let value = match name {
+                    "peer_up" => Some(status.up as u128),
+                    "peer_last_seen_ms" => status.last_seen,
+                    _ => Some(status.reconnects as u128),
+                };
+                // Peers never heard from have no last_seen sample
+                if let Some(value) = value {
+                    let _ = writeln!(out, "sensorbft_{}{{{},peer=\"{}\"}} {}", name, labels, status.peer, value);
+                }
+            }
+        }
+        for (name, help, field) in [
+            ("messages_total", "Messages sent and received", 0),
+            ("bytes_total", "Bytes sent and received", 1),
+        ] {
+            let _ = writeln!(out, "# HELP sensorbft_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE sensorbft_{} counter", name);
+            for total in self.traffic.iter() {
+                let direction = match total.direction {
+                    Direction::Sent => "out",
+                    Direction::Received => "in",
+                };
+                let value = if field == 0 { total.messages } else { total.bytes };
+                let _ = writeln!(out, "sensorbft_{}{{{},direction=\"{}\",phase=\"{}\",kind=\"{}\"}} {}",
+                    name, labels, direction, escape(&total.phase), escape(&total.kind), value);
+            }
+        }
+        out
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Serves `metrics` at `http://127.0.0.1:<port>/metrics`. Only listens on
+/// localhost; put a proxy in front to scrape from elsewhere.
+pub fn spawn_metrics_server(port: u16, metrics: SharedMetrics) -> io::Result<SocketAddr> {
+    let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port));
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let listener = TcpListener::from_std(listener)?;
+    let local_addr = listener.local_addr()?;
+    tracing::info!("Serving metrics on http://{}/metrics", local_addr);
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve(stream, metrics.clone()));
+                },
+                Err(e) => tracing::warn!("Metrics listener failed to accept: {}", e),
+            }
+        }
+    });
+    Ok(local_addr)
+}
+
+async fn serve(mut stream: TcpStream, metrics: SharedMetrics) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    // Only the request line matters; read until the end of the headers
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < MAX_REQUEST {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+    let request = String::from_utf8_lossy(&buf);
+    let mut parts = request.lines().next().unwrap_or("").split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = metrics.read().unwrap().render();
+            format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(), body)
+        },
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        tracing::debug!("Failed to answer a metrics request: {}", e);
+    }
+    let _ = stream.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_metrics_over_http() {
+        let mut metrics = Metrics::new(2, "appxcon");
+        metrics.round = 4;
+        metrics.traffic.push(TrafficTotal {
+            direction: Direction::Sent,
+            phase: "rbc".to_string(),
+            kind: "ECHO".to_string(),
+            messages: 12,
+            bytes: 300,
+        });
+        metrics.peers.push(PeerStatus { peer: 3, up: false, last_seen: Some(1000), down_since: Some(3000), reconnects: 1 });
+        let addr = spawn_metrics_server(0, metrics.shared()).unwrap();
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("sensorbft_round{node=\"2\",protocol=\"appxcon\"} 4\n"));
+        assert!(response.contains("sensorbft_peer_up{node=\"2\",protocol=\"appxcon\",peer=\"3\"} 0\n"));
+        assert!(response.contains("sensorbft_peer_last_seen_ms{node=\"2\",protocol=\"appxcon\",peer=\"3\"} 1000\n"));
+        assert!(response.contains("sensorbft_messages_total{node=\"2\",protocol=\"appxcon\",direction=\"out\",phase=\"rbc\",kind=\"ECHO\"} 12\n"));
+    }
+}
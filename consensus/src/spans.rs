@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+
+use fnv::FnvHashMap as HashMap;
+use tracing::Span;
+use types::Replica;
+
+/// Phase of the messages that belong to a BAA instance rather than an RBC
+const BAA_PHASE: &str = "baa";
+/// Heartbeats are not part of any round
+const LIVENESS_PHASE: &str = "liveness";
+/// How many rounds past the node's own a message may open spans for
+pub const SPAN_WINDOW: u64 = 2;
+
+/// The tracing spans of one node's run: a `node` span for the run, a `round`
+/// span per round under it, and under each round an `rbc` span per RBC
+/// originator and a `baa` span for the round's binary agreement instance.
+/// Events logged while handling a message carry the fields of every span
+/// above it, so a run can be filtered by node, round or originator.
+///
+/// Spans are created on first use and closed once the node is two rounds
+/// past them, or when the run is reset. Messages only open spans for rounds
+/// close to the node's own, so a peer cannot make the node keep spans for
+/// rounds it will never reach.
+#[derive(Debug)]
+pub struct ProtocolSpans {
+    node: Span,
+    rounds: BTreeMap<u64, Span>,
+    rbcs: HashMap<(u64, Replica), Span>,
+    baas: BTreeMap<u64, Span>,
+    /// The round the node was in at the last `advance`
+    current: u64,
+}
+
+impl ProtocolSpans {
+    pub fn new(protocol: &'static str, node: Replica, session: u64) -> ProtocolSpans {
+        ProtocolSpans {
+            node: tracing::info_span!("node", protocol, node, session),
+            rounds: BTreeMap::new(),
+            rbcs: HashMap::default(),
+            baas: BTreeMap::new(),
+            current: 0,
+        }
+    }
+
+    pub fn node(&self) -> Span {
+        self.node.clone()
+    }
+
+    pub fn round(&mut self, round: u64) -> Span {
+        let node = &self.node;
+        self.rounds.entry(round)
+            .or_insert_with(|| tracing::info_span!(parent: node, "round", round))
+            .clone()
+    }
+
+    /// The RBC `origin` started in `round`
+    pub fn rbc(&mut self, round: u64, origin: Replica) -> Span {
+        let parent = self.round(round);
+        self.rbcs.entry((round, origin))
+            .or_insert_with(|| tracing::info_span!(parent: &parent, "rbc", origin))
+            .clone()
+    }
+
+    /// The binary agreement instance of `round`
+    pub fn baa(&mut self, round: u64) -> Span {
+        let parent = self.round(round);
+        self.baas.entry(round)
+            .or_insert_with(|| tracing::info_span!(parent: &parent, "baa"))
+            .clone()
+    }
+
+    /// The span a message of `phase` belongs to; the node span for rounds
+    /// outside the window around the node's own
+    pub fn for_msg(&mut self, phase: &str, round: u64, origin: Option<Replica>) -> Span {
+        let in_window = round + 1 >= self.current && round <= self.current + SPAN_WINDOW;
+        match (phase, origin) {
+            (LIVENESS_PHASE, _) => self.node(),
+            _ if !in_window => self.node(),
+            (BAA_PHASE, _) => self.baa(round),
+            (_, Some(origin)) => self.rbc(round, origin),
+            (_, None) => self.round(round),
+        }
+    }
+
+    /// Closes the spans of rounds more than one behind `round`. Late
+    /// messages for those rounds get the node span from then on.
+    pub fn advance(&mut self, round: u64) {
+        self.current = round;
+        let keep = round.saturating_sub(1);
+        self.rounds = self.rounds.split_off(&keep);
+        self.baas = self.baas.split_off(&keep);
+        self.rbcs.retain(|(r, _), _| *r >= keep);
+    }
+
+    /// Closes every span and starts over in `session`
+    pub fn reset(&mut self, protocol: &'static str, node: Replica, session: u64) {
+        *self = ProtocolSpans::new(protocol, node, session);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_opens_spans_near_the_current_round() {
+        let mut spans = ProtocolSpans::new("appxcon", 0, 1);
+        spans.advance(5);
+        spans.for_msg("appx", 5, Some(1));
+        spans.for_msg("baa", 7, None);
+        spans.for_msg("appx", 1_000_000, Some(2));
+        spans.for_msg("appx", 3, Some(3));
+        assert_eq!(spans.rounds.keys().copied().collect::<Vec<u64>>(), vec![5, 7]);
+        assert_eq!(spans.rbcs.len(), 1);
+        assert_eq!(spans.baas.len(), 1);
+    }
+}
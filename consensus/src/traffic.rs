@@ -0,0 +1,197 @@
+use std::fs::File;
+use std::io::Write;
+
+use fnv::FnvHashMap as HashMap;
+use serde::{Serialize, Deserialize};
+use types::Replica;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Messages and bytes exchanged with one peer, for one message kind in one round
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficRecord {
+    pub peer: Replica,
+    pub direction: Direction,
+    pub phase: String,
+    pub kind: String,
+    pub round: u64,
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// Totals over all peers and rounds for one message kind
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficTotal {
+    pub direction: Direction,
+    pub phase: String,
+    pub kind: String,
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// What a node sends to the syncer once it completes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficReport {
+    pub node: Replica,
+    /// Session of the run the counters belong to
+    pub session: u64,
+    pub records: Vec<TrafficRecord>,
+}
+
+impl TrafficReport {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize traffic report")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<TrafficReport, bincode::Error> {
+        bincode::deserialize(bytes)
+    }
+
+    pub fn totals(&self) -> Vec<TrafficTotal> {
+        totals(self.records.iter())
+    }
+}
+
+/// Reports from all nodes, with totals across them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficSummary {
+    pub totals: Vec<TrafficTotal>,
+    pub reports: Vec<TrafficReport>,
+}
+
+impl TrafficSummary {
+    pub fn from_reports(mut reports: Vec<TrafficReport>) -> TrafficSummary {
+        reports.sort_by_key(|r| r.node);
+        // Every message is counted by both ends; the totals use the sending side
+        let totals = totals(reports.iter()
+            .flat_map(|r| r.records.iter())
+            .filter(|rec| rec.direction == Direction::Sent));
+        TrafficSummary {
+            totals,
+            reports,
+        }
+    }
+}
+
+type TrafficKey = (Replica, Direction, &'static str, &'static str, u64);
+
+/// Per-peer traffic counters kept by a node on its send and receive paths
+#[derive(Debug, Clone, Default)]
+pub struct TrafficStats {
+    // (peer, direction, phase, kind, round) -> (messages, bytes)
+    counts: HashMap<TrafficKey, (u64, u64)>,
+}
+
+impl TrafficStats {
+    pub fn new() -> TrafficStats {
+        TrafficStats::default()
+    }
+
+    pub fn record(
+        &mut self,
+        peer: Replica,
+        direction: Direction,
+        phase: &'static str,
+        kind: &'static str,
+        round: u64,
+        bytes: u64,
+    ) {
+        let entry = self.counts
+            .entry((peer, direction, phase, kind, round))
+            .or_default();
+        entry.0 += 1;
+        entry.1 += bytes;
+    }
+
+    /// All counters, sorted by peer, direction, round and kind
+    pub fn records(&self) -> Vec<TrafficRecord> {
+        let mut records: Vec<TrafficRecord> = self.counts.iter()
+            .map(|((peer, direction, phase, kind, round), (messages, bytes))| TrafficRecord {
+                peer: *peer,
+                direction: *direction,
+                phase: phase.to_string(),
+                kind: kind.to_string(),
+                round: *round,
+                messages: *messages,
+                bytes: *bytes,
+            })
+            .collect();
+        records.sort_by(|a, b| {
+            (a.peer, a.direction, a.round, &a.kind).cmp(&(b.peer, b.direction, b.round, &b.kind))
+        });
+        records
+    }
+
+    pub fn report(&self, node: Replica, session: u64) -> TrafficReport {
+        TrafficReport {
+            node,
+            session,
+            records: self.records(),
+        }
+    }
+
+    /// Logs the totals per phase and writes every counter to `path` as JSON
+    pub fn dump(&self, node: Replica, session: u64, path: &str) -> std::io::Result<()> {
+        let report = self.report(node, session);
+        for total in report.totals() {
+            tracing::info!("DP[Traffic]: {:?} {} {}: {} messages, {} bytes",
+                total.direction, total.phase, total.kind, total.messages, total.bytes);
+        }
+        write_json(path, &report)
+    }
+}
+
+/// Sums records over peers and rounds, keeping direction, phase and kind apart
+pub fn totals<'a>(records: impl Iterator<Item = &'a TrafficRecord>) -> Vec<TrafficTotal> {
+    let mut sums: HashMap<(Direction, &str, &str), (u64, u64)> = HashMap::default();
+    for rec in records {
+        let entry = sums
+            .entry((rec.direction, rec.phase.as_str(), rec.kind.as_str()))
+            .or_default();
+        entry.0 += rec.messages;
+        entry.1 += rec.bytes;
+    }
+    let mut totals: Vec<TrafficTotal> = sums.into_iter()
+        .map(|((direction, phase, kind), (messages, bytes))| TrafficTotal {
+            direction,
+            phase: phase.to_string(),
+            kind: kind.to_string(),
+            messages,
+            bytes,
+        })
+        .collect();
+    totals.sort_by(|a, b| (a.direction, &a.phase, &a.kind).cmp(&(b.direction, &b.phase, &b.kind)));
+    totals
+}
+
+pub fn write_json(path: &str, obj: &impl Serialize) -> std::io::Result<()> {
+    let bytes = serde_json::to_vec_pretty(obj)
+        .map_err(std::io::Error::other)?;
+    File::create(path)?.write_all(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totals_sum_over_peers_and_rounds() {
+        let mut stats = TrafficStats::new();
+        stats.record(1, Direction::Sent, "rbc", "ECHO", 0, 100);
+        stats.record(2, Direction::Sent, "rbc", "ECHO", 1, 50);
+        stats.record(1, Direction::Received, "rbc", "ECHO", 0, 70);
+        stats.record(1, Direction::Sent, "baa", "BinaryAAEcho", 3, 10);
+        let report = stats.report(0, 0);
+        assert_eq!(report.records.len(), 4);
+        let totals = report.totals();
+        let echo_sent = totals.iter()
+            .find(|t| t.direction == Direction::Sent && t.kind == "ECHO")
+            .unwrap();
+        assert_eq!((echo_sent.messages, echo_sent.bytes), (2, 150));
+        assert_eq!(totals.len(), 3);
+    }
+}
@@ -0,0 +1,244 @@
+use serde::{Serialize, Deserialize};
+use types::Replica;
+
+/// How long (in milliseconds) a round may go without progress before the
+/// watchdog fires, unless the config says otherwise
+pub const STALL_TIMEOUT_MS: u64 = 10_000;
+
+/// What the node has to show for the current round. Any change counts as
+/// progress: a new round, or one more vote, termination or witness in it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub round: u64,
+    pub steps: usize,
+}
+
+/// Fires once when the progress of a run stops changing for `timeout`
+/// milliseconds, and again only after the run moves on and stalls anew.
+/// Only armed between START and completion.
+#[derive(Debug, Clone)]
+pub struct ProgressWatchdog {
+    /// Zero turns the watchdog off
+    pub timeout: u128,
+    armed: bool,
+    fired: bool,
+    last: Progress,
+    since: u128,
+}
+
+impl ProgressWatchdog {
+    pub fn new(timeout_ms: u64) -> ProgressWatchdog {
+        ProgressWatchdog {
+            timeout: timeout_ms as u128,
+            armed: false,
+            fired: false,
+            last: Progress::default(),
+            since: 0,
+        }
+    }
+
+    pub fn arm(&mut self, now: u128) {
+        self.armed = self.timeout > 0;
+        self.fired = false;
+        self.last = Progress::default();
+        self.since = now;
+    }
+
+    pub fn disarm(&mut self) {
+        self.armed = false;
+    }
+
+    /// Returns how long the run has been stuck at `progress` if that just
+    /// crossed the timeout
+    pub fn check(&mut self, progress: Progress, now: u128) -> Option<u128> {
+        if !self.armed {
+            return None;
+        }
+        if progress != self.last {
+            self.last = progress;
+            self.since = now;
+            self.fired = false;
+            return None;
+        }
+        let stalled = now.saturating_sub(self.since);
+        if self.fired || stalled < self.timeout {
+            return None;
+        }
+        self.fired = true;
+        Some(stalled)
+    }
+}
+
+/// An RBC of the stuck round that has not terminated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RbcProgress {
+    pub origin: Replica,
+    /// Whether the originator's RBCInit arrived
+    pub init: bool,
+    pub echoes: usize,
+    pub readys: usize,
+    pub missing_echoes: Vec<Replica>,
+    pub missing_readys: Vec<Replica>,
+}
+
+/// A witness whose RBCs have not all terminated here
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WitnessProgress {
+    pub sender: Replica,
+    pub unterminated: Vec<Replica>,
+}
+
+/// One candidate value of a BAA instance that has not terminated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BaaProgress {
+    pub round: u64,
+    pub instance: Replica,
+    pub value: u64,
+    pub echoes: usize,
+    pub echo2s: usize,
+    pub missing_echo2s: Vec<Replica>,
+}
+
+/// Where a stuck round is waiting, dumped when the watchdog fires and sent
+/// to the syncer with `SyncState::Stalled`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StallReport {
+    pub node: Replica,
+    pub session: u64,
+    pub round: u64,
+    pub stalled_ms: u128,
+    pub num_nodes: usize,
+    /// Votes an RBC or BAA instance needs, n-f
+    pub quorum: usize,
+    pub rbcs: Vec<RbcProgress>,
+    pub witnesses: Vec<WitnessProgress>,
+    /// Nodes no witness has arrived from
+    pub missing_witnesses: Vec<Replica>,
+    pub baa: Vec<BaaProgress>,
+}
+
+impl StallReport {
+    pub fn new(node: Replica, session: u64, round: u64, stalled_ms: u128, num_nodes: usize, num_faults: usize) -> StallReport {
+        StallReport {
+            node,
+            session,
+            round,
+            stalled_ms,
+            num_nodes,
+            quorum: num_nodes - num_faults,
+            rbcs: Vec::new(),
+            witnesses: Vec::new(),
+            missing_witnesses: Vec::new(),
+            baa: Vec::new(),
+        }
+    }
+
+    /// The nodes in `0..num_nodes` that are not in `heard`
+    pub fn missing<'a>(&self, heard: impl IntoIterator<Item = &'a Replica>) -> Vec<Replica> {
+        let mut heard_from = vec![false; self.num_nodes];
+        for rep in heard {
+            if let Some(h) = heard_from.get_mut(*rep) {
+                *h = true;
+            }
+        }
+        (0..self.num_nodes).filter(|rep| !heard_from[*rep]).collect()
+    }
+
+    pub fn add_rbc<'a>(
+        &mut self,
+        origin: Replica,
+        init: bool,
+        echoes: impl IntoIterator<Item = &'a Replica>,
+        readys: impl IntoIterator<Item = &'a Replica>,
+    ) {
+        let missing_echoes = self.missing(echoes);
+        let missing_readys = self.missing(readys);
+        self.rbcs.push(RbcProgress {
+            origin,
+            init,
+            echoes: self.num_nodes - missing_echoes.len(),
+            readys: self.num_nodes - missing_readys.len(),
+            missing_echoes,
+            missing_readys,
+        });
+    }
+
+    pub fn add_baa<'a>(
+        &mut self,
+        round: u64,
+        instance: Replica,
+        value: u64,
+        echoes: usize,
+        echo2s: impl IntoIterator<Item = &'a Replica>,
+    ) {
+        let missing_echo2s = self.missing(echo2s);
+        self.baa.push(BaaProgress {
+            round,
+            instance,
+            value,
+            echoes,
+            echo2s: self.num_nodes - missing_echo2s.len(),
+            missing_echo2s,
+        });
+    }
+
+    /// Sorts the entries so reports of different nodes line up
+    pub fn sort(&mut self) {
+        self.rbcs.sort_by_key(|r| r.origin);
+        self.witnesses.sort_by_key(|w| w.sender);
+        self.baa.sort_by_key(|b| (b.round, b.instance, b.value));
+    }
+
+    pub fn log(&self) {
+        tracing::warn!(round = self.round, stalled_ms = self.stalled_ms as u64,
+            "Round {} made no progress for {} ms", self.round, self.stalled_ms);
+        for rbc in self.rbcs.iter() {
+            tracing::warn!(origin = rbc.origin,
+                "Stalled RBC of node {}: init {}, echoes {}/{} missing {:?}, readys {}/{} missing {:?}",
+                rbc.origin, rbc.init, rbc.echoes, self.quorum, rbc.missing_echoes,
+                rbc.readys, self.quorum, rbc.missing_readys);
+        }
+        for witness in self.witnesses.iter() {
+            tracing::warn!("Unverified witness from node {}: RBCs {:?} not terminated",
+                witness.sender, witness.unterminated);
+        }
+        if !self.missing_witnesses.is_empty() {
+            tracing::warn!("No witness from nodes {:?}", self.missing_witnesses);
+        }
+        for baa in self.baa.iter() {
+            tracing::warn!("Open BAA instance {} of round {} on value {}: echoes {}, echo2s {}/{} missing {:?}",
+                baa.instance, baa.round, baa.value, baa.echoes, baa.echo2s, self.quorum, baa.missing_echo2s);
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize stall report")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<StallReport, bincode::Error> {
+        bincode::deserialize(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_once_per_stall() {
+        let mut watchdog = ProgressWatchdog::new(100);
+        let progress = Progress { round: 1, steps: 3 };
+        assert_eq!(watchdog.check(progress, 0), None);
+        watchdog.arm(0);
+        assert_eq!(watchdog.check(progress, 50), None);
+        assert_eq!(watchdog.check(progress, 120), None);
+        assert_eq!(watchdog.check(progress, 150), Some(100));
+        assert_eq!(watchdog.check(progress, 400), None);
+        // Moving on re-arms it
+        let progress = Progress { round: 1, steps: 4 };
+        assert_eq!(watchdog.check(progress, 410), None);
+        assert_eq!(watchdog.check(progress, 510), Some(100));
+        watchdog.disarm();
+        assert_eq!(watchdog.check(Progress::default(), 1000), None);
+    }
+}
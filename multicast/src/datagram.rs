@@ -0,0 +1,172 @@
+use config::KeyMap;
+use crypto::hash::{Hash, do_mac, verf_mac};
+use serde::{Serialize, Deserialize};
+use types::{Replica, EnvelopeHeader, WireError, appxcon::{ProtMsg, WrapperMsg}};
+
+/// Bytes a fragment needs besides its chunk: enum tag, sender, sequence
+/// number, index, count and the chunk length
+pub const FRAGMENT_OVERHEAD: usize = 4 + 8 + 8 + 2 + 2 + 8;
+
+/// A protocol message as multicast by `sender`, with a MAC for every receiver
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McastMsg {
+    pub header: EnvelopeHeader,
+    pub protmsg: ProtMsg,
+    pub sender: Replica,
+    pub macs: Vec<(Replica, Hash)>,
+}
+
+impl McastMsg {
+    /// MACs the message for every replica in `keys` other than the sender
+    pub fn new(header: EnvelopeHeader, protmsg: ProtMsg, sender: Replica, keys: &KeyMap) -> McastMsg {
+        let bytes = WrapperMsg::mac_bytes(&header, &protmsg);
+        let mut macs: Vec<(Replica, Hash)> = keys.iter()
+            .filter(|(rep, _)| **rep != sender)
+            .map(|(rep, sk)| (*rep, do_mac(&bytes, sk)))
+            .collect();
+        macs.sort_by_key(|(rep, _)| *rep);
+        McastMsg {
+            header,
+            protmsg,
+            sender,
+            macs,
+        }
+    }
+
+    /// Checks the MAC for `receiver` under the key it shares with the sender
+    pub fn verify(&self, receiver: Replica, sk: &[u8]) -> bool {
+        match self.macs.iter().find(|(rep, _)| *rep == receiver) {
+            Some((_, mac)) => verf_mac(&WrapperMsg::mac_bytes(&self.header, &self.protmsg), sk, mac),
+            None => false,
+        }
+    }
+
+    /// The message `receiver` would have got over TCP, if the sender
+    /// included a MAC for it
+    pub fn into_wrapper(self, receiver: Replica) -> Option<WrapperMsg> {
+        let mac = self.macs.iter().find(|(rep, _)| *rep == receiver)?.1;
+        Some(WrapperMsg {
+            header: self.header,
+            protmsg: self.protmsg,
+            sender: self.sender,
+            mac,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize multicast message")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<McastMsg, WireError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Datagram {
+    /// Chunk `index` of `count` of the serialized `McastMsg` number `seq`
+    /// from `sender`. Fragments are not authenticated on their own; the
+    /// reassembled message is.
+    Fragment {
+        sender: Replica,
+        seq: u64,
+        index: u16,
+        count: u16,
+        chunk: Vec<u8>,
+    },
+    /// `sender` has multicast every message below `next_seq`, and can
+    /// still resend those from `first_seq` on
+    Announce {
+        sender: Replica,
+        first_seq: u64,
+        next_seq: u64,
+        macs: Vec<(Replica, Hash)>,
+    },
+    /// `sender` asks `target` to multicast the listed messages again
+    Nack {
+        sender: Replica,
+        target: Replica,
+        seqs: Vec<u64>,
+        mac: Hash,
+    },
+}
+
+impl Datagram {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize datagram")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Datagram, WireError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    pub fn sender(&self) -> Replica {
+        match self {
+            Datagram::Fragment { sender, .. } => *sender,
+            Datagram::Announce { sender, .. } => *sender,
+            Datagram::Nack { sender, .. } => *sender,
+        }
+    }
+
+    pub fn announce(sender: Replica, first_seq: u64, next_seq: u64, keys: &KeyMap) -> Datagram {
+        let bytes = announce_bytes(sender, first_seq, next_seq);
+        let mut macs: Vec<(Replica, Hash)> = keys.iter()
+            .filter(|(rep, _)| **rep != sender)
+            .map(|(rep, sk)| (*rep, do_mac(&bytes, sk)))
+            .collect();
+        macs.sort_by_key(|(rep, _)| *rep);
+        Datagram::Announce { sender, first_seq, next_seq, macs }
+    }
+
+    pub fn nack(sender: Replica, target: Replica, seqs: Vec<u64>, sk: &[u8]) -> Datagram {
+        let mac = do_mac(&nack_bytes(sender, target, &seqs), sk);
+        Datagram::Nack { sender, target, seqs, mac }
+    }
+
+    /// Checks the MAC of an announcement or NACK addressed to `receiver`
+    /// using the key `receiver` shares with the claimed sender. Fragments
+    /// always pass; the transport checks the reassembled `McastMsg` instead.
+    pub fn verify(&self, receiver: Replica, sk: &[u8]) -> bool {
+        match self {
+            Datagram::Fragment { .. } => true,
+            Datagram::Announce { sender, first_seq, next_seq, macs } => {
+                match macs.iter().find(|(rep, _)| *rep == receiver) {
+                    Some((_, mac)) => verf_mac(&announce_bytes(*sender, *first_seq, *next_seq), sk, mac),
+                    None => false,
+                }
+            },
+            Datagram::Nack { sender, target, seqs, mac } => {
+                verf_mac(&nack_bytes(*sender, *target, seqs), sk, mac)
+            },
+        }
+    }
+}
+
+fn announce_bytes(sender: Replica, first_seq: u64, next_seq: u64) -> Vec<u8> {
+    bincode::serialize(&("announce", sender, first_seq, next_seq)).expect("Failed to serialize announcement")
+}
+
+fn nack_bytes(sender: Replica, target: Replica, seqs: &Vec<u64>) -> Vec<u8> {
+    bincode::serialize(&("nack", sender, target, seqs)).expect("Failed to serialize NACK")
+}
+
+/// Splits a serialized message into fragment datagrams of at most `mtu` bytes
+pub fn fragment(sender: Replica, seq: u64, bytes: &[u8], mtu: usize) -> Vec<Vec<u8>> {
+    let chunk_size = mtu - FRAGMENT_OVERHEAD;
+    let chunks: Vec<&[u8]> = if bytes.is_empty() {
+        vec![bytes]
+    } else {
+        bytes.chunks(chunk_size).collect()
+    };
+    let count = chunks.len() as u16;
+    chunks.into_iter()
+        .enumerate()
+        .map(|(index, chunk)| Datagram::Fragment {
+            sender,
+            seq,
+            index: index as u16,
+            count,
+            chunk: chunk.to_vec(),
+        }.to_bytes())
+        .collect()
+}
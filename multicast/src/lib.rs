@@ -0,0 +1,22 @@
+//! Broadcast over authenticated UDP multicast.
+//!
+//! A broadcast goes out once, carrying one MAC per receiver, each under the
+//! key that receiver shares with the sender. Receivers pick out their MAC and
+//! hand the consensus layer the same `WrapperMsg` a TCP send would have
+//! delivered, so MAC checks and everything after them stay unchanged.
+//!
+//! Datagrams are unreliable. Every broadcast gets a per-sender sequence
+//! number and is split into fragments that fit the configured MTU. Receivers
+//! request missing sequence numbers with NACKs, and senders periodically
+//! announce how far they got so losses at the tail are noticed too, and
+//! from where they can still resend. A receiver starts a sender's stream at
+//! its first announcement.
+
+mod datagram;
+pub use datagram::*;
+
+mod reassembly;
+pub use reassembly::*;
+
+mod transport;
+pub use transport::*;
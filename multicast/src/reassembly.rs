@@ -0,0 +1,270 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use fnv::FnvHashMap as HashMap;
+
+/// Largest number of fragments one message may be split into
+pub const MAX_FRAGMENTS: u16 = 4096;
+/// How far past the oldest undelivered message of a peer we accept
+/// fragments. Bounds the sequence numbers a peer can make us track.
+pub const RECEIVE_WINDOW: u64 = 4096;
+/// Most sequence numbers requested in a single NACK
+pub const MAX_NACK_SEQS: usize = 128;
+/// Most messages of one peer we reassemble at a time
+pub const MAX_PARTIALS: usize = 256;
+/// Most bytes of unfinished messages we buffer for one peer. Fragments are
+/// not authenticated, so this is what anyone on the LAN can make us spend
+/// per replica id; it also caps the size of a multicast message.
+pub const MAX_PARTIAL_BYTES: usize = 16 << 20;
+
+struct Partial {
+    count: u16,
+    chunks: BTreeMap<u16, Vec<u8>>,
+    bytes: usize,
+    /// Order in which the partial messages were started
+    arrival: u64,
+}
+
+/// Receive state for the multicast stream of one peer
+pub struct PeerStream {
+    /// Largest chunk a fragment may carry
+    max_chunk: usize,
+    /// Set by the first authenticated announcement; until then we do not
+    /// know where the peer's stream starts
+    started: bool,
+    /// Every message below this has been delivered or can no longer be
+    /// recovered
+    delivered_below: u64,
+    /// Messages at or above `delivered_below` that have been delivered
+    delivered: BTreeSet<u64>,
+    /// One past the highest sequence number we know the peer has sent
+    next_seq: u64,
+    partial: HashMap<u64, Partial>,
+    /// Bytes held in `partial`
+    buffered: usize,
+    arrivals: u64,
+}
+
+impl PeerStream {
+    pub fn new(max_chunk: usize) -> PeerStream {
+        PeerStream {
+            max_chunk,
+            started: false,
+            delivered_below: 0,
+            delivered: BTreeSet::new(),
+            next_seq: 0,
+            partial: HashMap::default(),
+            buffered: 0,
+            arrivals: 0,
+        }
+    }
+
+    fn is_delivered(&self, seq: u64) -> bool {
+        seq < self.delivered_below || self.delivered.contains(&seq)
+    }
+
+    fn drop_partial(&mut self, seq: u64) -> Option<Partial> {
+        let partial = self.partial.remove(&seq)?;
+        self.buffered -= partial.bytes;
+        Some(partial)
+    }
+
+    /// Makes room for `bytes` more by dropping the partial messages started
+    /// longest ago, other than `keep`
+    fn evict(&mut self, keep: u64, bytes: usize) {
+        while self.partial.len() > MAX_PARTIALS || self.buffered + bytes > MAX_PARTIAL_BYTES {
+            let oldest = self.partial.iter()
+                .filter(|(seq, _)| **seq != keep)
+                .min_by_key(|(_, p)| p.arrival)
+                .map(|(seq, _)| *seq);
+            match oldest {
+                Some(seq) => {
+                    self.drop_partial(seq);
+                },
+                None => break,
+            }
+        }
+    }
+
+    /// Adds a fragment and returns the whole message once its last fragment
+    /// arrives. Fragments of delivered messages, too far ahead, too large,
+    /// or from a peer we have no announcement of yet, are dropped.
+    pub fn on_fragment(&mut self, seq: u64, index: u16, count: u16, chunk: Vec<u8>) -> Option<Vec<u8>> {
+        if !self.started || self.is_delivered(seq) || seq >= self.delivered_below + RECEIVE_WINDOW {
+            return None;
+        }
+        if count == 0 || count > MAX_FRAGMENTS || index >= count || chunk.len() > self.max_chunk {
+            return None;
+        }
+        self.next_seq = self.next_seq.max(seq + 1);
+        if !self.partial.contains_key(&seq) {
+            self.arrivals += 1;
+            self.partial.insert(seq, Partial {
+                count,
+                chunks: BTreeMap::new(),
+                bytes: 0,
+                arrival: self.arrivals,
+            });
+            self.evict(seq, 0);
+        }
+        let partial = &self.partial[&seq];
+        if partial.count != count {
+            // Fragments disagree on the count; someone is injecting them.
+            // Start over and let a NACK fetch the message again.
+            self.drop_partial(seq);
+            return None;
+        }
+        match partial.chunks.get(&index) {
+            Some(have) if *have != chunk => {
+                // Two different chunks for one slot; same as above
+                self.drop_partial(seq);
+                return None;
+            },
+            Some(_) => return None,
+            None => {},
+        }
+        self.evict(seq, chunk.len());
+        if self.buffered + chunk.len() > MAX_PARTIAL_BYTES {
+            // Too large to reassemble even on its own
+            self.drop_partial(seq);
+            return None;
+        }
+        self.buffered += chunk.len();
+        let partial = self.partial.get_mut(&seq)?;
+        partial.bytes += chunk.len();
+        partial.chunks.insert(index, chunk);
+        if partial.chunks.len() < count as usize {
+            return None;
+        }
+        let partial = self.drop_partial(seq)?;
+        Some(partial.chunks.into_values().flatten().collect())
+    }
+
+    /// The reassembled message was delivered
+    pub fn delivered(&mut self, seq: u64) {
+        self.drop_partial(seq);
+        self.delivered.insert(seq);
+        self.advance();
+    }
+
+    fn advance(&mut self) {
+        while self.delivered.remove(&self.delivered_below) {
+            self.delivered_below += 1;
+        }
+    }
+
+    /// The peer has sent everything below `next_seq` and can still resend
+    /// everything from `first_seq` on. The first announcement starts the
+    /// stream at `first_seq`; later ones skip what the peer no longer has.
+    /// Returns the number of messages given up on.
+    pub fn on_announce(&mut self, first_seq: u64, next_seq: u64) -> u64 {
+        if first_seq > next_seq {
+            return 0;
+        }
+        let mut skipped = 0;
+        if !self.started {
+            self.started = true;
+            self.delivered_below = first_seq;
+        } else if first_seq > self.delivered_below {
+            skipped = (self.delivered_below..first_seq)
+                .filter(|seq| !self.delivered.contains(seq))
+                .count() as u64;
+            self.delivered_below = first_seq;
+            self.delivered = self.delivered.split_off(&first_seq);
+            let lost: Vec<u64> = self.partial.keys().copied().filter(|seq| *seq < first_seq).collect();
+            for seq in lost {
+                self.drop_partial(seq);
+            }
+            self.advance();
+        }
+        let next_seq = next_seq.min(self.delivered_below + RECEIVE_WINDOW);
+        self.next_seq = self.next_seq.max(next_seq);
+        skipped
+    }
+
+    /// Sequence numbers the peer sent that we have not delivered yet
+    pub fn missing(&self) -> Vec<u64> {
+        (self.delivered_below..self.next_seq)
+            .filter(|seq| !self.delivered.contains(seq))
+            .take(MAX_NACK_SEQS)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started() -> PeerStream {
+        let mut stream = PeerStream::new(64);
+        stream.on_announce(0, 0);
+        stream
+    }
+
+    #[test]
+    fn reassembles_out_of_order_and_reports_gaps() {
+        let mut stream = started();
+        // Message 1 arrives in reverse order, message 0 not at all
+        assert_eq!(stream.on_fragment(1, 1, 2, vec![3, 4]), None);
+        assert_eq!(stream.on_fragment(1, 1, 2, vec![3, 4]), None);
+        assert_eq!(stream.on_fragment(1, 0, 2, vec![1, 2]), Some(vec![1, 2, 3, 4]));
+        stream.delivered(1);
+        assert_eq!(stream.missing(), vec![0]);
+        // A late announcement reveals message 2 was lost too
+        stream.on_announce(0, 3);
+        assert_eq!(stream.missing(), vec![0, 2]);
+        assert_eq!(stream.on_fragment(0, 0, 1, vec![9]), Some(vec![9]));
+        stream.delivered(0);
+        // Retransmissions of delivered messages are ignored
+        assert_eq!(stream.on_fragment(1, 0, 1, vec![1]), None);
+        assert_eq!(stream.missing(), vec![2]);
+    }
+
+    #[test]
+    fn rejects_inconsistent_and_out_of_window_fragments() {
+        let mut stream = PeerStream::new(64);
+        // Nothing is accepted before the first announcement
+        assert_eq!(stream.on_fragment(0, 0, 1, vec![1]), None);
+        stream.on_announce(0, 0);
+        assert_eq!(stream.on_fragment(RECEIVE_WINDOW, 0, 1, vec![1]), None);
+        assert_eq!(stream.on_fragment(0, 2, 2, vec![1]), None);
+        assert_eq!(stream.on_fragment(0, 0, 3, vec![1]), None);
+        assert_eq!(stream.on_fragment(0, 0, 1, vec![0; 65]), None);
+        // Disagrees on the count, so the partial message is thrown away
+        assert_eq!(stream.on_fragment(0, 1, 2, vec![2]), None);
+        assert_eq!(stream.on_fragment(0, 0, 2, vec![1]), None);
+        assert_eq!(stream.on_fragment(0, 1, 2, vec![2]), Some(vec![1, 2]));
+        assert_eq!(stream.missing(), vec![0]);
+        // A conflicting chunk throws the partial message away as well
+        assert_eq!(stream.on_fragment(1, 0, 2, vec![1]), None);
+        assert_eq!(stream.on_fragment(1, 0, 2, vec![7]), None);
+        assert_eq!(stream.on_fragment(1, 1, 2, vec![2]), None);
+        assert_eq!(stream.on_fragment(1, 0, 2, vec![1]), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn skips_what_the_peer_no_longer_has() {
+        // We join late and start where the peer's buffer does
+        let mut stream = PeerStream::new(64);
+        assert_eq!(stream.on_announce(10_000, 10_005), 0);
+        assert_eq!(stream.missing(), (10_000..10_005).collect::<Vec<u64>>());
+        assert_eq!(stream.on_fragment(10_001, 0, 1, vec![1]), Some(vec![1]));
+        stream.delivered(10_001);
+        assert_eq!(stream.on_fragment(10_003, 0, 2, vec![1]), None);
+        // We fell behind; 10_000 and 10_002 are gone, 10_003 is still there
+        assert_eq!(stream.on_announce(10_003, 10_005), 2);
+        assert_eq!(stream.missing(), vec![10_003, 10_004]);
+        assert_eq!(stream.on_fragment(10_003, 1, 2, vec![2]), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn evicts_the_oldest_partial_when_full() {
+        let mut stream = started();
+        for seq in 0..=MAX_PARTIALS as u64 {
+            assert_eq!(stream.on_fragment(seq, 0, 2, vec![seq as u8]), None);
+        }
+        assert_eq!(stream.partial.len(), MAX_PARTIALS);
+        assert!(!stream.partial.contains_key(&0));
+        assert_eq!(stream.buffered, MAX_PARTIALS);
+        assert_eq!(stream.on_fragment(1, 1, 2, vec![0]), Some(vec![1, 0]));
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+
+use config::{KeyMap, MulticastConfig};
+use fnv::FnvHashMap as HashMap;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use types::{Replica, EnvelopeHeader, appxcon::{ProtMsg, WrapperMsg}};
+
+use crate::{Datagram, McastMsg, PeerStream, FRAGMENT_OVERHEAD, MAX_FRAGMENTS, MAX_NACK_SEQS, MAX_PARTIAL_BYTES, fragment};
+
+/// Number of our own messages kept around to answer NACKs. Announcements
+/// tell receivers which is the oldest, so they stop asking for older ones.
+pub const RETRANSMIT_BUFFER: usize = 4096;
+
+/// Handle the consensus layer broadcasts through
+#[derive(Debug, Clone)]
+pub struct MulticastSender {
+    tx: UnboundedSender<(EnvelopeHeader, ProtMsg)>,
+}
+
+impl MulticastSender {
+    pub fn broadcast(&self, header: EnvelopeHeader, protmsg: ProtMsg) {
+        if self.tx.send((header, protmsg)).is_err() {
+            log::error!("Multicast transport has shut down, dropping broadcast");
+        }
+    }
+}
+
+pub struct MulticastTransport {
+    myid: Replica,
+    /// Keys shared with every other replica
+    keys: KeyMap,
+    config: MulticastConfig,
+    socket: UdpSocket,
+    deliver: UnboundedSender<WrapperMsg>,
+    outgoing: UnboundedReceiver<(EnvelopeHeader, ProtMsg)>,
+    /// Sequence number of our next broadcast
+    next_seq: u64,
+    /// Fragments of our recent broadcasts, by sequence number
+    sent: BTreeMap<u64, Vec<Vec<u8>>>,
+    streams: HashMap<Replica, PeerStream>,
+}
+
+impl MulticastTransport {
+    /// Joins the multicast group and starts the transport. Messages from
+    /// other replicas are handed to `deliver`, the same channel the TCP
+    /// receiver feeds.
+    pub fn spawn(
+        config: MulticastConfig,
+        myid: Replica,
+        keys: KeyMap,
+        deliver: UnboundedSender<WrapperMsg>,
+    ) -> io::Result<MulticastSender> {
+        let socket = bind(&config)?;
+        let (tx, rx) = unbounded_channel();
+        log::info!("Node {} multicasting on group {}", myid, config.group);
+        let transport = MulticastTransport {
+            myid,
+            keys,
+            config,
+            socket,
+            deliver,
+            outgoing: rx,
+            next_seq: 0,
+            sent: BTreeMap::new(),
+            streams: HashMap::default(),
+        };
+        tokio::spawn(transport.run());
+        Ok(MulticastSender { tx })
+    }
+
+    async fn run(mut self) {
+        let mut buf = vec![0u8; u16::MAX as usize];
+        let mut ticker = tokio::time::interval(Duration::from_millis(self.config.nack_interval_ms));
+        loop {
+            tokio::select! {
+                out = self.outgoing.recv() => {
+                    match out {
+                        Some((header, protmsg)) => self.multicast(header, protmsg).await,
+                        None => {
+                            log::info!("Multicast sender dropped, stopping the transport");
+                            break
+                        }
+                    }
+                },
+                res = self.socket.recv_from(&mut buf) => {
+                    match res {
+                        Ok((len, _from)) => self.on_datagram(&buf[..len]).await,
+                        Err(e) => log::warn!("Multicast receive failed: {}", e),
+                    }
+                },
+                _ = ticker.tick() => self.on_tick().await,
+            }
+        }
+    }
+
+    async fn send(&self, bytes: &[u8]) {
+        if let Err(e) = self.socket.send_to(bytes, SocketAddr::V4(self.config.group)).await {
+            log::warn!("Multicast send failed: {}", e);
+        }
+    }
+
+    async fn multicast(&mut self, header: EnvelopeHeader, protmsg: ProtMsg) {
+        let bytes = McastMsg::new(header, protmsg, self.myid, &self.keys).to_bytes();
+        let seq = self.next_seq;
+        let frags = fragment(self.myid, seq, &bytes, self.config.mtu);
+        if frags.len() > MAX_FRAGMENTS as usize || bytes.len() > MAX_PARTIAL_BYTES {
+            log::error!("Message of {} bytes is too large to multicast, dropping it", bytes.len());
+            return;
+        }
+        self.next_seq += 1;
+        for frag in frags.iter() {
+            self.send(frag).await;
+        }
+        self.sent.insert(seq, frags);
+        while self.sent.len() > RETRANSMIT_BUFFER {
+            self.sent.pop_first();
+        }
+    }
+
+    async fn on_datagram(&mut self, bytes: &[u8]) {
+        let datagram = match Datagram::from_bytes(bytes) {
+            Ok(datagram) => datagram,
+            Err(e) => {
+                log::debug!("Dropping undecodable multicast datagram: {}", e);
+                return;
+            }
+        };
+        let sender = datagram.sender();
+        if sender == self.myid {
+            // Our own datagrams loop back to us
+            return;
+        }
+        let sk = match self.keys.get(&sender) {
+            Some(sk) => sk,
+            None => {
+                log::debug!("Dropping multicast datagram from unknown sender {}", sender);
+                return;
+            }
+        };
+        if !datagram.verify(self.myid, sk) {
+            log::warn!("Dropping multicast datagram with a bad MAC claiming to be from {}", sender);
+            return;
+        }
+        match datagram {
+            Datagram::Fragment { sender, seq, index, count, chunk } => {
+                let max_chunk = self.config.mtu - FRAGMENT_OVERHEAD;
+                let stream = self.streams.entry(sender).or_insert_with(|| PeerStream::new(max_chunk));
+                let msg_bytes = match stream.on_fragment(seq, index, count, chunk) {
+                    Some(msg_bytes) => msg_bytes,
+                    None => return,
+                };
+                match McastMsg::from_bytes(&msg_bytes) {
+                    // Only a message that verifies may mark the sequence
+                    // number delivered, or a forged one would suppress the
+                    // genuine message for good
+                    Ok(msg) if msg.sender == sender && msg.verify(self.myid, sk) => {
+                        stream.delivered(seq);
+                        match msg.into_wrapper(self.myid) {
+                            Some(wrapper_msg) => {
+                                if self.deliver.send(wrapper_msg).is_err() {
+                                    log::error!("Consensus layer has shut down, dropping multicast message");
+                                }
+                            },
+                            None => log::warn!("Multicast message {} from {} carries no MAC for us", seq, sender),
+                        }
+                    },
+                    // Left undelivered, so the next tick asks for it again
+                    _ => log::warn!("Reassembled multicast message {} from {} is malformed or fails its MAC", seq, sender),
+                }
+            },
+            Datagram::Announce { sender, first_seq, next_seq, .. } => {
+                let max_chunk = self.config.mtu - FRAGMENT_OVERHEAD;
+                let skipped = self.streams.entry(sender)
+                    .or_insert_with(|| PeerStream::new(max_chunk))
+                    .on_announce(first_seq, next_seq);
+                if skipped > 0 {
+                    log::warn!("Node {} no longer has {} of its messages we missed, skipping them", sender, skipped);
+                }
+            },
+            Datagram::Nack { sender, target, seqs, .. } => {
+                if target != self.myid {
+                    return;
+                }
+                log::debug!("Node {} asked us to resend {:?}", sender, seqs);
+                // Bound the resends one datagram can trigger
+                let seqs: BTreeSet<u64> = seqs.into_iter().take(MAX_NACK_SEQS).collect();
+                for seq in seqs {
+                    match self.sent.get(&seq) {
+                        Some(frags) => {
+                            for frag in frags.iter() {
+                                self.send(frag).await;
+                            }
+                        },
+                        None => log::warn!("Node {} asked for message {} which we no longer have", sender, seq),
+                    }
+                }
+            },
+        }
+    }
+
+    async fn on_tick(&mut self) {
+        if self.next_seq > 0 {
+            let first_seq = self.sent.keys().next().copied().unwrap_or(self.next_seq);
+            let announce = Datagram::announce(self.myid, first_seq, self.next_seq, &self.keys);
+            self.send(&announce.to_bytes()).await;
+        }
+        let mut nacks = Vec::new();
+        for (peer, stream) in self.streams.iter() {
+            let missing = stream.missing();
+            if missing.is_empty() {
+                continue;
+            }
+            if let Some(sk) = self.keys.get(peer) {
+                nacks.push(Datagram::nack(self.myid, *peer, missing, sk));
+            }
+        }
+        for nack in nacks {
+            self.send(&nack.to_bytes()).await;
+        }
+    }
+}
+
+fn bind(config: &MulticastConfig) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // Every node on a host listens on the group port
+    socket.set_reuse_address(true)?;
+    let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, config.group.port());
+    socket.bind(&SocketAddr::V4(addr).into())?;
+    socket.join_multicast_v4(config.group.ip(), &config.interface)?;
+    socket.set_multicast_if_v4(&config.interface)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_multicast_ttl_v4(config.ttl)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::hash::verf_mac;
+    use tokio::time::timeout;
+    use types::ProtocolId;
+    use zeroize::Zeroizing;
+
+    fn key(i: Replica, j: Replica) -> Vec<u8> {
+        vec![(i.min(j) * 16 + i.max(j)) as u8; crypto::SECRET_KEY_SIZE]
+    }
+
+    fn spawn_node(config: &MulticastConfig, id: Replica, n: usize) -> (MulticastSender, UnboundedReceiver<WrapperMsg>) {
+        let keys = (0..n).map(|j| (j, Zeroizing::new(key(id, j)))).collect();
+        let (tx, rx) = unbounded_channel();
+        let sender = MulticastTransport::spawn(config.clone(), id, keys, tx)
+            .expect("failed to join the loopback multicast group");
+        (sender, rx)
+    }
+
+    #[tokio::test]
+    async fn loopback_delivers_fragmented_and_lost_messages() {
+        let port = 40000 + (std::process::id() % 20000) as u16;
+        let mut config = MulticastConfig::new(SocketAddrV4::new(Ipv4Addr::new(239, 255, 77, 1), port));
+        config.interface = Ipv4Addr::LOCALHOST;
+        config.mtu = config::MIN_MULTICAST_MTU;
+        let header = EnvelopeHeader::new(ProtocolId::AppxCon, 3);
+        // Large enough to need a few dozen fragments
+        let protmsg = ProtMsg::WITNESS((0..1000).collect(), 0, 7);
+
+        let (node0, _rx0) = spawn_node(&config, 0, 3);
+        let (_node1, mut rx1) = spawn_node(&config, 1, 3);
+        node0.broadcast(header, protmsg.clone());
+
+        let wrapper_msg = timeout(Duration::from_secs(5), rx1.recv()).await
+            .expect("node 1 never got the broadcast")
+            .unwrap();
+        assert_eq!(wrapper_msg.sender, 0);
+        assert!(verf_mac(&WrapperMsg::mac_bytes(&header, &protmsg), &key(0, 1), &wrapper_msg.mac));
+
+        // Node 2 joins after the broadcast went out, so it only learns about
+        // the message from announcements and has to NACK for it
+        let (_node2, mut rx2) = spawn_node(&config, 2, 3);
+        let wrapper_msg = timeout(Duration::from_secs(5), rx2.recv()).await
+            .expect("node 2 never recovered the lost broadcast")
+            .unwrap();
+        assert_eq!(wrapper_msg.protmsg.round(), 7);
+        assert!(verf_mac(&WrapperMsg::mac_bytes(&header, &protmsg), &key(0, 2), &wrapper_msg.mac));
+    }
+}
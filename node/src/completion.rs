@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+use types::Replica;
+
+/// When the syncer considers a phase of a run finished
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompletionPolicy {
+    /// Completions that finish a run; all nodes by default
+    pub quorum: Option<usize>,
+    /// Completions that finish the sharing phase; 2n/3+1 by default
+    pub sharing_quorum: Option<usize>,
+    /// Milliseconds after START at which a phase is finished with whatever
+    /// completions arrived, quorum or not. Waits forever when unset.
+    pub timeout_ms: Option<u64>,
+    /// Milliseconds to keep waiting for the remaining nodes once the quorum
+    /// is reached
+    pub grace_ms: u64,
+}
+
+impl CompletionPolicy {
+    pub fn quorum(&self, num_nodes: usize) -> usize {
+        self.quorum.unwrap_or(num_nodes)
+    }
+
+    pub fn sharing_quorum(&self, num_nodes: usize) -> usize {
+        self.sharing_quorum.unwrap_or((2*num_nodes/3)+1)
+    }
+
+    /// Checks the quorums fit a system of `num_nodes` nodes
+    pub fn validate(&self, num_nodes: usize) -> Result<(), String> {
+        for (name, quorum) in [("quorum", self.quorum), ("sharing quorum", self.sharing_quorum)] {
+            match quorum {
+                Some(q) if q == 0 || q > num_nodes => {
+                    return Err(format!("The {} must be between 1 and {}, got {}", name, num_nodes, q));
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the nodes of a finished phase fared
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletionOutcome {
+    pub quorum: usize,
+    pub quorum_reached: bool,
+    /// The phase was cut short by the timeout
+    pub timed_out: bool,
+    /// The first `quorum` nodes to complete
+    pub on_time: Vec<Replica>,
+    /// Nodes that completed after the quorum was reached
+    pub late: Vec<Replica>,
+    /// Nodes that never reported completion
+    pub missing: Vec<Replica>,
+}
+
+impl CompletionOutcome {
+    pub fn classify(times: &HashMap<Replica, u128>, num_nodes: usize, quorum: usize, timed_out: bool) -> CompletionOutcome {
+        let mut order: Vec<(u128, Replica)> = times.iter().map(|(rep, time)| (*time, *rep)).collect();
+        order.sort();
+        let mut on_time: Vec<Replica> = order.iter().take(quorum).map(|(_, rep)| *rep).collect();
+        let mut late: Vec<Replica> = order.iter().skip(quorum).map(|(_, rep)| *rep).collect();
+        on_time.sort();
+        late.sort();
+        let missing = (0..num_nodes).filter(|rep| !times.contains_key(rep)).collect();
+        CompletionOutcome {
+            quorum,
+            quorum_reached: times.len() >= quorum,
+            timed_out,
+            on_time,
+            late,
+            missing,
+        }
+    }
+
+    pub fn status(&self, node: Replica) -> &'static str {
+        if self.on_time.contains(&node) {
+            "on_time"
+        } else if self.late.contains(&node) {
+            "late"
+        } else {
+            "missing"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_time_late_and_missing() {
+        let times = [(3, 40), (0, 10), (2, 30), (1, 10)].into_iter().collect();
+        let outcome = CompletionOutcome::classify(&times, 5, 3, false);
+        assert_eq!(outcome.on_time, vec![0, 1, 2]);
+        assert_eq!(outcome.late, vec![3]);
+        assert_eq!(outcome.missing, vec![4]);
+        assert!(outcome.quorum_reached);
+        let outcome = CompletionOutcome::classify(&times, 5, 5, true);
+        assert_eq!((outcome.on_time.len(), outcome.quorum_reached), (4, false));
+    }
+}
@@ -0,0 +1,171 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Serialize, Deserialize};
+use types::{Replica, RunSpec};
+
+use crate::{RunReport, MembershipSchedule};
+
+/// K back-to-back runs driven by the syncer, each with fresh inputs
+#[derive(Debug, Clone)]
+pub struct Experiment {
+    /// Session of run k is `base_session + k`
+    pub base_session: u64,
+    /// inputs[k][i] is the input of node i in run k; revoked nodes have an
+    /// entry that is not used
+    pub inputs: Vec<Vec<u64>>,
+    /// Membership changes between the runs
+    pub schedule: MembershipSchedule,
+    /// The run in progress
+    pub current: usize,
+    /// Whether the nodes have been reset for the current run
+    pub armed: bool,
+    pub reports: Vec<RunReport>,
+}
+
+impl Experiment {
+    /// Draws inputs uniformly from `[low, high)`
+    pub fn from_seed(runs: usize, num_nodes: usize, seed: u64, low: u64, high: u64, base_session: u64, schedule: MembershipSchedule) -> Experiment {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let inputs = (0..runs)
+            .map(|run| (0..schedule.num_nodes(run, num_nodes)).map(|_| rng.gen_range(low, high)).collect())
+            .collect();
+        Experiment::new(inputs, base_session, schedule)
+    }
+
+    /// Reads one run per line, each a comma-separated list of one input per
+    /// node of that run's membership
+    pub fn from_file(path: &str, num_nodes: usize, base_session: u64, schedule: MembershipSchedule) -> io::Result<Experiment> {
+        let f = File::open(path)?;
+        let mut inputs = Vec::new();
+        for (lineno, line) in BufReader::new(f).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let run: Vec<u64> = line.split(',')
+                .map(|v| v.trim().parse::<u64>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path, lineno + 1, e)))?;
+            let expected = schedule.num_nodes(inputs.len(), num_nodes);
+            if run.len() != expected {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                    format!("{}:{}: expected {} inputs, found {}", path, lineno + 1, expected, run.len())));
+            }
+            inputs.push(run);
+        }
+        Ok(Experiment::new(inputs, base_session, schedule))
+    }
+
+    fn new(inputs: Vec<Vec<u64>>, base_session: u64, schedule: MembershipSchedule) -> Experiment {
+        Experiment {
+            base_session,
+            inputs,
+            schedule,
+            current: 0,
+            armed: false,
+            reports: Vec::new(),
+        }
+    }
+
+    pub fn runs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn session(&self, run: usize) -> u64 {
+        self.base_session + run as u64
+    }
+
+    pub fn spec(&self, run: usize, node: Replica) -> RunSpec {
+        RunSpec {
+            run,
+            session: self.session(run),
+            input: self.inputs[run][node],
+            membership: self.schedule.at(run).cloned(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.current >= self.runs()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub min: u128,
+    pub p50: u128,
+    pub p90: u128,
+    pub p99: u128,
+    pub max: u128,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles; None for an empty sample
+    pub fn of(mut samples: Vec<u128>) -> Option<Percentiles> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        let rank = |p: usize| samples[(p * samples.len()).div_ceil(100).max(1) - 1];
+        Some(Percentiles {
+            min: samples[0],
+            p50: rank(50),
+            p90: rank(90),
+            p99: rank(99),
+            max: samples[samples.len() - 1],
+        })
+    }
+}
+
+/// Statistics across all runs of an experiment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentSummary {
+    pub runs: usize,
+    pub runs_within_epsilon: usize,
+    /// Runs finished by the timeout before enough nodes completed
+    pub runs_timed_out: usize,
+    /// Over every node of every run
+    pub node_latency_ms: Option<Percentiles>,
+    /// Over the slowest node of each run
+    pub run_latency_ms: Option<Percentiles>,
+    pub spread: Option<Percentiles>,
+    pub reports: Vec<RunReport>,
+}
+
+impl ExperimentSummary {
+    pub fn new(reports: Vec<RunReport>) -> ExperimentSummary {
+        let node_latency = reports.iter()
+            .flat_map(|r| r.results.iter().map(|res| res.latency_ms))
+            .collect();
+        let run_latency = reports.iter()
+            .filter_map(|r| r.results.iter().map(|res| res.latency_ms).max())
+            .collect();
+        let spread = reports.iter()
+            .filter_map(|r| r.spread.map(|s| s as u128))
+            .collect();
+        ExperimentSummary {
+            runs: reports.len(),
+            runs_within_epsilon: reports.iter().filter(|r| r.within_epsilon).count(),
+            runs_timed_out: reports.iter().filter(|r| !r.completion.quorum_reached).count(),
+            node_latency_ms: Percentiles::of(node_latency),
+            run_latency_ms: Percentiles::of(run_latency),
+            spread: Percentiles::of(spread),
+            reports,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let p = Percentiles::of((1..=100).collect()).unwrap();
+        assert_eq!((p.min, p.p50, p.p90, p.p99, p.max), (1, 50, 90, 99, 100));
+        let p = Percentiles::of(vec![7]).unwrap();
+        assert_eq!((p.p50, p.p99), (7, 7));
+        assert!(Percentiles::of(Vec::new()).is_none());
+    }
+}
@@ -1,2 +1,17 @@
 pub mod syncer;
-pub use syncer::*;
\ No newline at end of file
+pub use syncer::*;
+
+mod report;
+pub use report::*;
+
+mod experiment;
+pub use experiment::*;
+
+mod membership;
+pub use membership::*;
+
+mod completion;
+pub use completion::*;
+
+mod logging;
+pub use logging::*;
@@ -0,0 +1,50 @@
+use anyhow::{Result, anyhow};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::filter::LevelFilter;
+
+/// How log lines are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One JSON object per event, carrying the fields of every enclosing
+    /// span so a run can be filtered by node, round or RBC originator
+    Json,
+    /// Human readable lines
+    Text,
+}
+
+impl LogFormat {
+    pub fn parse(format: &str) -> Result<LogFormat> {
+        match format {
+            "json" => Ok(LogFormat::Json),
+            "text" => Ok(LogFormat::Text),
+            _ => Err(anyhow!("Unknown log format {}, expected json or text", format)),
+        }
+    }
+}
+
+/// The level for the number of `-v` flags given
+pub fn verbosity(occurrences: u64) -> LevelFilter {
+    match occurrences {
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Installs the global subscriber. Records from crates still on `log`,
+/// such as the syncer, go through the same subscriber. Closing a round, RBC
+/// or BAA span logs how long it was open.
+pub fn init_logging(level: LevelFilter, format: LogFormat) -> Result<()> {
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_span_events(FmtSpan::CLOSE);
+    let res = match format {
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init(),
+        LogFormat::Text => builder.try_init(),
+    };
+    res.map_err(|e| anyhow!("Unable to install the logger: {}", e))
+}
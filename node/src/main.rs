@@ -1,107 +1,42 @@
-use anyhow::{Result,anyhow};
+use anyhow::{Result, anyhow};
 use clap::{
-    load_yaml, 
-    App
+    load_yaml,
+    App,
+    ArgMatches
 };
-use config::Node;
+use config::{Node, Passphrase, ProtocolOverrides};
 use fnv::FnvHashMap;
-use node::Syncer;
+use node::{Syncer, RunParams, read_lines, Experiment, MembershipSchedule, CompletionPolicy, LogFormat, init_logging, verbosity};
 use signal_hook::{iterator::Signals, consts::{SIGINT, SIGTERM}};
-use std::{net::{SocketAddr, SocketAddrV4}, collections::hash_map::DefaultHasher};
+use std::{fmt::Display, str::FromStr, time::{SystemTime, UNIX_EPOCH}};
+use tokio::sync::oneshot;
+use types::{ProtocolId, Membership};
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    log::error!("{}", std::env::current_dir().unwrap().display());
+async fn main() {
+    // The message and its causes, without the debug backtrace
+    if let Err(e) = run().await {
+        eprintln!("error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run() -> Result<()> {
     let yaml = load_yaml!("cli.yml");
     let m = App::from_yaml(yaml).get_matches();
-    //println!("{:?}",m);
-    let conf_str = m.value_of("config")
-        .expect("unable to convert config file into a string");
-    let vss_type = m.value_of("vsstype")
-        .expect("Unable to detect VSS type");
-    let sleep = m.value_of("sleep")
-        .expect("Unable to detect sleep time").parse::<u128>().unwrap();
-    let batch = m.value_of("batch")
-        .expect("Unable to parse batch size").parse::<usize>().unwrap();
-    let val_appx = m.value_of("val")
-        .expect("Value required").parse::<u64>().unwrap();
-    let delta = m.value_of("delta")
-        .expect("Value required").parse::<u64>().unwrap();
-    let epsilon = m.value_of("epsilon")
-        .expect("Value required").parse::<u64>().unwrap();
-    let tri = m.value_of("tri")
-        .expect("Value required").parse::<u64>().unwrap();
-    let syncer_file = m.value_of("syncer")
-        .expect("Unable to parse syncer ip file");
-    let conf_file = std::path::Path::new(conf_str);
-    let str = String::from(conf_str);
-    let mut config = match conf_file
-        .extension()
-        .expect("Unable to get file extension")
-        .to_str()
-        .expect("Failed to convert the extension into ascii string") 
-    {
-        "json" => Node::from_json(str),
-        "dat" => Node::from_bin(str),
-        "toml" => Node::from_toml(str),
-        "yaml" => Node::from_yaml(str),
-        _ => panic!("Invalid config file extension"),
+    // SubcommandRequiredElseHelp makes clap print the help without one
+    let (command, sub) = match m.subcommand() {
+        (command, Some(sub)) => (command, sub),
+        _ => return Err(anyhow!("No subcommand given; see --help")),
+    };
+    let log_format = LogFormat::parse(sub.value_of("log_format").unwrap_or("json"))?;
+    init_logging(verbosity(sub.occurrences_of("debug")), log_format)?;
+    let exit_tx = match command {
+        "run-appx" => run_node(sub, ProtocolId::AppxCon)?,
+        "run-hyb" => run_node(sub, ProtocolId::HybAppxCon)?,
+        "syncer" => run_syncer(sub)?,
+        _ => return Err(anyhow!("Unknown subcommand {}", command)),
     };
-
-    simple_logger::SimpleLogger::new().with_utc_timestamps().init().unwrap();
-    // match m.occurrences_of("debug") {
-    //     0 => log::set_max_level(log::LevelFilter::Info),
-    //     1 => log::set_max_level(log::LevelFilter::Debug),
-    //     2 | _ => log::set_max_level(log::LevelFilter::Trace),
-    // }
-    log::info!("epsilon: {:?},delta: {:?},value: {:?}, tri:{:?}",epsilon,delta,val_appx,tri);
-    log::set_max_level(log::LevelFilter::Info);
-    config
-        .validate()
-        .expect("The decoded config is not valid");
-    if let Some(f) = m.value_of("ip") {
-        let f_str = f.to_string();
-        log::info!("Logging the file f {}",f_str);
-        config.update_config(util::io::file_to_ips(f.to_string()));
-    }
-    let config = config;
-    // Start the Reliable Broadcast protocol
-    let exit_tx;
-    match vss_type{
-        // "ped" =>{
-        //     //exit_tx = pedavss_cc::node::Context::spawn(config,sleep).unwrap();
-        // },
-        // "fre" => {
-        //     //exit_tx = hash_cc::node::Context::spawn(config,sleep).unwrap();
-        // },
-        // "hr" => {
-        //     //exit_tx = hash_cc_baa::node::Context::spawn(config,sleep,batch).unwrap();
-        // },
-        "appx" => {
-            exit_tx = appxcon::node::Context::spawn(config, sleep, val_appx,epsilon).unwrap();
-        },
-        "hyb" =>{
-            exit_tx = hyb_appxcon::node::Context::spawn(config,sleep,val_appx,delta,epsilon,tri).unwrap();
-        },
-        "sync" => {
-            let f_str = syncer_file.to_string();
-            log::info!("Logging the file f {}",f_str);
-            let ip_str = util::io::file_to_ips(f_str);
-            let mut net_map = FnvHashMap::default();
-            let mut idx = 0;
-            for ip in ip_str{
-                net_map.insert(idx, ip.clone());
-                idx += 1;
-            }
-            //let client_addr = net_map.get(&(net_map.len()-1)).unwrap();
-            exit_tx = Syncer::spawn(net_map, config.client_addr.clone()).unwrap();
-        },
-        _ =>{
-            log::error!("Matching VSS not provided, canceling execution");
-            return Ok(());
-        }
-    }
-    //let exit_tx = pedavss_cc::node::Context::spawn(config).unwrap();
     // Implement a waiting strategy
     let mut signals = Signals::new(&[SIGINT, SIGTERM])?;
     signals.forever().next();
@@ -113,12 +48,156 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// The value of `--name`, if given
+fn parse<T>(m: &ArgMatches, name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    m.value_of(name)
+        .map(|v| v.parse::<T>().map_err(|e| anyhow!("Invalid --{} {}: {}", name, v, e)))
+        .transpose()
+}
+
+/// Loads `--config` and applies the flags every subcommand shares
+fn load_config(m: &ArgMatches) -> Result<Node> {
+    let conf_str = m.value_of("config")
+        .ok_or_else(|| anyhow!("No --config given"))?;
+    // Encrypted secrets are decrypted with the passphrase of
+    // --passphrase_file, $SENSORBFT_PASSPHRASE or a prompt
+    let passphrase = Passphrase::from_file_or_env(m.value_of("passphrase_file"));
+    // The error already names its cause
+    let mut config = Node::load_with(conf_str, &passphrase)
+        .map_err(|e| anyhow!("{}", e))?;
+    if let Some(f) = m.value_of("ip") {
+        log::info!("Reading the addresses of the nodes from {}", f);
+        config.update_config(read_lines(f)?)
+            .map_err(|e| anyhow!("Invalid ip file {}: {}", f, e))?;
+    }
+    if let Some(bind) = parse(m, "bind")? {
+        config.bind_addr = Some(bind);
+    }
+    if let Some(session) = parse(m, "session")? {
+        config.session = session;
+    }
+    log::info!("Running session {}", config.session);
+    if let Some(port) = parse(m, "metrics")? {
+        config.metrics_port = Some(port);
+    }
+    if let Some(timeout) = parse(m, "stall_timeout")? {
+        config.stall_timeout_ms = Some(timeout);
+    }
+    Ok(config)
+}
 
+/// Starts a node of `protocol`
+fn run_node(m: &ArgMatches, protocol: ProtocolId) -> Result<oneshot::Sender<()>> {
+    let conf_str = m.value_of("config").unwrap_or_default();
+    let mut config = load_config(m)?;
+    let overrides = ProtocolOverrides {
+        value: parse(m, "val")?,
+        epsilon: parse(m, "epsilon")?,
+        delta: parse(m, "delta")?,
+        tri: parse(m, "tri")?,
+    };
+    config.protocol = Some(overrides.apply(protocol, config.protocol.as_ref())
+        .map_err(|e| anyhow!("Invalid protocol parameters in {}: {}", conf_str, e))?);
+    log::info!("Running {:?}", config.protocol);
+    // Check the config as the protocol will run it, overrides included
+    config.validate_for(protocol)
+        .map_err(|e| anyhow!("Invalid config {}: {}", conf_str, e))?;
+    let sleep = match parse(m, "sleep")? {
+        Some(sleep) => sleep,
+        None => SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis(),
+    };
+    match protocol {
+        ProtocolId::AppxCon => appxcon::node::Context::spawn(config, sleep),
+        ProtocolId::HybAppxCon => hyb_appxcon::node::Context::spawn(config, sleep),
+        ProtocolId::CTRBC => Err(anyhow!("CTRBC has no node to run")),
+    }
+}
 
-pub fn to_socket_address(
-    ip_str: &str,
-    port: u16,
-) -> SocketAddr {
-    let addr = SocketAddrV4::new(ip_str.parse().unwrap(), port);
-    addr.into()
-}
\ No newline at end of file
+/// Starts the syncer, which drives the nodes through a run or an experiment
+fn run_syncer(m: &ArgMatches) -> Result<oneshot::Sender<()>> {
+    let conf_str = m.value_of("config").unwrap_or_default();
+    let config = load_config(m)?;
+    config.validate()
+        .map_err(|e| anyhow!("Invalid config {}: {}", conf_str, e))?;
+    let syncer_file = m.value_of("syncer")
+        .ok_or_else(|| anyhow!("No --syncer file given"))?;
+    log::info!("Reading where to reach the nodes from {}", syncer_file);
+    let mut net_map = FnvHashMap::default();
+    for (idx, ip) in read_lines(syncer_file)?.into_iter().enumerate() {
+        net_map.insert(idx, ip);
+    }
+    if net_map.is_empty() {
+        return Err(anyhow!("{} lists no nodes", syncer_file));
+    }
+    let completion = CompletionPolicy {
+        quorum: parse(m, "quorum")?,
+        sharing_quorum: parse(m, "sharing_quorum")?,
+        timeout_ms: parse(m, "timeout")?,
+        grace_ms: parse(m, "grace")?.unwrap_or(0),
+    };
+    completion.validate(net_map.len())
+        .map_err(|e| anyhow!(e))?;
+    // The syncer records the parameters of the run; take them from the
+    // flags, or else from the config's protocol section
+    let section = config.protocol.as_ref();
+    let epsilon = parse(m, "epsilon")?.or(section.map(|s| s.epsilon()))
+        .ok_or_else(|| anyhow!("The syncer needs --epsilon or a protocol section giving it"))?;
+    let delta = parse(m, "delta")?.or(section.and_then(|s| s.delta()));
+    let tri = parse(m, "tri")?.or(section.and_then(|s| s.tri()));
+    let params = RunParams {
+        protocol: m.value_of("protocol").map(String::from),
+        num_nodes: net_map.len(),
+        revoked: config.revoked.clone(),
+        epsilon,
+        // Only hyb has a delta and a tri
+        delta: delta.unwrap_or(0),
+        tri: tri.unwrap_or(0),
+        session: config.session,
+        completion,
+    };
+    let report_path = m.value_of("report")
+        .unwrap_or("syncer-report")
+        .to_string();
+    // Membership changes between runs; the syncer starts with the
+    // nodes of its syncer file and the revoked replicas of its config
+    let initial = Membership {
+        epoch: 0,
+        num_nodes: net_map.len(),
+        num_faults: config.num_faults,
+        revoked: config.revoked.clone(),
+    };
+    let schedule = match m.value_of("reconfig") {
+        Some(path) => MembershipSchedule::read(path, &initial)
+            .map_err(|e| anyhow!("Unable to read the membership schedule {}: {}", path, e))?,
+        None => MembershipSchedule::default(),
+    };
+    for membership in schedule.memberships() {
+        params.completion.validate(membership.members().count())
+            .map_err(|e| anyhow!("{} in epoch {}", e, membership.epoch))?;
+    }
+    let experiment = match (m.value_of("inputs"), parse::<usize>(m, "runs")?) {
+        (Some(inputs), _) => Some(Experiment::from_file(inputs, net_map.len(), config.session, schedule)
+            .map_err(|e| anyhow!("Unable to read the experiment inputs {}: {}", inputs, e))?),
+        (None, Some(runs)) => {
+            let seed = parse(m, "seed")?.unwrap_or(0);
+            // Honest inputs lie within tri of each other
+            let tri = tri.ok_or_else(|| anyhow!("--runs draws the inputs from [0, tri]; give --tri or a hyb protocol section"))?;
+            Some(Experiment::from_seed(runs, net_map.len(), seed, 0, tri+1, config.session, schedule))
+        },
+        (None, None) if !schedule.is_empty() => {
+            return Err(anyhow!("Membership changes happen between runs; --reconfig needs --runs or --inputs"));
+        },
+        (None, None) => None,
+    };
+    if let Some(exp) = &experiment {
+        if exp.runs() == 0 {
+            return Err(anyhow!("The experiment has no runs"));
+        }
+        log::info!("Running an experiment of {} runs", exp.runs());
+    }
+    Syncer::spawn(net_map, syncer_file.to_string(), config.revoked.clone(), config.syncer_listen_addr(), params, report_path, experiment)
+}
@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use types::{Membership, Replica};
+
+/// Membership changes of an experiment, by the run they take effect at
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MembershipSchedule {
+    changes: BTreeMap<usize, Membership>,
+}
+
+impl MembershipSchedule {
+    /// Reads one change per line: the run it takes effect at, n, f and the
+    /// comma-separated revoked replicas, if any, e.g. `3 5 1 2`. Change k
+    /// starts epoch k+1. Blank lines and everything after a `#` are
+    /// ignored. `initial` is the membership of the first run.
+    pub fn read(path: &str, initial: &Membership) -> io::Result<MembershipSchedule> {
+        let f = File::open(path)?;
+        let invalid = |lineno: usize, msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path, lineno + 1, msg));
+        let mut schedule = MembershipSchedule::default();
+        let mut last = initial.clone();
+        let mut last_run = 0;
+        for (lineno, line) in BufReader::new(f).lines().enumerate() {
+            let line = line?;
+            let fields: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+            if fields.len() < 3 || fields.len() > 4 {
+                return Err(invalid(lineno, "expected <run> <num_nodes> <num_faults> [revoked,...]".to_string()));
+            }
+            let number = |s: &str| s.parse::<usize>().map_err(|e| invalid(lineno, format!("{}: {}", s, e)));
+            let run = number(fields[0])?;
+            let mut revoked = match fields.get(3) {
+                Some(list) => list.split(',').map(|r| number(r.trim())).collect::<Result<Vec<Replica>, _>>()?,
+                None => Vec::new(),
+            };
+            revoked.sort_unstable();
+            revoked.dedup();
+            let membership = Membership {
+                epoch: last.epoch + 1,
+                num_nodes: number(fields[1])?,
+                num_faults: number(fields[2])?,
+                revoked,
+            };
+            if run <= last_run {
+                return Err(invalid(lineno, format!("run {} does not come after run {}", run, last_run)));
+            }
+            check(&last, &membership).map_err(|e| invalid(lineno, e))?;
+            schedule.changes.insert(run, membership.clone());
+            last = membership;
+            last_run = run;
+        }
+        Ok(schedule)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The change that takes effect at `run`
+    pub fn at(&self, run: usize) -> Option<&Membership> {
+        self.changes.get(&run)
+    }
+
+    /// n during `run`
+    pub fn num_nodes(&self, run: usize, initial: usize) -> usize {
+        self.changes.range(..=run).next_back()
+            .map(|(_, m)| m.num_nodes)
+            .unwrap_or(initial)
+    }
+
+    pub fn memberships(&self) -> impl Iterator<Item = &Membership> {
+        self.changes.values()
+    }
+}
+
+/// Ids are not reused: n never shrinks and a revoked replica stays revoked
+fn check(last: &Membership, next: &Membership) -> Result<(), String> {
+    // The same n > 3f that Node::validate_for holds every protocol to
+    if next.num_nodes <= 3*next.num_faults {
+        return Err(format!("f = {} is too large for n = {}", next.num_faults, next.num_nodes));
+    }
+    if next.num_nodes < last.num_nodes {
+        return Err(format!("n shrinks from {} to {}; revoke the nodes that leave instead", last.num_nodes, next.num_nodes));
+    }
+    if let Some(rep) = next.revoked.iter().find(|r| **r >= next.num_nodes) {
+        return Err(format!("revoked replica {} is not below n = {}", rep, next.num_nodes));
+    }
+    if let Some(rep) = last.revoked.iter().find(|r| !next.revoked.contains(r)) {
+        return Err(format!("replica {} was revoked and cannot come back", rep));
+    }
+    if next.revoked.len() > next.num_faults {
+        return Err(format!("{} replicas are revoked but only f = {} faults are tolerated", next.revoked.len(), next.num_faults));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(lines: &str) -> io::Result<MembershipSchedule> {
+        let path = std::env::temp_dir().join(format!("membership-{}-{}", std::process::id(), lines.len()));
+        std::fs::write(&path, lines)?;
+        let initial = Membership { epoch: 0, num_nodes: 4, num_faults: 1, revoked: Vec::new() };
+        let schedule = MembershipSchedule::read(path.to_str().unwrap(), &initial);
+        let _ = std::fs::remove_file(&path);
+        schedule
+    }
+
+    #[test]
+    fn reads_membership_changes() {
+        let s = schedule("# node 4 joins, then node 2 leaves\n2 5 1\n\n4 5 1 2\n").unwrap();
+        assert_eq!(s.at(2), Some(&Membership { epoch: 1, num_nodes: 5, num_faults: 1, revoked: vec![] }));
+        assert_eq!(s.at(4).unwrap().epoch, 2);
+        assert!(s.at(3).is_none());
+        assert_eq!((s.num_nodes(1, 4), s.num_nodes(3, 4), s.num_nodes(9, 4)), (4, 5, 5));
+
+        assert!(schedule("2 5 1 2\n4 5 1\n").is_err());
+        assert!(schedule("2 3 1\n").is_err());
+        assert!(schedule("2 5 2\n").is_err());
+        assert!(schedule("2 5 1 1,2\n").is_err());
+        assert!(schedule("2 5 1\n2 6 1\n").is_err());
+    }
+}
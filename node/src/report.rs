@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+
+use consensus::{CompletionReport, StallReport};
+use serde::{Serialize, Deserialize};
+use types::Replica;
+
+use crate::{CompletionPolicy, CompletionOutcome};
+
+/// The parameters a run was started with, as given to the syncer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunParams {
+    pub protocol: Option<String>,
+    pub num_nodes: usize,
+    /// Replicas below `num_nodes` that are no longer members
+    #[serde(default)]
+    pub revoked: Vec<Replica>,
+    pub epsilon: u64,
+    pub delta: u64,
+    pub tri: u64,
+    pub session: u64,
+    pub completion: CompletionPolicy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeResult {
+    pub node: Replica,
+    /// Milliseconds from the START broadcast to the node reporting completion
+    pub latency_ms: u128,
+    pub value: u64,
+}
+
+/// Durations of one phase or stage across all nodes, and all rounds for
+/// a phase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DurationAggregate {
+    pub name: String,
+    pub samples: usize,
+    pub mean_ms: u128,
+    pub max_ms: u128,
+}
+
+impl DurationAggregate {
+    fn collect<'a>(durations: impl Iterator<Item = (&'a str, Option<u128>)>) -> Vec<DurationAggregate> {
+        let mut samples: BTreeMap<&str, Vec<u128>> = BTreeMap::new();
+        for (name, duration) in durations {
+            if let Some(duration) = duration {
+                samples.entry(name).or_default().push(duration);
+            }
+        }
+        samples.into_iter()
+            .map(|(name, samples)| DurationAggregate {
+                name: name.to_string(),
+                samples: samples.len(),
+                mean_ms: samples.iter().sum::<u128>() / samples.len() as u128,
+                max_ms: samples.iter().copied().max().unwrap_or(0),
+            })
+            .collect()
+    }
+}
+
+/// What the completion reports of the nodes add up to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeReportSummary {
+    /// Nodes that sent a completion report
+    pub reports: usize,
+    pub min_rounds: Option<u64>,
+    pub max_rounds: Option<u64>,
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub phases: Vec<DurationAggregate>,
+    pub stages: Vec<DurationAggregate>,
+}
+
+impl NodeReportSummary {
+    pub fn new(reports: &[CompletionReport]) -> NodeReportSummary {
+        let phases = DurationAggregate::collect(reports.iter()
+            .flat_map(|r| r.summary.phases.iter())
+            .map(|p| (p.phase.as_str(), p.duration())));
+        let stages = DurationAggregate::collect(reports.iter()
+            .flat_map(|r| r.summary.stages.iter())
+            .map(|s| (s.stage.as_str(), s.duration())));
+        NodeReportSummary {
+            reports: reports.len(),
+            min_rounds: reports.iter().map(|r| r.summary.rounds_to_termination).min(),
+            max_rounds: reports.iter().map(|r| r.summary.rounds_to_termination).max(),
+            messages_sent: reports.iter().map(|r| r.summary.messages_sent).sum(),
+            bytes_sent: reports.iter().map(|r| r.summary.bytes_sent).sum(),
+            phases,
+            stages,
+        }
+    }
+}
+
+/// Outcome of one run, written by the syncer once the nodes complete
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunReport {
+    pub params: RunParams,
+    /// Milliseconds since the epoch at which the syncer sent START
+    pub start_time: u128,
+    pub results: Vec<NodeResult>,
+    pub min_value: Option<u64>,
+    pub max_value: Option<u64>,
+    pub spread: Option<u64>,
+    /// Whether all decided values lie within epsilon of each other
+    pub within_epsilon: bool,
+    pub completion: CompletionOutcome,
+    pub node_summary: NodeReportSummary,
+    /// Completion reports of the nodes that sent one
+    pub node_reports: Vec<CompletionReport>,
+    /// Latest stall report of each node whose watchdog fired
+    pub stalls: Vec<StallReport>,
+}
+
+impl RunReport {
+    pub fn new(
+        params: RunParams,
+        start_time: u128,
+        mut results: Vec<NodeResult>,
+        completion: CompletionOutcome,
+        mut node_reports: Vec<CompletionReport>,
+        mut stalls: Vec<StallReport>,
+    ) -> RunReport {
+        results.sort_by_key(|r| r.node);
+        node_reports.sort_by_key(|r| r.node);
+        stalls.sort_by_key(|r| r.node);
+        let min_value = results.iter().map(|r| r.value).min();
+        let max_value = results.iter().map(|r| r.value).max();
+        let spread = min_value.zip(max_value).map(|(min, max)| max - min);
+        let within_epsilon = spread.map(|s| s <= params.epsilon).unwrap_or(false);
+        RunReport {
+            params,
+            start_time,
+            results,
+            min_value,
+            max_value,
+            spread,
+            within_epsilon,
+            completion,
+            node_summary: NodeReportSummary::new(&node_reports),
+            node_reports,
+            stalls,
+        }
+    }
+
+    /// Writes `<prefix>.json` with the whole report and `<prefix>.csv` with
+    /// one row per member. Missing nodes get empty latency and value columns,
+    /// and nodes without a completion report empty rounds and bytes.
+    pub fn write(&self, prefix: &str) -> io::Result<()> {
+        consensus::write_json(&format!("{}.json", prefix), self)?;
+        let mut f = File::create(format!("{}.csv", prefix))?;
+        writeln!(f, "node,latency_ms,value,status,rounds,bytes_sent")?;
+        for node in (0..self.params.num_nodes).filter(|rep| !self.params.revoked.contains(rep)) {
+            let status = self.completion.status(node);
+            let (rounds, bytes_sent) = match self.node_reports.iter().find(|r| r.node == node) {
+                Some(report) => (report.summary.rounds_to_termination.to_string(), report.summary.bytes_sent.to_string()),
+                None => (String::new(), String::new()),
+            };
+            match self.results.iter().find(|r| r.node == node) {
+                Some(result) => writeln!(f, "{},{},{},{},{},{}", node, result.latency_ms, result.value, status, rounds, bytes_sent)?,
+                None => writeln!(f, "{},,,{},{},{}", node, status, rounds, bytes_sent)?,
+            }
+        }
+        Ok(())
+    }
+}
+
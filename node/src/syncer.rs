@@ -1,14 +1,35 @@
-use std::{collections::{HashSet, HashMap}, net::{SocketAddr,SocketAddrV4}, time::{SystemTime, UNIX_EPOCH, Duration}};
+use std::{collections::{HashSet, HashMap}, fs::File, io::{BufRead, BufReader}, net::SocketAddr, time::{SystemTime, UNIX_EPOCH, Duration}};
 
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, Context as _};
 use appxcon::node::SyncHandler;
+use consensus::{TrafficReport, TrafficSummary, CompletionReport, StallReport};
+
+use crate::{RunParams, RunReport, NodeResult, Experiment, ExperimentSummary, CompletionOutcome};
 use fnv::FnvHashMap;
 use network::{plaintcp::{TcpReceiver, TcpReliableSender, CancelHandler}, Acknowledgement};
 use tokio::sync::{oneshot, mpsc::{unbounded_channel, UnboundedReceiver}};
-use types::{Replica, SyncMsg, SyncState};
+use types::{Replica, SyncMsg, SyncState, Membership};
+
+/// Where the syncer is in the current run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase{
+    /// Waiting for the nodes to come up, or between the runs of an experiment
+    Idle,
+    /// START went out; waiting for completions
+    Running,
+    /// StartRecon went out; waiting for reconstructions
+    Recon,
+    Done,
+}
 
 pub struct Syncer{
+    /// Replicas 0..num_nodes, revoked ones included
     pub num_nodes: usize,
+    /// Replicas that left; they are not waited for
+    pub revoked: Vec<Replica>,
+    /// The file net_map was read from, reread when nodes join
+    pub net_file: String,
+    pub epoch: u64,
     pub start_time: u128,
     pub sharing_complete_times: HashMap<Replica,u128>,
     pub recon_start_time: u128,
@@ -16,6 +37,20 @@ pub struct Syncer{
     pub alive: HashSet<Replica>,
     pub timings:HashMap<Replica,u128>,
     pub values: HashMap<Replica,u64>,
+    pub traffic: HashMap<Replica,TrafficReport>,
+    /// Completion reports of the current run
+    pub completion_reports: HashMap<Replica,CompletionReport>,
+    /// Latest stall report of each node in the current run
+    pub stall_reports: HashMap<Replica,StallReport>,
+    /// Run parameters and where to write the result file
+    pub params: RunParams,
+    pub report_path: String,
+    /// Set when the syncer drives several runs back to back
+    pub experiment: Option<Experiment>,
+    pub phase: Phase,
+    /// Milliseconds since the epoch at which the current phase is finished
+    /// regardless of how many nodes completed
+    pub deadline: Option<u128>,
     pub cli_addr: SocketAddr,
     pub rx_net: UnboundedReceiver<SyncMsg>,
     pub net_send: TcpReliableSender<Replica,SyncMsg,Acknowledgement>,
@@ -27,23 +62,21 @@ pub struct Syncer{
 impl Syncer{
     pub fn spawn(
         net_map: FnvHashMap<Replica,String>,
-        cli_addr:SocketAddr,
+        net_file: String,
+        revoked: Vec<Replica>,
+        listen_addr:SocketAddr,
+        params:RunParams,
+        report_path:String,
+        experiment:Option<Experiment>,
     )-> anyhow::Result<oneshot::Sender<()>>{
         let (exit_tx, exit_rx) = oneshot::channel();
         let (tx_net_to_server, rx_net_to_server) = unbounded_channel();
-        let cli_addr_sock = cli_addr.port();
-        let new_sock_address = SocketAddrV4::new("0.0.0.0".parse().unwrap(), cli_addr_sock);
         TcpReceiver::<Acknowledgement, SyncMsg, _>::spawn(
-            std::net::SocketAddr::V4(new_sock_address),
+            listen_addr,
             SyncHandler::new(tx_net_to_server),
         );
-        let mut server_addrs :FnvHashMap<Replica,SocketAddr>= FnvHashMap::default();
         println!("{:?}",net_map);
-        for (replica,address) in net_map.iter(){
-            let address:SocketAddr = address.parse().expect("Unable to parse address");
-            server_addrs.insert(*replica, SocketAddr::from(address.clone()));
-        }
-        let net_send = TcpReliableSender::<Replica,SyncMsg,Acknowledgement>::with_peers(server_addrs);
+        let net_send = connect(&net_map, &revoked)?;
         tokio::spawn(async move{
             let mut syncer = Syncer{
                 net_map:net_map.clone(),
@@ -51,10 +84,21 @@ impl Syncer{
                 sharing_complete_times:HashMap::default(),
                 recon_start_time:0,
                 num_nodes:net_map.len(),
+                revoked:revoked,
+                net_file:net_file,
+                epoch:0,
                 alive:HashSet::default(),
                 values:HashMap::default(),
+                traffic:HashMap::default(),
+                completion_reports:HashMap::default(),
+                stall_reports:HashMap::default(),
+                params:params,
+                report_path:report_path,
+                experiment:experiment,
+                phase:Phase::Idle,
+                deadline:None,
                 timings:HashMap::default(),
-                cli_addr:cli_addr,
+                cli_addr:listen_addr,
                 rx_net:rx_net_to_server,
                 net_send:net_send,
                 exit_rx:exit_rx,
@@ -67,13 +111,16 @@ impl Syncer{
         Ok(exit_tx)
     }
     pub async fn broadcast(&mut self, sync_msg:SyncMsg){
-        for replica in 0..self.num_nodes {
+        let members: Vec<Replica> = self.members().collect();
+        for replica in members {
             let cancel_handler:CancelHandler<Acknowledgement> = self.net_send.send(replica, sync_msg.clone()).await;
             self.add_cancel_handler(cancel_handler);    
         }
     }
     pub async fn run(&mut self)-> Result<()>{
         loop {
+            let until_deadline = self.until_deadline();
+            let has_deadline = self.deadline.is_some();
             tokio::select! {
                 // Receive exit handlers
                 exit_val = &mut self.exit_rx => {
@@ -81,6 +128,9 @@ impl Syncer{
                     log::info!("Termination signal received by the server. Exiting.");
                     break
                 },
+                _ = tokio::time::sleep(until_deadline), if has_deadline => {
+                    self.on_deadline().await;
+                },
                 msg = self.rx_net.recv() => {
                     // Received a protocol message
                     // Received a protocol message
@@ -92,59 +142,56 @@ impl Syncer{
                         SyncState::ALIVE=>{
                             log::info!("Got ALIVE message from node {}",msg.sender);
                             self.alive.insert(msg.sender);
-                            if self.alive.len() == self.num_nodes{
+                            if self.members().all(|rep| self.alive.contains(&rep)){
+                                if self.experiment.as_ref().map(|exp| !exp.armed).unwrap_or(false){
+                                    // Hand out the inputs of the next run first; the nodes answer with ALIVE again
+                                    self.reset_nodes().await;
+                                    continue;
+                                }
                                 // sleep before sending message
                                 std::thread::sleep(Duration::from_secs(3));
                                 self.broadcast(SyncMsg { 
                                     sender: self.num_nodes, 
                                     state: SyncState::START,
-                                    value:0
+                                    value:0,
+                                    payload: Vec::new()
                                 }).await;
                                 self.start_time = SystemTime::now()
                                 .duration_since(UNIX_EPOCH)
                                 .unwrap()
                                 .as_millis();
+                                self.enter_phase(Phase::Running);
                             }
                         },
                         SyncState::STARTED=>{
                             log::info!("Node {} started the protocol",msg.sender);
                         },
+                        SyncState::CompletedSharing | SyncState::COMPLETED if self.phase != Phase::Running =>{
+                            log::warn!("Node {} reported completion while no run is in progress",msg.sender);
+                        },
                         SyncState::CompletedSharing=>{
                             log::info!("Node {} completed the sharing phase of the protocol",msg.sender);
                             self.sharing_complete_times.insert(msg.sender, SystemTime::now().duration_since(UNIX_EPOCH)
                             .unwrap()
                             .as_millis());
                             self.values.insert(msg.sender,msg.value);
-                            if self.sharing_complete_times.len() == (2*self.num_nodes/3)+1{
-                                // All nodes terminated sharing protocol
-                                let mut vec_times = Vec::new();
-                                for (_rep,time) in self.sharing_complete_times.iter(){
-                                    vec_times.push(time.clone()-self.start_time);
-                                }
-                                vec_times.sort();
-                                log::info!("All n nodes completed the sharing protocol {:?} {:?}",vec_times,self.values);
-                                self.start_time = SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap()
-                                .as_millis(); 
-                                self.broadcast(SyncMsg { sender: self.num_nodes, state: SyncState::StartRecon, value:0 }).await;
+                            let quorum = self.params.completion.sharing_quorum(self.active());
+                            if self.quorum_check(self.sharing_complete_times.len(), quorum){
+                                self.finish_sharing(false).await;
                             }
                         },
+                        SyncState::CompletedRecon if self.phase != Phase::Recon =>{
+                            log::warn!("Node {} reported reconstruction while no reconstruction is in progress",msg.sender);
+                        },
                         SyncState::CompletedRecon=>{
                             log::info!("Node {} completed the reconstruction phase of the protocol",msg.sender);
                             self.timings.insert(msg.sender, SystemTime::now()
                             .duration_since(UNIX_EPOCH)
                             .unwrap()
                             .as_millis());
-                            if self.timings.len() == self.num_nodes{
-                                // All nodes terminated protocol
-                                let mut vec_times = Vec::new();
-                                for (_rep,time) in self.timings.iter(){
-                                    vec_times.push(time.clone()-self.start_time);
-                                }
-                                vec_times.sort();
-                                log::info!("All n nodes completed the recon protocol {:?} {:?}",vec_times,self.values);
-                                self.broadcast(SyncMsg { sender: self.num_nodes, state: SyncState::STOP, value:0}).await;
+                            let quorum = self.params.completion.quorum(self.active());
+                            if self.quorum_check(self.timings.len(), quorum){
+                                self.finish_recon(false).await;
                             }
                         },
                         SyncState::COMPLETED=>{
@@ -154,17 +201,70 @@ impl Syncer{
                             .unwrap()
                             .as_millis());
                             self.values.insert(msg.sender,msg.value);
-                            if self.timings.len() == self.num_nodes{
-                                // All nodes terminated protocol
-                                let mut vec_times = Vec::new();
-                                for (_rep,time) in self.timings.iter(){
-                                    vec_times.push(time.clone()-self.start_time);
-                                }
-                                vec_times.sort();
-                                log::info!("All n nodes completed the protocol {:?} with values {:?}",vec_times,self.values);
-                                self.broadcast(SyncMsg { sender: self.num_nodes, state: SyncState::STOP, value:0}).await;
+                            let quorum = self.params.completion.quorum(self.active());
+                            if self.quorum_check(self.timings.len(), quorum){
+                                self.finish_run(false).await;
                             }
                         }
+                        SyncState::CompletionReport=>{
+                            match CompletionReport::from_bytes(&msg.payload){
+                                Ok(report) if report.session != self.current_session() =>{
+                                    log::warn!("Dropping completion report of node {} from session {}",msg.sender,report.session);
+                                },
+                                Ok(report)=>{
+                                    log::info!("Node {} finished {} rounds in {} ms with value {}",
+                                        msg.sender,report.summary.rounds_to_termination,report.summary.total_ms,report.value);
+                                    self.completion_reports.insert(msg.sender, report);
+                                },
+                                Err(e)=>{
+                                    log::warn!("Malformed completion report from node {}: {}",msg.sender,e);
+                                }
+                            }
+                        },
+                        SyncState::Stalled=>{
+                            match StallReport::from_bytes(&msg.payload){
+                                Ok(report) if report.session != self.current_session() =>{
+                                    log::warn!("Dropping stall report of node {} from session {}",msg.sender,report.session);
+                                },
+                                Ok(report)=>{
+                                    log::warn!("Node {} stalled in round {} for {} ms: {} RBCs and {} BAA instances open, waiting on witnesses from {:?}",
+                                        msg.sender,report.round,report.stalled_ms,report.rbcs.len(),report.baa.len(),report.missing_witnesses);
+                                    self.stall_reports.insert(msg.sender, report);
+                                },
+                                Err(e)=>{
+                                    log::warn!("Malformed stall report from node {}: {}",msg.sender,e);
+                                }
+                            }
+                        },
+                        SyncState::ReconfigFailed=>{
+                            log::error!("Node {} could not move to epoch {}: {}",msg.sender,msg.value,String::from_utf8_lossy(&msg.payload));
+                            log::error!("Stopping the experiment, the nodes disagree on the membership");
+                            self.enter_phase(Phase::Done);
+                            self.broadcast(SyncMsg { sender: self.num_nodes, state: SyncState::STOP, value:0, payload:Vec::new()}).await;
+                        },
+                        SyncState::TrafficReport=>{
+                            match TrafficReport::from_bytes(&msg.payload){
+                                Ok(report) if report.session != self.current_session() =>{
+                                    log::warn!("Dropping traffic report of node {} from session {}",msg.sender,report.session);
+                                },
+                                Ok(report)=>{
+                                    log::info!("Got traffic report from node {}",msg.sender);
+                                    self.traffic.insert(msg.sender, report);
+                                    // Rewritten on every report so a node that never completes does not hold back the rest
+                                    let summary = TrafficSummary::from_reports(self.traffic.values().cloned().collect());
+                                    let path = match &self.experiment{
+                                        Some(exp) => format!("traffic-run{}.json",exp.current),
+                                        None => "traffic.json".to_string(),
+                                    };
+                                    if let Err(e) = consensus::write_json(&path, &summary){
+                                        log::error!("Failed to write traffic summary: {}",e);
+                                    }
+                                },
+                                Err(e)=>{
+                                    log::warn!("Malformed traffic report from node {}: {}",msg.sender,e);
+                                }
+                            }
+                        },
                         _=>{}
                     }
                 },
@@ -172,8 +272,304 @@ impl Syncer{
         }
         Ok(())
     }
+    /// Writes the result file for the nodes that completed at `times`
+    fn write_report(&self, times:&HashMap<Replica,u128>, outcome:CompletionOutcome) -> RunReport{
+        let results = times.iter()
+            .filter_map(|(rep,time)| self.values.get(rep).map(|value| NodeResult{
+                node: *rep,
+                latency_ms: time.saturating_sub(self.start_time),
+                value: *value,
+            }))
+            .collect();
+        let node_reports = self.completion_reports.iter()
+            .filter(|(rep,_)| times.contains_key(rep))
+            .map(|(_,report)| report.clone())
+            .collect();
+        let mut params = self.params.clone();
+        params.session = self.current_session();
+        let mut path = self.report_path.clone();
+        if let Some(exp) = &self.experiment{
+            path = format!("{}-run{}",self.report_path,exp.current);
+        }
+        let stalls = self.stall_reports.values().cloned().collect();
+        let report = RunReport::new(params, self.start_time, results, outcome, node_reports, stalls);
+        log::info!("Decided values span [{:?}, {:?}], spread {:?}, within epsilon {}",
+            report.min_value, report.max_value, report.spread, report.within_epsilon);
+        match report.write(&path){
+            Ok(()) => log::info!("Wrote run report to {}.json and {}.csv",path,path),
+            Err(e) => log::error!("Failed to write run report to {}: {}",path,e),
+        }
+        report
+    }
+
+    /// Session of the run in progress
+    fn current_session(&self) -> u64{
+        match &self.experiment{
+            Some(exp) => exp.session(exp.current),
+            None => self.params.session,
+        }
+    }
+
+    /// Moves to `phase` and arms the timeout of the phase, if any
+    fn enter_phase(&mut self, phase:Phase){
+        self.phase = phase;
+        self.deadline = match phase{
+            Phase::Running | Phase::Recon => self.params.completion.timeout_ms
+                .map(|timeout| self.start_time + timeout as u128),
+            Phase::Idle | Phase::Done => None,
+        };
+    }
+
+    fn until_deadline(&self) -> Duration{
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let remaining = self.deadline.unwrap_or(now).saturating_sub(now);
+        Duration::from_millis(remaining as u64)
+    }
+
+    /// Called with the number of completions after each one arrives.
+    /// Returns whether the phase is over: every node completed, or the
+    /// quorum was reached and there is no grace period to wait out.
+    fn quorum_check(&mut self, completed:usize, quorum:usize) -> bool{
+        if completed == self.active(){
+            return true;
+        }
+        if completed == quorum{
+            let grace = self.params.completion.grace_ms;
+            if grace == 0{
+                return true;
+            }
+            log::info!("{} of {} nodes completed, waiting {} ms for the rest",completed,self.active(),grace);
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+            let grace_end = now + grace as u128;
+            self.deadline = Some(self.deadline.map_or(grace_end, |d| d.min(grace_end)));
+        }
+        false
+    }
+
+    /// The timeout or grace period of the current phase ran out
+    async fn on_deadline(&mut self){
+        self.deadline = None;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let timed_out = self.params.completion.timeout_ms
+            .map(|timeout| now >= self.start_time + timeout as u128)
+            .unwrap_or(false);
+        match self.phase{
+            // Only the sharing path of hyb reports CompletedSharing
+            Phase::Running if !self.sharing_complete_times.is_empty() => self.finish_sharing(timed_out).await,
+            Phase::Running => self.finish_run(timed_out).await,
+            Phase::Recon => self.finish_recon(timed_out).await,
+            Phase::Idle | Phase::Done => {}
+        }
+    }
+
+    /// How the members fared; revoked replicas are not missing
+    fn classify(&self, times:&HashMap<Replica,u128>, quorum:usize, timed_out:bool) -> CompletionOutcome{
+        let mut outcome = CompletionOutcome::classify(times, self.num_nodes, quorum, timed_out);
+        outcome.missing.retain(|rep| !self.revoked.contains(rep));
+        outcome
+    }
+
+    fn log_outcome(&self, what:&str, times:&HashMap<Replica,u128>, outcome:&CompletionOutcome){
+        let mut vec_times = Vec::new();
+        for (_rep,time) in times.iter(){
+            vec_times.push(time.saturating_sub(self.start_time));
+        }
+        vec_times.sort();
+        if outcome.timed_out{
+            log::warn!("The {} timed out with {} of {} nodes complete, quorum {}",what,times.len(),self.active(),outcome.quorum);
+        }
+        log::info!("{} of {} nodes completed the {} {:?} with values {:?}",times.len(),self.active(),what,vec_times,self.values);
+        if !outcome.late.is_empty() || !outcome.missing.is_empty(){
+            log::warn!("Late nodes: {:?}, missing nodes: {:?}",outcome.late,outcome.missing);
+        }
+    }
+
+    async fn finish_sharing(&mut self, timed_out:bool){
+        let quorum = self.params.completion.sharing_quorum(self.active());
+        let outcome = self.classify(&self.sharing_complete_times, quorum, timed_out);
+        self.log_outcome("sharing protocol", &self.sharing_complete_times, &outcome);
+        let report = self.write_report(&self.sharing_complete_times, outcome);
+        if self.experiment.is_some(){
+            self.next_run(report).await;
+            return;
+        }
+        self.start_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis(); 
+        self.enter_phase(Phase::Recon);
+        self.broadcast(SyncMsg { sender: self.num_nodes, state: SyncState::StartRecon, value:0, payload:Vec::new() }).await;
+    }
+
+    async fn finish_recon(&mut self, timed_out:bool){
+        let quorum = self.params.completion.quorum(self.active());
+        let outcome = self.classify(&self.timings, quorum, timed_out);
+        self.log_outcome("recon protocol", &self.timings, &outcome);
+        self.enter_phase(Phase::Done);
+        self.broadcast(SyncMsg { sender: self.num_nodes, state: SyncState::STOP, value:0, payload:Vec::new()}).await;
+    }
+
+    async fn finish_run(&mut self, timed_out:bool){
+        let quorum = self.params.completion.quorum(self.active());
+        let outcome = self.classify(&self.timings, quorum, timed_out);
+        self.log_outcome("protocol", &self.timings, &outcome);
+        let report = self.write_report(&self.timings, outcome);
+        if self.experiment.is_some(){
+            self.next_run(report).await;
+            return;
+        }
+        self.enter_phase(Phase::Done);
+        self.broadcast(SyncMsg { sender: self.num_nodes, state: SyncState::STOP, value:0, payload:Vec::new()}).await;
+    }
+
+    /// Sends every node its input and session for the current run, and
+    /// the new membership if it changes with this run
+    async fn reset_nodes(&mut self){
+        let (specs, membership) = match self.experiment.as_mut(){
+            Some(exp) => {
+                exp.armed = true;
+                log::info!("Resetting the nodes for run {} of {}",exp.current+1,exp.runs());
+                let membership = exp.schedule.at(exp.current).cloned();
+                let num_nodes = membership.as_ref().map_or(self.num_nodes, |m| m.num_nodes);
+                ((0..num_nodes).map(|rep| exp.spec(exp.current, rep)).collect::<Vec<_>>(), membership)
+            },
+            None => return,
+        };
+        self.alive.clear();
+        self.timings.clear();
+        self.values.clear();
+        self.sharing_complete_times.clear();
+        self.completion_reports.clear();
+        self.stall_reports.clear();
+        self.traffic.clear();
+        if let Some(membership) = membership{
+            // The replicas that leave learn it from their RESET
+            let leaving: Vec<Replica> = self.members().filter(|rep| !membership.is_member(*rep)).collect();
+            for replica in leaving{
+                let cancel_handler = self.net_send.send(replica, SyncMsg {
+                    sender: self.num_nodes,
+                    state: SyncState::RESET,
+                    value: specs[replica].input,
+                    payload: specs[replica].to_bytes()
+                }).await;
+                self.add_cancel_handler(cancel_handler);
+            }
+            if let Err(e) = self.reconfigure(&membership){
+                log::error!("Unable to move to epoch {}: {}, stopping the experiment",membership.epoch,e);
+                self.enter_phase(Phase::Done);
+                self.broadcast(SyncMsg { sender: self.num_nodes, state: SyncState::STOP, value:0, payload:Vec::new()}).await;
+                return;
+            }
+        }
+        let specs: Vec<_> = specs.into_iter().enumerate()
+            .filter(|(replica, _)| self.is_member(*replica))
+            .collect();
+        for (replica, spec) in specs{
+            let cancel_handler = self.net_send.send(replica, SyncMsg {
+                sender: self.num_nodes,
+                state: SyncState::RESET,
+                value: spec.input,
+                payload: spec.to_bytes()
+            }).await;
+            self.add_cancel_handler(cancel_handler);
+        }
+    }
+
+    /// Records a finished run, then either starts the next one or writes
+    /// the summary and stops the nodes
+    async fn next_run(&mut self, report:RunReport){
+        let reports = match self.experiment.as_mut(){
+            Some(exp) => {
+                exp.reports.push(report);
+                exp.current += 1;
+                exp.armed = false;
+                if !exp.is_done(){
+                    self.enter_phase(Phase::Idle);
+                    self.reset_nodes().await;
+                    return;
+                }
+                exp.reports.clone()
+            },
+            None => return,
+        };
+        self.enter_phase(Phase::Done);
+        let summary = ExperimentSummary::new(reports);
+        log::info!("Experiment finished: {} of {} runs within epsilon, run latency {:?}, spread {:?}",
+            summary.runs_within_epsilon, summary.runs, summary.run_latency_ms, summary.spread);
+        let path = format!("{}-summary.json",self.report_path);
+        match consensus::write_json(&path, &summary){
+            Ok(()) => log::info!("Wrote experiment summary to {}",path),
+            Err(e) => log::error!("Failed to write experiment summary to {}: {}",path,e),
+        }
+        self.broadcast(SyncMsg { sender: self.num_nodes, state: SyncState::STOP, value:0, payload:Vec::new()}).await;
+    }
+
+    /// Rereads where the nodes listen and talks to the members of the new
+    /// epoch only
+    fn reconfigure(&mut self, membership:&Membership) -> Result<()>{
+        let mut net_map = FnvHashMap::default();
+        for (replica, ip) in read_lines(&self.net_file)?.into_iter().enumerate(){
+            net_map.insert(replica, ip);
+        }
+        if net_map.len() < membership.num_nodes{
+            return Err(anyhow!("{} lists {} nodes, epoch {} has {}",self.net_file,net_map.len(),membership.epoch,membership.num_nodes));
+        }
+        net_map.retain(|replica, _| *replica < membership.num_nodes);
+        self.params.completion.validate(membership.members().count())
+            .map_err(|e| anyhow!(e))?;
+        log::info!("Moving to epoch {} with {} nodes tolerating {} faults, revoked {:?}",
+            membership.epoch,membership.num_nodes,membership.num_faults,membership.revoked);
+        // Dropping the old sender closes the connections to the nodes that left
+        self.net_send = connect(&net_map, &membership.revoked)?;
+        self.net_map = net_map;
+        self.num_nodes = membership.num_nodes;
+        self.revoked = membership.revoked.clone();
+        self.epoch = membership.epoch;
+        self.params.num_nodes = membership.num_nodes;
+        self.params.revoked = membership.revoked.clone();
+        Ok(())
+    }
+
+    pub fn is_member(&self, replica:Replica) -> bool{
+        replica < self.num_nodes && !self.revoked.contains(&replica)
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = Replica> + '_{
+        (0..self.num_nodes).filter(move |rep| !self.revoked.contains(rep))
+    }
+
+    /// Number of members
+    pub fn active(&self) -> usize{
+        self.num_nodes - self.revoked.len()
+    }
+
     pub fn add_cancel_handler(&mut self, canc: CancelHandler<Acknowledgement>){
         self.cancel_handlers
             .push(canc);
     }
-}
\ No newline at end of file
+}
+
+/// A sender to every node of `net_map` that is not revoked
+fn connect(net_map:&FnvHashMap<Replica,String>, revoked:&[Replica]) -> Result<TcpReliableSender<Replica,SyncMsg,Acknowledgement>>{
+    let mut server_addrs :FnvHashMap<Replica,SocketAddr>= FnvHashMap::default();
+    for (replica,address) in net_map.iter().filter(|(rep,_)| !revoked.contains(rep)){
+        let address = config::resolve(address)
+            .map_err(|e| anyhow!("Unable to resolve the address {} of node {}: {}",address,replica,e))?;
+        server_addrs.insert(*replica, address);
+    }
+    Ok(TcpReliableSender::<Replica,SyncMsg,Acknowledgement>::with_peers(server_addrs))
+}
+
+/// The non-empty lines of `path`
+pub fn read_lines(path: &str) -> Result<Vec<String>> {
+    let f = File::open(path)
+        .with_context(|| format!("Unable to open {}", path))?;
+    let mut lines = Vec::new();
+    for line in BufReader::new(f).lines() {
+        let line = line.with_context(|| format!("Unable to read {}", path))?;
+        if !line.trim().is_empty() {
+            lines.push(line.trim().to_string());
+        }
+    }
+    Ok(lines)
+}
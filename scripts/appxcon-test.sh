@@ -14,30 +14,31 @@ sleep=$1
 st_time=$((curr_date+sleep))
 echo $st_time
 # Run the syncer now
-./target/$TYPE/node \
+./target/$TYPE/node syncer \
     --config $TESTDIR/nodes-0.json \
     --ip ip_file \
-    --sleep $st_time \
-    --vsstype sync \
+    --session $st_time \
+    --protocol $2 \
+    --report logs/report \
     --epsilon 10 \
     --delta 5000 \
-    --val 100 \
     --tri $tri \
-    --syncer $3 \
-    --batch $4 > logs/syncer.log &
+    --syncer $3 > logs/syncer.log &
 
+# Only hyb takes delta and tri
+hyb_args=""
+if [ "$2" == "hyb" ]; then
+    hyb_args="--delta 10 --tri $tri"
+fi
 for((i=0;i<4;i++)); do
-./target/$TYPE/node \
+./target/$TYPE/node run-$2 \
     --config $TESTDIR/nodes-$i.json \
     --ip ip_file \
     --sleep $st_time \
+    --session $st_time \
     --epsilon 10 \
-    --delta 10 \
     --val ${vals[$i]} \
-    --tri $tri \
-    --vsstype $2 \
-    --syncer $3 \
-    --batch $4 > logs/$i.log &
+    $hyb_args > logs/$i.log &
 done
 
 # Client has finished; Kill the nodes
@@ -0,0 +1,180 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+/// A machine of the deployment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    pub addr: IpAddr,
+    /// Most nodes the host runs; no limit when unset
+    pub slots: Option<usize>,
+}
+
+/// Reads a hosts inventory: one host per line, its address optionally
+/// followed by the most nodes it may run, e.g. `10.42.0.232 2`. Blank lines
+/// and everything after a `#` are ignored.
+pub fn read_inventory(path: &str) -> Result<Vec<Host>, String> {
+    let f = File::open(path)
+        .map_err(|e| format!("unable to read the hosts inventory {}: {}", path, e))?;
+    let mut hosts = Vec::new();
+    for (lineno, line) in BufReader::new(f).lines().enumerate() {
+        let line = line.map_err(|e| format!("unable to read {}: {}", path, e))?;
+        let line = line.split('#').next().unwrap_or("");
+        let mut fields = line.split_whitespace();
+        let addr = match fields.next() {
+            Some(addr) => addr,
+            None => continue,
+        };
+        let addr: IpAddr = addr.parse()
+            .map_err(|_| format!("{}:{}: {} is not an IP address", path, lineno+1, addr))?;
+        let slots = fields.next()
+            .map(|s| s.parse::<usize>()
+                .map_err(|_| format!("{}:{}: {} is not a number of nodes", path, lineno+1, s)))
+            .transpose()?;
+        if let Some(extra) = fields.next() {
+            return Err(format!("{}:{}: unexpected {}", path, lineno+1, extra));
+        }
+        if hosts.iter().any(|h: &Host| h.addr == addr) {
+            return Err(format!("{}:{}: {} is listed twice", path, lineno+1, addr));
+        }
+        hosts.push(Host { addr, slots });
+    }
+    if hosts.is_empty() {
+        return Err(format!("the hosts inventory {} lists no hosts", path));
+    }
+    Ok(hosts)
+}
+
+/// Where the nodes and the syncer of a deployment run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topology {
+    /// Address node i listens to the other nodes on
+    pub nodes: Vec<SocketAddr>,
+    /// Port node i listens to the syncer on, on the host of `nodes[i]`
+    pub client_ports: Vec<u16>,
+    /// Address the syncer listens to the nodes on
+    pub syncer: SocketAddr,
+}
+
+impl Topology {
+    /// Every node and the syncer on this machine, node i on base_port+i
+    pub fn local(num_nodes: usize, base_port: u16, client_base_port: u16, syncer_port: u16) -> Result<Topology, String> {
+        let host = Host { addr: IpAddr::V4(Ipv4Addr::LOCALHOST), slots: None };
+        Topology::spread(&[host], None, num_nodes, base_port, client_base_port, syncer_port)
+    }
+
+    /// Deals the nodes out to `hosts` in turn, skipping hosts that are
+    /// full. The k-th node on a host listens on base_port+k and
+    /// client_base_port+k. The syncer runs on `syncer_host`, by default the
+    /// host of node 0.
+    pub fn spread(
+        hosts: &[Host],
+        syncer_host: Option<IpAddr>,
+        num_nodes: usize,
+        base_port: u16,
+        client_base_port: u16,
+        syncer_port: u16,
+    ) -> Result<Topology, String> {
+        let capacity: Option<usize> = hosts.iter().map(|h| h.slots).sum();
+        if let Some(capacity) = capacity {
+            if capacity < num_nodes {
+                return Err(format!("the hosts have room for {} nodes, not {}", capacity, num_nodes));
+            }
+        }
+        let mut placed = vec![0usize; hosts.len()];
+        let mut next = 0;
+        let mut nodes = Vec::with_capacity(num_nodes);
+        let mut client_ports = Vec::with_capacity(num_nodes);
+        for _ in 0..num_nodes {
+            while hosts[next].slots.is_some_and(|slots| placed[next] >= slots) {
+                next = (next+1) % hosts.len();
+            }
+            let k = placed[next];
+            let port = |base: u16| base.checked_add(k as u16)
+                .ok_or_else(|| format!("port {}+{} is out of range", base, k));
+            nodes.push(SocketAddr::new(hosts[next].addr, port(base_port)?));
+            client_ports.push(port(client_base_port)?);
+            placed[next] += 1;
+            next = (next+1) % hosts.len();
+        }
+        let syncer_host = syncer_host
+            .or_else(|| nodes.first().map(|a| a.ip()))
+            .unwrap_or(hosts[0].addr);
+        let topology = Topology {
+            nodes,
+            client_ports,
+            syncer: SocketAddr::new(syncer_host, syncer_port),
+        };
+        topology.check_ports()?;
+        Ok(topology)
+    }
+
+    /// No two listeners on one host may share a port
+    fn check_ports(&self) -> Result<(), String> {
+        let mut used = BTreeSet::new();
+        let listeners = self.nodes.iter().copied()
+            .chain(self.nodes.iter().zip(&self.client_ports).map(|(a, p)| SocketAddr::new(a.ip(), *p)))
+            .chain(std::iter::once(self.syncer));
+        for addr in listeners {
+            if !used.insert(addr) {
+                return Err(format!("{} is allocated twice; move the base ports further apart", addr));
+            }
+        }
+        Ok(())
+    }
+
+    /// The hosts that run a node or the syncer, in order of first use
+    pub fn hosts(&self) -> Vec<IpAddr> {
+        let mut hosts: Vec<IpAddr> = Vec::new();
+        for ip in self.nodes.iter().map(|a| a.ip()).chain(std::iter::once(self.syncer.ip())) {
+            if !hosts.contains(&ip) {
+                hosts.push(ip);
+            }
+        }
+        hosts
+    }
+
+    /// The nodes on `host`
+    pub fn nodes_on(&self, host: IpAddr) -> Vec<usize> {
+        (0..self.nodes.len()).filter(|i| self.nodes[*i].ip() == host).collect()
+    }
+
+    /// Every node followed by the syncer, as the nodes read it with `--ip`
+    pub fn ip_lines(&self) -> Vec<String> {
+        self.nodes.iter()
+            .chain(std::iter::once(&self.syncer))
+            .map(|a| a.to_string())
+            .collect()
+    }
+
+    /// Where the syncer reaches each node, as it reads it with `--syncer`
+    pub fn syncer_lines(&self) -> Vec<String> {
+        self.nodes.iter().zip(&self.client_ports)
+            .map(|(a, p)| SocketAddr::new(a.ip(), *p).to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spreads_nodes_over_hosts() {
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+        let hosts = [Host { addr: a, slots: Some(1) }, Host { addr: b, slots: None }];
+        let topology = Topology::spread(&hosts, None, 4, 8500, 10000, 5000).unwrap();
+        assert_eq!(topology.ip_lines(), vec![
+            "10.0.0.1:8500", "10.0.0.2:8500", "10.0.0.2:8501", "10.0.0.2:8502", "10.0.0.1:5000"]);
+        assert_eq!(topology.syncer_lines()[3], "10.0.0.2:10002");
+        assert_eq!(topology.nodes_on(b), vec![1, 2, 3]);
+        assert_eq!(topology.hosts(), vec![a, b]);
+
+        let full = [Host { addr: a, slots: Some(1) }, Host { addr: b, slots: Some(2) }];
+        assert!(Topology::spread(&full, None, 4, 8500, 10000, 5000).is_err());
+        // The client ports of the third node collide with the base ports
+        assert!(Topology::spread(&hosts, Some(b), 4, 8500, 8502, 5000).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+use rand::distributions::{Distribution, Normal};
+use serde::Serialize;
+use types::Replica;
+
+/// How the node inputs are drawn
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputModel {
+    /// Every node draws uniformly from the input range
+    Uniform,
+    /// Honest nodes draw uniformly within `spread` of `center`
+    Clustered { center: u64, spread: u64 },
+    /// Nodes sit row by row on a square grid `spacing` apart and read the
+    /// signal of a source at `target`: `strength` dB less 20log10 of the
+    /// distance, plus Gaussian noise of deviation `noise`. Inputs are the
+    /// readings in thousandths of a dB.
+    Sensor { spacing: f64, target: (f64, f64), strength: f64, noise: f64 },
+}
+
+impl InputModel {
+    /// The middle of the sensor grid of `num_nodes` nodes
+    pub fn grid_center(num_nodes: usize, spacing: f64) -> (f64, f64) {
+        let side = (num_nodes as f64).sqrt().ceil();
+        let middle = spacing*(side-1.0).max(0.0)/2.0;
+        (middle, middle)
+    }
+}
+
+/// One node's input and where it came from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeInput {
+    pub id: Replica,
+    pub input: u64,
+    /// Given an arbitrary value from the input range instead of one from
+    /// the model, as a Byzantine node could
+    pub outlier: bool,
+    /// Where the sensor sits
+    pub position: Option<(f64, f64)>,
+    /// What the node would read without noise: the cluster center, or the
+    /// noiseless sensor reading
+    pub truth: Option<u64>,
+}
+
+/// The generated inputs of a config set and what they were drawn from,
+/// written next to the configs so a run can be reproduced and checked
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroundTruth {
+    pub seed: u64,
+    pub model: InputModel,
+    /// Outliers, and every node of the uniform model, draw from [low, high)
+    pub range: (u64, u64),
+    pub nodes: Vec<NodeInput>,
+    /// Smallest and largest honest input; the agreed values must lie
+    /// between them
+    pub honest_min: u64,
+    pub honest_max: u64,
+}
+
+impl GroundTruth {
+    /// Draws the inputs of `num_nodes` nodes from `model`, giving
+    /// `outliers` nodes picked at random a value from `range` instead
+    pub fn generate(seed: u64, model: InputModel, range: (u64, u64), num_nodes: usize, outliers: usize) -> Result<GroundTruth, String> {
+        let (low, high) = range;
+        if low >= high {
+            return Err(format!("empty input range [{}, {})", low, high));
+        }
+        if outliers > num_nodes {
+            return Err(format!("{} outliers among {} nodes", outliers, num_nodes));
+        }
+        if let InputModel::Sensor { spacing, noise, .. } = model {
+            if spacing.is_nan() || spacing <= 0.0 || noise.is_nan() || noise < 0.0 {
+                return Err(format!("sensor spacing {} must be positive and noise {} not negative", spacing, noise));
+            }
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut ids: Vec<Replica> = (0..num_nodes).collect();
+        ids.shuffle(&mut rng);
+        let mut outlier = vec![false; num_nodes];
+        for id in &ids[..outliers] {
+            outlier[*id] = true;
+        }
+        let side = (num_nodes as f64).sqrt().ceil() as usize;
+        let noise = match &model {
+            InputModel::Sensor { noise, .. } => Some(Normal::new(0.0, *noise)),
+            _ => None,
+        };
+        let nodes: Vec<NodeInput> = (0..num_nodes).map(|id| {
+            let (input, position, truth) = match &model {
+                InputModel::Uniform => (rng.gen_range(low, high), None, None),
+                InputModel::Clustered { center, spread } => {
+                    let lo = center.saturating_sub(*spread);
+                    let hi = center.saturating_add(*spread).saturating_add(1);
+                    (rng.gen_range(lo, hi), None, Some(*center))
+                },
+                InputModel::Sensor { spacing, target, strength, .. } => {
+                    let position = (spacing*(id % side) as f64, spacing*(id / side) as f64);
+                    let distance = ((position.0-target.0).powi(2) + (position.1-target.1).powi(2)).sqrt();
+                    // Within a unit of the source the reading saturates
+                    let reading = strength - 20.0*distance.max(1.0).log10();
+                    let noisy = reading + noise.unwrap().sample(&mut rng);
+                    (milli(noisy), Some(position), Some(milli(reading)))
+                },
+            };
+            // Drawn for every node so the honest inputs do not depend on
+            // how many outliers there are
+            let arbitrary = rng.gen_range(low, high);
+            NodeInput {
+                id,
+                input: if outlier[id] { arbitrary } else { input },
+                outlier: outlier[id],
+                position,
+                truth,
+            }
+        }).collect();
+        let honest = nodes.iter().filter(|n| !n.outlier).map(|n| n.input);
+        let honest_min = honest.clone().min().unwrap_or(0);
+        let honest_max = honest.max().unwrap_or(0);
+        Ok(GroundTruth { seed, model, range, nodes, honest_min, honest_max })
+    }
+
+    pub fn inputs(&self) -> Vec<u64> {
+        self.nodes.iter().map(|n| n.input).collect()
+    }
+
+    /// The inputs as one comma-separated line, the format the syncer reads
+    /// with `--inputs`
+    pub fn inputs_line(&self) -> String {
+        self.inputs().iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+fn milli(db: f64) -> u64 {
+    (db*1000.0).round().max(0.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_inputs_are_reproducible() {
+        let model = InputModel::Clustered { center: 5000, spread: 10 };
+        let truth = GroundTruth::generate(7, model.clone(), (0, 1_000_000), 7, 2).unwrap();
+        assert_eq!(truth, GroundTruth::generate(7, model.clone(), (0, 1_000_000), 7, 2).unwrap());
+        assert_eq!(truth.nodes.iter().filter(|n| n.outlier).count(), 2);
+        assert!(truth.honest_min >= 4990 && truth.honest_max <= 5010);
+        // The outliers do not move the honest inputs
+        let clean = GroundTruth::generate(7, model, (0, 1_000_000), 7, 0).unwrap();
+        for (node, clean) in truth.nodes.iter().zip(clean.nodes.iter()) {
+            if !node.outlier {
+                assert_eq!(node.input, clean.input);
+            }
+        }
+
+        let sensors = InputModel::Sensor { spacing: 5.0, target: (0.0, 0.0), strength: 75.0, noise: 0.0 };
+        let truth = GroundTruth::generate(1, sensors, (0, 100_000), 4, 0).unwrap();
+        // Nodes 1 and 2 are one grid step from the source at node 0
+        assert_eq!(truth.inputs(), vec![75_000, 61_021, 61_021, 58_010]);
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use clap::ArgMatches;
+use config::{Client, Node, Passphrase, join_host_port, split_host_port};
+use crypto::{Algorithm, ed25519, secp256k1};
+use types::{ProtocolId, Replica};
+use util::io::write_json;
+use zeroize::Zeroizing;
+
+use crate::{write_config, write_lines};
+
+/// The format genconfig writes for a config file extension
+fn out_type(ext: &str) -> Option<&'static str> {
+    match ext {
+        "json" => Some("json"),
+        "dat" => Some("binary"),
+        "toml" => Some("toml"),
+        "yml" | "yaml" => Some("yaml"),
+        _ => None,
+    }
+}
+
+/// The id and extension of a node config genconfig wrote, from its file name
+fn node_file(name: &str) -> Option<(Replica, &str)> {
+    let (stem, ext) = name.strip_prefix("nodes-")?.split_once('.')?;
+    out_type(ext)?;
+    Some((stem.parse().ok()?, ext))
+}
+
+/// A fresh key to share between two nodes
+fn shared_key() -> Vec<u8> {
+    secp256k1::SecretKey::generate().to_bytes().to_vec()
+}
+
+/// A fresh public and secret key
+fn keypair(alg: &Algorithm) -> Result<(Vec<u8>, Vec<u8>), Box<dyn Error>> {
+    match alg {
+        Algorithm::ED25519 => {
+            let kp = ed25519::Keypair::generate();
+            Ok((kp.public().encode().to_vec(), kp.encode().to_vec()))
+        },
+        Algorithm::SECP256K1 => {
+            let kp = secp256k1::Keypair::generate();
+            Ok((kp.public().encode().to_vec(), kp.secret().to_bytes().to_vec()))
+        },
+        _ => Err(format!("no keypairs for {:?}", alg).into()),
+    }
+}
+
+/// The node configs genconfig wrote to a directory, and the client config
+/// if there is one
+struct ConfigSet {
+    dir: String,
+    ext: String,
+    nodes: BTreeMap<Replica, Node>,
+    client: Option<Client>,
+    /// The passphrase the secrets were encrypted with, to encrypt them
+    /// again on save
+    passphrase: Option<Zeroizing<Vec<u8>>>,
+}
+
+impl ConfigSet {
+    /// Loads the set, decrypting the secrets with a passphrase from
+    /// `passphrase` if they are encrypted
+    fn open(dir: &str, passphrase: &Passphrase) -> Result<ConfigSet, Box<dyn Error>> {
+        let mut nodes = BTreeMap::new();
+        let mut ext = None;
+        let entries = fs::read_dir(dir)
+            .map_err(|e| format!("unable to read the config set in {}: {}", dir, e))?;
+        for entry in entries {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            let (id, this_ext) = match node_file(name) {
+                Some(parts) => parts,
+                None => continue,
+            };
+            if ext.get_or_insert_with(|| this_ext.to_string()) != this_ext {
+                return Err(format!("{} mixes .{} and .{} configs", dir, ext.unwrap(), this_ext).into());
+            }
+            let node = Node::load_sealed(&path.display().to_string())?;
+            if node.id != id {
+                return Err(format!("{} holds the config of node {}", path.display(), node.id).into());
+            }
+            nodes.insert(id, node);
+        }
+        let ext = ext.ok_or_else(|| format!("no node configs in {}", dir))?;
+        // Read once for the whole set
+        let secret = match nodes.values().any(Node::is_sealed) {
+            true => Some(passphrase.read().map_err(|e| e.to_string())?),
+            false => None,
+        };
+        if let Some(secret) = &secret {
+            for node in nodes.values_mut() {
+                node.unseal(secret)
+                    .map_err(|e| format!("unable to decrypt the secrets of node {}: {}", node.id, e))?;
+            }
+        }
+        let client_path = format!("{}/client.{}", dir, ext);
+        let client = match Path::new(&client_path).exists() {
+            true => Some(Client::load(&client_path)?),
+            false => None,
+        };
+        Ok(ConfigSet { dir: dir.to_string(), ext, nodes, client, passphrase: secret })
+    }
+
+    fn any(&self) -> &Node {
+        self.nodes.values().next().expect("a config set has at least one node")
+    }
+
+    fn node_mut(&mut self, id: Replica) -> Result<&mut Node, Box<dyn Error>> {
+        let dir = &self.dir;
+        self.nodes.get_mut(&id)
+            .ok_or_else(|| format!("{} has no config for node {}", dir, id).into())
+    }
+
+    /// Adds node n with fresh keys. The syncer's entry in every net_map
+    /// moves from n to n+1 to make room.
+    fn add_node(&mut self, address: Option<&str>, client_port: Option<u16>, value: Option<u64>) -> Result<Replica, Box<dyn Error>> {
+        let template = self.any().clone();
+        let id = template.num_nodes;
+        let syncer = template.net_map.get(&id).cloned()
+            .ok_or_else(|| format!("net_map of node {} has no syncer entry", template.id))?;
+        let address = match address {
+            Some(address) => address.to_string(),
+            // The port after the highest node's, on its host
+            None => {
+                let last = template.net_map.get(&(id-1))
+                    .ok_or_else(|| format!("net_map of node {} has no entry for node {}", template.id, id-1))?;
+                let (host, port) = split_host_port(last)
+                    .ok_or_else(|| format!("unable to parse the address {} of node {}", last, id-1))?;
+                join_host_port(host, port+1)
+            },
+        };
+        let client_port = match client_port {
+            Some(port) => port,
+            None => self.nodes.values().map(|n| n.client_port).max().unwrap_or(0) + 1,
+        };
+        let mut node = template.clone();
+        node.id = id;
+        node.client_port = client_port;
+        node.protocol = template.protocol.map(|p| p.with_value(value.unwrap_or(p.value())));
+        node.sk_map.clear();
+        node.secret_key_bytes.clear();
+        let pk = match template.crypto_alg {
+            Algorithm::NOPKI => None,
+            ref alg => {
+                let (pk, sk) = keypair(alg)?;
+                node.secret_key_bytes = sk;
+                Some(pk)
+            },
+        };
+        for other in self.nodes.values_mut() {
+            if template.crypto_alg == Algorithm::NOPKI {
+                let key = shared_key();
+                other.sk_map.insert(id, key.clone());
+                node.sk_map.insert(other.id, key);
+            }
+        }
+        if template.crypto_alg == Algorithm::NOPKI {
+            node.sk_map.insert(id, shared_key());
+        }
+        self.nodes.insert(id, node);
+        for other in self.nodes.values_mut() {
+            other.num_nodes = id+1;
+            other.net_map.insert(id+1, syncer.clone());
+            other.net_map.insert(id, address.clone());
+            if let Some(pk) = &pk {
+                other.pk_map.insert(id, pk.clone());
+            }
+        }
+        if let Some(client) = &mut self.client {
+            let host = split_host_port(&address).map(|(h, _)| h).unwrap_or("127.0.0.1");
+            client.num_nodes = id+1;
+            client.net_map.insert(id, join_host_port(host, client_port));
+            if let Some(pk) = &pk {
+                client.server_pk.insert(id, pk.clone());
+            }
+        }
+        Ok(id)
+    }
+
+    /// Replaces every key of node `id`: its keypair, or each key it shares
+    fn rotate(&mut self, id: Replica) -> Result<(), Box<dyn Error>> {
+        let alg = self.node_mut(id)?.crypto_alg.clone();
+        if alg == Algorithm::NOPKI {
+            let peers: Vec<Replica> = self.nodes.keys().copied().filter(|j| *j != id).collect();
+            for j in peers {
+                let key = shared_key();
+                self.node_mut(j)?.sk_map.insert(id, key.clone());
+                self.node_mut(id)?.sk_map.insert(j, key);
+            }
+            self.node_mut(id)?.sk_map.insert(id, shared_key());
+            return Ok(());
+        }
+        let (pk, sk) = keypair(&alg)?;
+        self.node_mut(id)?.secret_key_bytes = sk;
+        for node in self.nodes.values_mut() {
+            node.pk_map.insert(id, pk.clone());
+        }
+        if let Some(client) = &mut self.client {
+            client.server_pk.insert(id, pk);
+        }
+        Ok(())
+    }
+
+    /// Drops node `id` and every key of it. It keeps its id and counts
+    /// against num_faults from then on.
+    fn revoke(&mut self, id: Replica) -> Result<(), Box<dyn Error>> {
+        self.node_mut(id)?;
+        self.nodes.remove(&id);
+        for node in self.nodes.values_mut() {
+            node.sk_map.remove(&id);
+            node.pk_map.remove(&id);
+            node.revoked.push(id);
+            node.revoked.sort_unstable();
+        }
+        if let Some(client) = &mut self.client {
+            client.server_pk.remove(&id);
+        }
+        Ok(())
+    }
+
+    /// Checks the set as genconfig does and writes it back, encrypted if it
+    /// was, deleting the configs of revoked nodes
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let mut nodes: Vec<Node> = self.nodes.values().cloned().collect();
+        for node in nodes.iter() {
+            node.validate_for(node.protocol.map(|p| p.id()).unwrap_or(ProtocolId::AppxCon))
+                .map_err(|e| format!("node {}: {}", node.id, e))?;
+        }
+        Node::validate_set(&nodes)?;
+        if let Some(secret) = &self.passphrase {
+            for node in nodes.iter_mut() {
+                node.seal(secret)?;
+            }
+        }
+        let out = out_type(&self.ext).expect("only known extensions are loaded");
+        for node in nodes.iter() {
+            write_config(&format!("{}/nodes-{}", self.dir, node.id), node, out);
+        }
+        for id in self.any().revoked.iter() {
+            let path = format!("{}/nodes-{}.{}", self.dir, id, self.ext);
+            if Path::new(&path).exists() {
+                fs::remove_file(&path)?;
+            }
+        }
+        // Where the syncer reaches each node
+        let syncer_lines = self.client.as_ref().map(|client| (0..client.num_nodes)
+            .filter_map(|i| client.net_map.get(&i).cloned())
+            .collect::<Vec<String>>());
+        if let Some(client) = &self.client {
+            client.validate()?;
+            write_config(&format!("{}/client", self.dir), client, out);
+            if Path::new(&format!("{}/syncer", self.dir)).exists() {
+                write_lines(&format!("{}/syncer", self.dir), syncer_lines.as_deref().unwrap_or_default())?;
+                write_json(format!("{}/syncer.json", self.dir), &client.net_map);
+            }
+        }
+        // Every node and the syncer, as genconfig writes it to the working
+        // directory; revoked nodes keep their line
+        let any = self.any();
+        let ip_lines: Vec<String> = (0..=any.num_nodes)
+            .filter_map(|i| any.net_map.get(&i).cloned())
+            .collect();
+        if Path::new("ip_file").exists() {
+            write_lines("ip_file", &ip_lines)?;
+        }
+        self.save_bundles(&nodes, out, &ip_lines, syncer_lines.as_deref())
+    }
+
+    /// Rewrites the per-host bundles in `<dir>/hosts`, if genconfig wrote
+    /// them: every host gets the configs of the nodes now on it and the new
+    /// ip_file, so no bundle keeps the config of a revoked node or old keys
+    fn save_bundles(&self, nodes: &[Node], out: &str, ip_lines: &[String], syncer_lines: Option<&[String]>) -> Result<(), Box<dyn Error>> {
+        let root = format!("{}/hosts", self.dir);
+        if !Path::new(&root).is_dir() {
+            return Ok(());
+        }
+        let host_of = |node: &Node| node.net_map.get(&node.id)
+            .and_then(|addr| split_host_port(addr))
+            .map(|(host, _)| host.to_string());
+        // The hosts of existing bundles, and of nodes added since
+        let mut hosts: BTreeSet<String> = nodes.iter().filter_map(host_of).collect();
+        for entry in fs::read_dir(&root)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                hosts.insert(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        for host in hosts {
+            let dir = format!("{}/{}", root, host);
+            fs::create_dir_all(&dir)?;
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.file_name().and_then(|n| n.to_str()).and_then(node_file).is_some() {
+                    fs::remove_file(&path)?;
+                }
+            }
+            let syncer_here = Path::new(&format!("{}/syncer", dir)).exists();
+            for (i, node) in nodes.iter().enumerate() {
+                // The syncer is started with the config of the lowest node
+                if host_of(node).as_deref() == Some(host.as_str()) || (syncer_here && i == 0) {
+                    write_config(&format!("{}/nodes-{}", dir, node.id), node, out);
+                }
+            }
+            write_lines(&format!("{}/ip_file", dir), ip_lines)?;
+            if let (true, Some(lines)) = (syncer_here, syncer_lines) {
+                write_lines(&format!("{}/syncer", dir), lines)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the `add-node`, `rotate` or `revoke` subcommand on the config set in
+/// `--target`. Every config is rewritten, along with the ip_file in the
+/// working directory and the per-host bundles if genconfig wrote them, so
+/// all of them have to be redistributed.
+pub fn run(name: &str, m: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let target = m.value_of("target")
+        .expect("target directory of the config set not specified");
+    let passphrase = Passphrase::from_file_or_env(m.value_of("passphrase_file"));
+    let mut set = ConfigSet::open(target, &passphrase)?;
+    let node = || m.value_of("node")
+        .expect("node not specified")
+        .parse::<Replica>()
+        .map_err(|e| format!("unable to parse the node id: {}", e));
+    match name {
+        "add-node" => {
+            let client_port = m.value_of("client_port")
+                .map(|p| p.parse::<u16>().map_err(|e| format!("unable to parse the client port: {}", e)))
+                .transpose()?;
+            let value = m.value_of("value")
+                .map(|v| v.parse::<u64>().map_err(|e| format!("unable to parse the value: {}", e)))
+                .transpose()?;
+            let id = set.add_node(m.value_of("address"), client_port, value)?;
+            if let Some(faults) = m.value_of("faults") {
+                let faults = faults.parse::<usize>()
+                    .map_err(|e| format!("unable to parse the number of faults: {}", e))?;
+                for node in set.nodes.values_mut() {
+                    node.num_faults = faults;
+                }
+                if let Some(client) = &mut set.client {
+                    client.num_faults = faults;
+                }
+            }
+            set.save()?;
+            println!("Added node {} at {}", id, set.nodes[&id].net_map[&id]);
+        },
+        "rotate" => {
+            let id = node()?;
+            set.rotate(id)?;
+            set.save()?;
+            println!("Rotated the keys of node {}", id);
+        },
+        "revoke" => {
+            let id = node()?;
+            set.revoke(id)?;
+            set.save()?;
+            println!("Revoked node {}", id);
+        },
+        _ => return Err(format!("unknown subcommand {}", name).into()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nopki_set(n: usize) -> ConfigSet {
+        let mut nodes = BTreeMap::new();
+        for i in 0..n {
+            let mut node = Node::new();
+            node.id = i;
+            node.num_nodes = n;
+            node.num_faults = (n-1)/3;
+            node.crypto_alg = Algorithm::NOPKI;
+            node.client_port = 10000 + i as u16;
+            for j in 0..=n {
+                node.net_map.insert(j, format!("127.0.0.1:{}", 8500+j));
+            }
+            nodes.insert(i, node);
+        }
+        let mut set = ConfigSet { dir: String::new(), ext: "json".to_string(), nodes, client: None, passphrase: None };
+        for i in 0..n {
+            set.rotate(i).unwrap();
+        }
+        set
+    }
+
+    fn check(set: &ConfigSet) {
+        let nodes: Vec<Node> = set.nodes.values().cloned().collect();
+        for node in nodes.iter() {
+            node.validate_for(ProtocolId::AppxCon).unwrap();
+        }
+        Node::validate_set(&nodes).unwrap();
+    }
+
+    #[test]
+    fn updates_only_the_affected_keys() {
+        let mut set = nopki_set(4);
+        check(&set);
+        let before = set.nodes[&1].sk_map.clone();
+        set.rotate(2).unwrap();
+        check(&set);
+        assert_ne!(set.nodes[&1].sk_map[&2], before[&2]);
+        assert_eq!(set.nodes[&1].sk_map[&3], before[&3]);
+
+        let id = set.add_node(None, None, None).unwrap();
+        assert_eq!(id, 4);
+        assert_eq!(set.nodes[&0].net_map[&4], "127.0.0.1:8504");
+        // The syncer moved up to make room
+        assert_eq!(set.nodes[&0].net_map[&5], "127.0.0.1:8504");
+        set.nodes.values_mut().for_each(|n| { n.net_map.insert(5, "127.0.0.1:5000".to_string()); });
+        check(&set);
+        assert_eq!(set.nodes[&1].sk_map[&3], before[&3]);
+
+        set.revoke(3).unwrap();
+        check(&set);
+        assert!(!set.nodes[&0].sk_map.contains_key(&3));
+        assert_eq!(set.nodes[&4].revoked, vec![3]);
+        // f = 1 leaves no room for a second revocation
+        set.revoke(0).unwrap();
+        let nodes: Vec<Node> = set.nodes.values().cloned().collect();
+        assert!(matches!(nodes[0].validate(), Err(config::ParseError::TooManyRevoked(2, 1))));
+    }
+}
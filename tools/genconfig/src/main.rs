@@ -2,14 +2,24 @@
 // protocol.
 
 use crypto::{ed25519, secp256k1::{self,SecretKey}};
-use config::{Node, Client};
+use config::{Node, Client, MulticastConfig, LinkEmulation, ProtocolConfig, Passphrase};
 use clap::{load_yaml, App};
-use rand::Rng;
-use types::Replica;
+use types::{ProtocolId, Replica};
 use crypto::Algorithm;
 use std::{error::Error, io::{BufWriter, Write}, fs::File};
 use util::io::*;
 use fnv::FnvHashMap as HashMap;
+use std::str::FromStr;
+use std::net::IpAddr;
+use serde::Serialize;
+
+mod inputs;
+use inputs::{InputModel, GroundTruth};
+
+mod hosts;
+use hosts::{Topology, read_inventory};
+
+mod lifecycle;
 
 // fn new_root_cert() -> Result<(X509, PKey<Private>), ErrorStack> {
 //     let rsa = Rsa::generate(2048)?;
@@ -134,9 +144,39 @@ use fnv::FnvHashMap as HashMap;
 //     Ok((cert, privkey))
 // }
 
+/// Parses `a,b`
+fn parse_pair<T: FromStr>(s: &str) -> Option<(T, T)> {
+    let (a, b) = s.split_once(',')?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+/// Writes `obj` to `<stem>.<ext>` in the format `out`
+fn write_config(stem: &str, obj: &impl Serialize, out: &str) {
+    match out {
+        "json" => write_json(format!("{}.json", stem), obj),
+        "binary" => write_bin(format!("{}.dat", stem), obj),
+        "toml" => write_toml(format!("{}.toml", stem), obj),
+        "yaml" => write_yaml(format!("{}.yml", stem), obj),
+        _ => (),
+    }
+}
+
+fn write_lines(filename: &str, lines: &[String]) -> Result<(), Box<dyn Error>> {
+    let file = File::create(filename)?;
+    let mut writer = BufWriter::new(file);
+    for line in lines {
+        writeln!(writer,"{}",line)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let yaml = load_yaml!("cli.yml");
     let m = App::from_yaml(yaml).get_matches();
+    if let (name, Some(sub)) = m.subcommand() {
+        return lifecycle::run(name, sub);
+    }
     let num_nodes:usize =  m.value_of("num_nodes")
         .expect("number of nodes not specified")
         .parse::<usize>()
@@ -182,6 +222,93 @@ fn main() -> Result<(), Box<dyn Error>> {
         .expect("Client port expected")
         .parse::<u16>()
         .expect("unable to parse client's port into an integer");
+    let inventory = m.value_of("hosts");
+    let topology = match inventory {
+        Some(path) => {
+            let hosts = read_inventory(path)?;
+            let syncer_host = m.value_of("syncer_host").map(|host| host.parse::<IpAddr>()
+                .expect("unable to parse the syncer host into an IP address"));
+            Topology::spread(&hosts, syncer_host, num_nodes, base_port, client_base_port, c_rport)?
+        },
+        None => Topology::local(num_nodes, base_port, client_base_port, c_rport)?,
+    };
+    let syncer_lines = topology.syncer_lines();
+    let multicast = m.value_of("multicast").map(|group| {
+        let mut mcast = MulticastConfig::new(group.parse()
+            .expect("unable to parse the multicast group into an ipv4 address and port"));
+        if let Some(interface) = m.value_of("multicast_interface") {
+            mcast.interface = interface.parse()
+                .expect("unable to parse the multicast interface into an ipv4 address");
+        }
+        mcast
+    });
+    let bind_addr = m.value_of("bind").map(|ip| ip.parse::<IpAddr>()
+        .expect("unable to parse the bind address into an IP address"));
+    let links = match m.value_of("links") {
+        Some(path) => Some(LinkEmulation::load(path).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    let seed: u64 = match m.value_of("seed") {
+        Some(seed) => seed.parse()
+            .expect("unable to parse the seed into a number"),
+        None => rand::random(),
+    };
+    let range: (u64, u64) = parse_pair(m.value_of("input_range").unwrap_or("0,20000000"))
+        .expect("unable to parse the input range into low,high");
+    let parse_f64 = |name: &str, default: f64| m.value_of(name)
+        .map(|v| v.parse::<f64>().unwrap_or_else(|_| panic!("unable to parse {} into a number", name)))
+        .unwrap_or(default);
+    let model = match m.value_of("input_model").unwrap_or("uniform") {
+        "clustered" => {
+            let (center, spread) = match m.value_of("cluster") {
+                Some(cluster) => parse_pair(cluster)
+                    .expect("unable to parse the cluster into center,spread"),
+                None => ((range.0+range.1)/2, (range.1-range.0)/100),
+            };
+            InputModel::Clustered { center, spread }
+        },
+        "sensor" => {
+            let spacing = parse_f64("spacing", 5.0);
+            let target = match m.value_of("source") {
+                Some(source) => parse_pair(source)
+                    .expect("unable to parse the source into x,y"),
+                None => InputModel::grid_center(num_nodes, spacing),
+            };
+            InputModel::Sensor {
+                spacing,
+                target,
+                strength: parse_f64("strength", 75.0),
+                noise: parse_f64("noise", 1.0),
+            }
+        },
+        _ => InputModel::Uniform,
+    };
+    let outliers: usize = m.value_of("outliers")
+        .unwrap_or("0")
+        .parse()
+        .expect("unable to parse the number of outliers");
+    if outliers > num_faults {
+        return Err(format!("{} outliers is more than the {} faults tolerated", outliers, num_faults).into());
+    }
+    let truth = GroundTruth::generate(seed, model, range, num_nodes, outliers)
+        .map_err(|e| format!("unable to generate the node inputs: {}", e))?;
+    let epsilon: u64 = m.value_of("epsilon")
+        .unwrap_or("1")
+        .parse()
+        .expect("unable to parse epsilon into a number");
+    let protocol = match m.value_of("protocol") {
+        Some("appx") => Some(ProtocolConfig::Appx { value: 0, epsilon }),
+        Some(_) => Some(ProtocolConfig::Hyb {
+            value: 0,
+            delta: m.value_of("delta")
+                .expect("--hyb_delta is required for a hyb protocol section")
+                .parse()
+                .expect("unable to parse delta into a number"),
+            epsilon,
+            tri: truth.honest_max - truth.honest_min,
+        }),
+        None => None,
+    };
     let mut client = Client::new();
     client.block_size = blocksize;
     client.crypto_alg = t.clone();
@@ -220,10 +347,12 @@ fn main() -> Result<(), Box<dyn Error>> {
         node[i].num_faults = num_faults;
         node[i].block_size = blocksize;
         node[i].payload = payload;
-        node[i].client_port = client_base_port+(i as u16);
-        // generate random number for approximate consensus
-        let num = rand::thread_rng().gen_range(0, 20000000);
-        node[i].prot_payload = format!("a,{},50000,100",num);
+        node[i].client_port = topology.client_ports[i];
+        node[i].client_addr = topology.syncer;
+        node[i].multicast = multicast.clone();
+        node[i].links = links.clone();
+        node[i].bind_addr = bind_addr;
+        node[i].protocol = protocol.map(|p| p.with_value(truth.nodes[i].input));
         //String::from("a,");
         //node[i].prot_payload = String::from("cc,/home/akhil/research/EEBA/libchatter/");
         node[i].crypto_alg = t.clone();
@@ -246,12 +375,8 @@ fn main() -> Result<(), Box<dyn Error>> {
             }
             _ => (),
         };
-        ip.insert(i as Replica, 
-        format!("{}:{}", "127.0.0.1", base_port+(i as u16))
-        );
-        client.net_map.insert(i as Replica, 
-        format!("127.0.0.1:{}", client_base_port+(i as u16))
-        );
+        ip.insert(i as Replica, topology.nodes[i].to_string());
+        client.net_map.insert(i as Replica, syncer_lines[i].clone());
 
 
         //let (new_cert, new_pkey) = get_signed_cert(&cert, &privkey)?;
@@ -260,7 +385,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         //node[i].my_cert = new_cert.to_der()?;
         //node[i].my_cert_key = new_pkey.private_key_to_der()?;
     }
-    ip.insert(num_nodes, format!("127.0.0.1:{}",c_rport));
+    ip.insert(num_nodes, topology.syncer.to_string());
     //client.root_cert = cert.to_der()?;
 
     for i in 0..num_nodes {
@@ -272,76 +397,65 @@ fn main() -> Result<(), Box<dyn Error>> {
         //let filename = format!("ip_file");
         println!("Writing ips to ip_file");
         // write ips to ip_file
-        {
-            let file = File::create("ip_file")?;
-            let mut writer = BufWriter::new(file);
-            for iter in 0..num_nodes+1{
-                writeln!(writer,"{}",ip.get(&iter).unwrap())?;
-            }
-            writer.flush()?;
-        }
-        {
-            let file = File::create(format!("{}/syncer",target))?;
-            let mut writer = BufWriter::new(file);
-            for iter in 0..num_nodes{
-                writeln!(writer,"{}",client.net_map.get(&iter).unwrap())?;
-            }
-            writer.flush()?;
-        }
+        write_lines("ip_file", &topology.ip_lines())?;
+        write_lines(&format!("{}/syncer",target), &syncer_lines)?;
         //write_json(filename, &ip.clone());
     }
+    // The inputs as a one run experiment for the syncer, and where they
+    // came from
+    println!("Writing inputs drawn with seed {} to {}/inputs", seed, target);
+    write_lines(&format!("{}/inputs",target), &[truth.inputs_line()])?;
+    write_json(format!("{}/ground_truth.json",target), &truth);
     let filename = format!("{}/syncer.json",target);
     write_json(filename, &client.net_map.clone());
     client.server_pk = pk;
 
-    // Write all the files
-    for i in 0..num_nodes {
-        match out {
-            "json" => {
-                let filename = format!("{}/nodes-{}.json",target,i);
-                write_json(filename, &node[i]);
-            },
-            "binary" => {
-                let filename = format!("{}/nodes-{}.dat",target,i);
-                write_bin(filename, &node[i]);
-            },
-            "toml" => {
-                let filename = format!("{}/nodes-{}.toml",target,i);
-                write_toml(filename, &node[i]);
-            },
-            "yaml" => {
-                let filename = format!("{}/nodes-{}.yml",target,i);
-                write_yaml(filename, &node[i]);
-            },
-            _ => (),
-        }
-        node[i].validate()
+    // Write all the files, checked while the keys are in the clear
+    for n in node.iter() {
+        n.validate_for(protocol.map(|p| p.id()).unwrap_or(ProtocolId::AppxCon))
             .expect("failed to validate node config");
     }
+    Node::validate_set(&node)
+        .expect("the node configs do not agree");
+    if m.is_present("encrypt") {
+        println!("Encrypting the secret keys of the node configs");
+        let secret = Passphrase::from_file_or_env(m.value_of("passphrase_file")).read()?;
+        for n in node.iter_mut() {
+            n.seal(&secret)?;
+        }
+    }
+    for i in 0..num_nodes {
+        write_config(&format!("{}/nodes-{}",target,i), &node[i], out);
+    }
 
     // Write the client file
-    match out {
-        "json" => {
-            let filename = format!("{}/client.json",target);
-            write_json(filename, &client);
-        },
-        "binary" => {
-            let filename = format!("{}/client.dat",target);
-            write_bin(filename, &client);
-        },
-        "toml" => {
-            let filename = format!("{}/client.toml",target);
-            write_toml(filename, &client);
-        },
-        "yaml" => {
-            let filename = format!("{}/client.yml",target);
-            write_yaml(filename, &client);
-        },
-        _ => (),
-    }
+    write_config(&format!("{}/client",target), &client, out);
     client.validate()
         .expect("failed to validate the client config");
 
+    // What each host needs: the configs of its nodes, the ip_file, and on
+    // the syncer's host what the syncer reads
+    if inventory.is_some() {
+        println!("Writing per-host bundles to {}/hosts", target);
+        for host in topology.hosts() {
+            let dir = format!("{}/hosts/{}",target,host);
+            std::fs::create_dir_all(&dir)?;
+            let mut ids = topology.nodes_on(host);
+            if host == topology.syncer.ip() {
+                write_lines(&format!("{}/syncer",dir), &syncer_lines)?;
+                write_lines(&format!("{}/inputs",dir), &[truth.inputs_line()])?;
+                // The syncer is started with the config of node 0
+                if !ids.contains(&0) {
+                    ids.push(0);
+                }
+            }
+            for i in ids {
+                write_config(&format!("{}/nodes-{}",dir,i), &node[i], out);
+            }
+            write_lines(&format!("{}/ip_file",dir), &topology.ip_lines())?;
+        }
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,199 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail, Context};
+use config::Node;
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use crate::ClusterSpec;
+
+/// How often the launcher checks on the run
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The syncer and node processes of one run. Every process still running is
+/// killed when the cluster is dropped, so an error or panic in the launcher
+/// does not leave stray nodes holding on to the ports. Ctrl-C and SIGTERM
+/// make the wait fail instead of killing the launcher, so they end up in
+/// the same cleanup.
+pub struct Cluster {
+    bin_dir: PathBuf,
+    run_dir: PathBuf,
+    children: Vec<(String, Child)>,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Cluster {
+    pub fn new(bin_dir: PathBuf, run_dir: PathBuf) -> Result<Cluster> {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        for signal in [SIGINT, SIGTERM] {
+            signal_hook::flag::register(signal, Arc::clone(&interrupted))
+                .context("Unable to install the signal handler")?;
+        }
+        Ok(Cluster {
+            bin_dir,
+            run_dir,
+            children: Vec::new(),
+            interrupted,
+        })
+    }
+
+    /// Generates configs for the spec into `<run_dir>/config`, or copies the
+    /// ones the spec points to, and writes the address files the node
+    /// binary expects next to them
+    pub fn prepare_configs(&self, spec: &ClusterSpec) -> Result<PathBuf> {
+        let config_dir = self.run_dir.join("config");
+        fs::create_dir_all(&config_dir)
+            .with_context(|| format!("Unable to create {}", config_dir.display()))?;
+        match &spec.config_dir {
+            Some(dir) => {
+                for i in 0..spec.n {
+                    let name = format!("nodes-{}.json", i);
+                    fs::copy(Path::new(dir).join(&name), config_dir.join(&name))
+                        .with_context(|| format!("Unable to copy {} from {}", name, dir))?;
+                }
+            },
+            None => {
+                let status = Command::new(self.bin_dir.join("genconfig"))
+                    .args(["-n", &spec.n.to_string()])
+                    .args(["-f", &spec.faults().to_string()])
+                    .args(["-d", &spec.delta.to_string()])
+                    .args(["-b", "1"])
+                    .args(["-P", &spec.base_port.to_string()])
+                    .args(["-C", &spec.client_base_port.to_string()])
+                    .args(["-q", &spec.syncer_port.to_string()])
+                    .args(["-A", "NOPKI"])
+                    .arg("-t").arg(&config_dir)
+                    .stdout(Stdio::null())
+                    .status()
+                    .with_context(|| format!("Unable to run genconfig from {}", self.bin_dir.display()))?;
+                if !status.success() {
+                    bail!("genconfig failed with {}", status);
+                }
+            },
+        }
+        let nodes = (0..spec.n)
+            .map(|i| Node::from_json(config_dir.join(format!("nodes-{}.json", i)).display().to_string()))
+            .collect::<Result<Vec<Node>, _>>()?;
+        Node::validate_set(&nodes)
+            .with_context(|| format!("The configs in {} do not agree", config_dir.display()))?;
+        // Every node followed by the syncer, as the nodes read it with --ip
+        let ips = (0..=spec.n)
+            .map(|i| nodes[0].net_map.get(&i).cloned()
+                .ok_or_else(|| anyhow!("nodes-0.json has no address for {}", i)))
+            .collect::<Result<Vec<_>>>()?;
+        write_lines(&self.run_dir.join("ip_file"), &ips)?;
+        // Where the syncer reaches each node, as it reads it with --syncer
+        let syncer_ips: Vec<String> = nodes.iter()
+            .map(|node| format!("127.0.0.1:{}", node.client_port))
+            .collect();
+        write_lines(&self.run_dir.join("syncer"), &syncer_ips)?;
+        Ok(config_dir)
+    }
+
+    /// Starts the syncer and then the nodes, all set to begin at
+    /// `start_time` (milliseconds since the epoch)
+    pub fn spawn(&mut self, spec: &ClusterSpec, config_dir: &Path, start_time: u128) -> Result<()> {
+        let tri = spec.tri().to_string();
+        let common = |command: &str, i: usize| -> Vec<String> {
+            vec![
+                command.into(),
+                "--config".into(), config_dir.join(format!("nodes-{}.json", i)).display().to_string(),
+                "--ip".into(), "ip_file".into(),
+                "--session".into(), start_time.to_string(),
+                "--epsilon".into(), spec.epsilon.to_string(),
+            ]
+        };
+        let hyb_args = ["--delta".to_string(), spec.delta.to_string(), "--tri".to_string(), tri];
+        let mut syncer_args = common("syncer", 0);
+        syncer_args.extend(["--syncer", "syncer", "--protocol", &spec.protocol, "--report", "report"].map(String::from));
+        syncer_args.extend(hyb_args.iter().cloned());
+        self.spawn_one("syncer", syncer_args)?;
+        let command = format!("run-{}", spec.protocol);
+        for (i, input) in spec.inputs.iter().enumerate() {
+            let mut args = common(&command, i);
+            args.extend(["--sleep".to_string(), start_time.to_string(), "--val".to_string(), input.to_string()]);
+            // Only hyb takes delta and tri
+            if spec.protocol == "hyb" {
+                args.extend(hyb_args.iter().cloned());
+            }
+            self.spawn_one(&format!("node-{}", i), args)?;
+        }
+        Ok(())
+    }
+
+    fn spawn_one(&mut self, name: &str, args: Vec<String>) -> Result<()> {
+        let log_path = self.run_dir.join(format!("{}.log", name));
+        let log = File::create(&log_path)
+            .with_context(|| format!("Unable to create {}", log_path.display()))?;
+        let child = Command::new(self.bin_dir.join("node"))
+            .args(&args)
+            // Traffic summaries and reports land in the run directory
+            .current_dir(&self.run_dir)
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()
+            .with_context(|| format!("Unable to start {} from {}", name, self.bin_dir.display()))?;
+        self.children.push((name.to_string(), child));
+        Ok(())
+    }
+
+    /// Waits until `done` exists in the run directory. Fails if any process
+    /// exits first, the timeout passes or the launcher is interrupted.
+    pub fn wait_for(&mut self, done: &str, timeout: Duration) -> Result<PathBuf> {
+        let path = self.run_dir.join(done);
+        let deadline = Instant::now() + timeout;
+        loop {
+            if path.exists() {
+                return Ok(path);
+            }
+            if self.interrupted.load(Ordering::Relaxed) {
+                bail!("Interrupted; stopping the cluster");
+            }
+            for (name, child) in self.children.iter_mut() {
+                if let Some(status) = child.try_wait()? {
+                    bail!("{} exited early with {}; see {}.log", name, status, name);
+                }
+            }
+            if Instant::now() >= deadline {
+                bail!("The run did not complete within {} s", timeout.as_secs());
+            }
+            sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Kills every process still running
+    pub fn shutdown(&mut self) {
+        for (name, child) in self.children.iter_mut() {
+            if let Ok(None) = child.try_wait() {
+                if let Err(e) = child.kill() {
+                    eprintln!("Unable to kill {}: {}", name, e);
+                }
+            }
+            let _ = child.wait();
+        }
+        self.children.clear();
+    }
+}
+
+impl Drop for Cluster {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn write_lines(path: &Path, lines: &[String]) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Unable to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    for line in lines {
+        writeln!(writer, "{}", line)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+// Runs a local cluster: generates configs, starts the syncer and the nodes,
+// waits for the run to complete and collects everything into a run
+// directory.
+
+use std::{fs::{self, File}, path::PathBuf, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use anyhow::{Result, Context};
+use clap::{load_yaml, App};
+use node::RunReport;
+
+mod spec;
+pub use spec::*;
+
+mod cluster;
+pub use cluster::*;
+
+fn main() -> Result<()> {
+    let yaml = load_yaml!("cli.yml");
+    let m = App::from_yaml(yaml).get_matches();
+    let spec = ClusterSpec::from_file(m.value_of("spec")
+        .expect("cluster spec not specified"))?;
+    let bin_dir = match m.value_of("bin") {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::current_exe()?
+            .parent()
+            .expect("the launcher binary has no parent directory")
+            .to_path_buf(),
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let run_dir = PathBuf::from(m.value_of("out").unwrap_or("runs"))
+        .join(format!("{}-n{}-{}", spec.protocol, spec.n, now));
+    fs::create_dir_all(&run_dir)
+        .with_context(|| format!("Unable to create the run directory {}", run_dir.display()))?;
+    // Children run inside the run directory, so hand them absolute paths
+    let run_dir = run_dir.canonicalize()?;
+    let bin_dir = bin_dir.canonicalize()
+        .with_context(|| format!("Binary directory {} not found", bin_dir.display()))?;
+    serde_json::to_writer_pretty(File::create(run_dir.join("spec.json"))?, &spec)?;
+    println!("Running {} with n={} f={} in {}", spec.protocol, spec.n, spec.faults(), run_dir.display());
+
+    let mut cluster = Cluster::new(bin_dir, run_dir.clone())?;
+    let config_dir = cluster.prepare_configs(&spec)?;
+    let start_time = now + spec.start_delay_ms as u128;
+    cluster.spawn(&spec, &config_dir, start_time)?;
+    let report_path = cluster.wait_for("report.json", Duration::from_secs(spec.timeout_secs))?;
+    cluster.shutdown();
+
+    let report: RunReport = serde_json::from_reader(File::open(&report_path)?)
+        .with_context(|| format!("Malformed run report {}", report_path.display()))?;
+    println!("{} of {} nodes completed; late {:?}, missing {:?}",
+        report.results.len(), spec.n, report.completion.late, report.completion.missing);
+    println!("Decided values span [{:?}, {:?}], spread {:?}, within epsilon {}",
+        report.min_value, report.max_value, report.spread, report.within_epsilon);
+    println!("Logs and reports are in {}", run_dir.display());
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Result, anyhow, bail, Context};
+use serde::{Serialize, Deserialize};
+
+/// Protocols the node binary knows how to run
+pub const PROTOCOLS: [&str; 2] = ["appx", "hyb"];
+
+fn default_base_port() -> u16 { 8500 }
+fn default_client_base_port() -> u16 { 10000 }
+fn default_syncer_port() -> u16 { 5000 }
+fn default_timeout_secs() -> u64 { 300 }
+fn default_start_delay_ms() -> u64 { 3000 }
+
+/// A local cluster to run: which protocol, how many nodes and what they
+/// start with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterSpec {
+    /// `appx` or `hyb`
+    pub protocol: String,
+    pub n: usize,
+    /// Defaults to (n-1)/3
+    #[serde(default)]
+    pub f: Option<usize>,
+    /// One input per node
+    pub inputs: Vec<u64>,
+    pub epsilon: u64,
+    pub delta: u64,
+    /// Defaults to the spread of the inputs
+    #[serde(default)]
+    pub tri: Option<u64>,
+    /// Node i listens to the other nodes on base_port+i
+    #[serde(default = "default_base_port")]
+    pub base_port: u16,
+    /// Node i listens to the syncer on client_base_port+i
+    #[serde(default = "default_client_base_port")]
+    pub client_base_port: u16,
+    #[serde(default = "default_syncer_port")]
+    pub syncer_port: u16,
+    /// Existing configs to use instead of generating fresh ones
+    #[serde(default)]
+    pub config_dir: Option<String>,
+    /// How long to wait for the run to complete before giving up
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Time for every process to come up before the protocol starts
+    #[serde(default = "default_start_delay_ms")]
+    pub start_delay_ms: u64,
+}
+
+impl ClusterSpec {
+    /// Reads a spec in json, toml or yaml, by file extension
+    pub fn from_file(path: &str) -> Result<ClusterSpec> {
+        let mut buf = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut buf))
+            .with_context(|| format!("Unable to read the cluster spec {}", path))?;
+        let ext = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+        let spec: ClusterSpec = match ext {
+            "json" => serde_json::from_str(&buf).map_err(anyhow::Error::new),
+            "toml" => toml::from_str(&buf).map_err(anyhow::Error::new),
+            "yaml" | "yml" => serde_yaml::from_str(&buf).map_err(anyhow::Error::new),
+            _ => Err(anyhow!("unknown extension {:?}; expected json, toml or yaml", ext)),
+        }.with_context(|| format!("Invalid cluster spec {}", path))?;
+        spec.validate()
+            .with_context(|| format!("Invalid cluster spec {}", path))?;
+        Ok(spec)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !PROTOCOLS.contains(&self.protocol.as_str()) {
+            bail!("unknown protocol {:?}; expected one of {:?}", self.protocol, PROTOCOLS);
+        }
+        if self.n == 0 {
+            bail!("a cluster needs at least one node");
+        }
+        if self.n <= 3*self.faults() {
+            bail!("n={} nodes cannot tolerate f={} faults; need n > 3f", self.n, self.faults());
+        }
+        if self.inputs.len() != self.n {
+            bail!("expected {} inputs, one per node, found {}", self.n, self.inputs.len());
+        }
+        Ok(())
+    }
+
+    pub fn faults(&self) -> usize {
+        self.f.unwrap_or((self.n-1)/3)
+    }
+
+    pub fn tri(&self) -> u64 {
+        self.tri.unwrap_or_else(|| {
+            let min = self.inputs.iter().min().copied().unwrap_or(0);
+            let max = self.inputs.iter().max().copied().unwrap_or(0);
+            max - min
+        })
+    }
+}
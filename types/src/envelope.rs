@@ -0,0 +1,67 @@
+use serde::{Serialize, Deserialize};
+
+use crate::{WireError, WireReady};
+
+/// Bump whenever the encoding of a protocol message changes, so that nodes
+/// built from different commits refuse each other's messages
+pub const WIRE_VERSION: u16 = 1;
+
+/// The protocol a message belongs to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolId {
+    AppxCon,
+    HybAppxCon,
+    CTRBC,
+}
+
+/// Sent ahead of every protocol message and covered by its MAC.
+///
+/// `version` must stay the first field so the header of a message from a
+/// different build still decodes.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct EnvelopeHeader {
+    pub version: u16,
+    pub protocol: ProtocolId,
+    pub session: u64,
+}
+
+impl EnvelopeHeader {
+    pub fn new(protocol: ProtocolId, session: u64) -> Self {
+        EnvelopeHeader {
+            version: WIRE_VERSION,
+            protocol,
+            session,
+        }
+    }
+
+    /// Checks a received header against the one this node sends with
+    pub fn check(&self, ours: &EnvelopeHeader) -> Result<(), WireError> {
+        if self.version != ours.version {
+            return Err(WireError::VersionMismatch(ours.version, self.version));
+        }
+        if self.protocol != ours.protocol {
+            return Err(WireError::ProtocolMismatch(ours.protocol, self.protocol));
+        }
+        if self.session != ours.session {
+            return Err(WireError::SessionMismatch(ours.session, self.session));
+        }
+        Ok(())
+    }
+}
+
+/// A protocol message as it travels between replicas. The transport only
+/// splits the stream into frames; the receiving node decodes them itself,
+/// so a frame that does not decode is counted by the node instead of being
+/// lost inside the transport.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Frame(pub Vec<u8>);
+
+impl Frame {
+    pub fn encode<M: WireReady>(msg: &M) -> Frame {
+        Frame(msg.to_bytes())
+    }
+
+    pub fn decode<M: WireReady>(&self) -> Result<M, WireError> {
+        M::from_bytes(&self.0)
+    }
+}
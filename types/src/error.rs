@@ -0,0 +1,49 @@
+use crate::ProtocolId;
+
+/// Errors raised when a frame received from the network cannot be turned
+/// into a protocol message
+#[derive(Debug)]
+pub enum WireError {
+    // the bytes do not decode into the expected message type
+    Decode(bincode::Error),
+    // the message decoded, but a field inside it is malformed
+    Malformed(&'static str),
+    // (ours, theirs): the message comes from a build with another wire format
+    VersionMismatch(u16, u16),
+    // (ours, theirs): the message belongs to another protocol
+    ProtocolMismatch(ProtocolId, ProtocolId),
+    // (ours, theirs): the message belongs to another run
+    SessionMismatch(u64, u64),
+}
+
+impl std::fmt::Display for WireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WireError::Decode(e) =>
+            write!(f, "failed to decode the frame: {}", e),
+            WireError::Malformed(field) =>
+            write!(f, "malformed field in message: {}", field),
+            WireError::VersionMismatch(ours, theirs) =>
+            write!(f, "wire version {} does not match ours ({})", theirs, ours),
+            WireError::ProtocolMismatch(ours, theirs) =>
+            write!(f, "message for protocol {:?} while running {:?}", theirs, ours),
+            WireError::SessionMismatch(ours, theirs) =>
+            write!(f, "message from session {} while running session {}", theirs, ours),
+        }
+    }
+}
+
+impl std::error::Error for WireError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WireError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<bincode::Error> for WireError {
+    fn from(e: bincode::Error) -> Self {
+        WireError::Decode(e)
+    }
+}
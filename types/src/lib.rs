@@ -7,4 +7,10 @@ pub use msg::*;
 mod traits;
 pub use traits::*;
 
+mod error;
+pub use error::*;
+
+mod envelope;
+pub use envelope::*;
+
 pub type View = usize;
\ No newline at end of file
@@ -2,7 +2,7 @@ use crypto::hash::{Hash};
 use crypto::hash::{do_mac};
 use merkle_light::proof::Proof;
 use serde::{Serialize, Deserialize};
-use crate::{WireReady};
+use crate::{WireReady, WireError, EnvelopeHeader};
 
 use super::Replica;
 
@@ -54,6 +54,64 @@ pub enum ProtMsg{
     // (Msg for AA inst, message), sender node, round number
     BinaryAAEcho(Vec<(Replica,Vec<u8>)>,Replica,u64),
     BinaryAAEcho2(Vec<(Replica,Vec<u8>)>,Replica,u64),
+    // Liveness probe: sender node, the sender's current round
+    Heartbeat(Replica,u64),
+}
+
+impl ProtMsg {
+    /// Name of the variant, for accounting
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProtMsg::RBCInit(..) => "RBCInit",
+            ProtMsg::ECHO(..) => "ECHO",
+            ProtMsg::READY(..) => "READY",
+            ProtMsg::WITNESS(..) => "WITNESS",
+            ProtMsg::WITNESS2(..) => "WITNESS2",
+            ProtMsg::CTRBCInit(..) => "CTRBCInit",
+            ProtMsg::CTECHO(..) => "CTECHO",
+            ProtMsg::CTREADY(..) => "CTREADY",
+            ProtMsg::CTReconstruct(..) => "CTReconstruct",
+            ProtMsg::BinaryAAEcho(..) => "BinaryAAEcho",
+            ProtMsg::BinaryAAEcho2(..) => "BinaryAAEcho2",
+            ProtMsg::Heartbeat(..) => "Heartbeat",
+        }
+    }
+
+    /// Protocol phase the message belongs to. The n-parallel RBCs and
+    /// witnesses cost O(n^3) messages a round, binary AA O(n^2).
+    pub fn phase(&self) -> &'static str {
+        match self {
+            ProtMsg::RBCInit(msg, ..) | ProtMsg::ECHO(msg, ..) | ProtMsg::READY(msg, ..) => {
+                // Later rounds of hyb reuse rnd_estm to flag termination
+                if msg.rnd_estm && msg.round == 0 { "estimation" } else { "rbc" }
+            },
+            ProtMsg::WITNESS(..) | ProtMsg::WITNESS2(..) => "witness",
+            ProtMsg::CTRBCInit(..) | ProtMsg::CTECHO(..) | ProtMsg::CTREADY(..) | ProtMsg::CTReconstruct(..) => "ctrbc",
+            ProtMsg::BinaryAAEcho(..) | ProtMsg::BinaryAAEcho2(..) => "baa",
+            ProtMsg::Heartbeat(..) => "liveness",
+        }
+    }
+
+    pub fn round(&self) -> u64 {
+        match self {
+            ProtMsg::RBCInit(msg, ..) | ProtMsg::ECHO(msg, ..) | ProtMsg::READY(msg, ..) => msg.round,
+            ProtMsg::WITNESS(_, _, round) | ProtMsg::WITNESS2(_, _, round) => *round,
+            ProtMsg::CTRBCInit(msg) => msg.round,
+            ProtMsg::CTECHO(msg, _) | ProtMsg::CTREADY(msg, _) | ProtMsg::CTReconstruct(msg, _) => msg.round,
+            ProtMsg::BinaryAAEcho(_, _, round) | ProtMsg::BinaryAAEcho2(_, _, round) => *round,
+            ProtMsg::Heartbeat(_, round) => *round,
+        }
+    }
+
+    /// Node that started the RBC the message belongs to
+    pub fn origin(&self) -> Option<Replica> {
+        match self {
+            ProtMsg::RBCInit(msg, ..) | ProtMsg::ECHO(msg, ..) | ProtMsg::READY(msg, ..) => Some(msg.origin),
+            ProtMsg::CTRBCInit(msg) => Some(msg.origin),
+            ProtMsg::CTECHO(msg, _) | ProtMsg::CTREADY(msg, _) | ProtMsg::CTReconstruct(msg, _) => Some(msg.origin),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug,Serialize,Deserialize,Clone)]
@@ -79,29 +137,46 @@ impl MerkleProof {
 
 #[derive(Debug,Serialize,Deserialize,Clone)]
 pub struct WrapperMsg{
+    pub header: EnvelopeHeader,
     pub protmsg: ProtMsg,
     pub sender:Replica,
     pub mac:Hash,
 }
 
 impl WrapperMsg{
-    pub fn new(msg:ProtMsg,sender:Replica, sk: &[u8]) -> Self{
+    pub fn new(header:EnvelopeHeader, msg:ProtMsg,sender:Replica, sk: &[u8]) -> Self{
         let new_msg = msg.clone();
-        let bytes = bincode::serialize(&new_msg).expect("Failed to serialize protocol message");
+        let bytes = WrapperMsg::mac_bytes(&header, &new_msg);
         let mac = do_mac(&bytes.as_slice(), sk);
         Self{
+            header: header,
             protmsg: new_msg,
             mac: mac,
             sender:sender
         }
     }
+
+    /// Serialized size of the WrapperMsg carrying `msg`, without MACing it
+    pub fn wire_size(header:&EnvelopeHeader, msg:&ProtMsg) -> u64{
+        bincode::serialized_size(&(header, msg, Replica::default(), Hash::default())).unwrap_or(0)
+    }
+
+    /// The bytes covered by the MAC: the header and the protocol message
+    pub fn mac_bytes(header:&EnvelopeHeader, msg:&ProtMsg) -> Vec<u8>{
+        bincode::serialize(&(header, msg)).expect("Failed to serialize protocol message")
+    }
 }
 
 impl WireReady for WrapperMsg{
-    fn from_bytes(bytes: &[u8]) -> Self {
-        let c:Self = bincode::deserialize(bytes)
-            .expect("failed to decode the protocol message");
-        c.init()
+    fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        // The version leads the frame, so check it before trying to decode
+        // a message body that may be laid out differently
+        let version:u16 = bincode::deserialize(bytes)?;
+        if version != crate::WIRE_VERSION {
+            return Err(WireError::VersionMismatch(crate::WIRE_VERSION, version));
+        }
+        let c:Self = bincode::deserialize(bytes)?;
+        Ok(c.init())
     }
 
     fn to_bytes(&self) -> Vec<u8> {
@@ -6,7 +6,7 @@ use merkle_light::merkle::MerkleTree;
 use num_bigint::{BigInt, Sign};
 use serde::{Serialize, Deserialize};
 
-use crate::{appxcon::{MerkleProof, HashingAlg, verify_merkle_proof}, WireReady};
+use crate::{appxcon::{MerkleProof, HashingAlg, verify_merkle_proof}, WireReady, WireError};
 
 use super::{Replica, Round};
 
@@ -260,10 +260,9 @@ impl WrapperMsg{
 }
 
 impl WireReady for WrapperMsg{
-    fn from_bytes(bytes: &[u8]) -> Self {
-        let c:Self = bincode::deserialize(bytes)
-            .expect("failed to decode the protocol message");
-        c.init()
+    fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        let c:Self = bincode::deserialize(bytes)?;
+        Ok(c.init())
     }
 
     fn to_bytes(&self) -> Vec<u8> {
@@ -299,10 +298,9 @@ impl WrapperSMRMsg{
 }
 
 impl WireReady for WrapperSMRMsg{
-    fn from_bytes(bytes: &[u8]) -> Self {
-        let c:Self = bincode::deserialize(bytes)
-            .expect("failed to decode the protocol message");
-        c.init()
+    fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        let c:Self = bincode::deserialize(bytes)?;
+        Ok(c.init())
     }
 
     fn to_bytes(&self) -> Vec<u8> {
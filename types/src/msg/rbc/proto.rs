@@ -2,7 +2,7 @@ use serde::{
     Serialize,
     Deserialize
 };
-use crate::WireReady;
+use crate::{WireReady, WireError};
 use crate::rbc::{WrapperMsg};
 
 #[derive(Debug,Serialize,Deserialize,Clone)]
@@ -18,10 +18,9 @@ impl ProtocolMsg {
 }
 
 impl WireReady for ProtocolMsg{
-    fn from_bytes(bytes: &[u8]) -> Self {
-        let c:Self = bincode::deserialize(bytes)
-            .expect("failed to decode the protocol message");
-        c.init()
+    fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        let c:Self = bincode::deserialize(bytes)?;
+        Ok(c.init())
     }
 
     fn to_bytes(&self) -> Vec<u8> {
@@ -1,6 +1,6 @@
 use serde::{Serialize, Deserialize};
 
-use crate::{WireReady, Replica};
+use crate::{WireReady, WireError, Replica};
 
 #[derive(Debug,Serialize,Deserialize,Clone)]
 pub enum SyncState{
@@ -12,21 +12,35 @@ pub enum SyncState{
     COMPLETED,
     CompletedRecon,
     STOP,
-    STOPPED
+    STOPPED,
+    // Message and byte counts; the payload carries the report
+    TrafficReport,
+    // Re-arm for another run without restarting; the payload carries a RunSpec
+    RESET,
+    // Rounds, values, timings and traffic of a completed node; the payload
+    // carries the report. Sent just before COMPLETED or CompletedSharing.
+    CompletionReport,
+    // The node's watchdog saw no progress in the current round for too
+    // long; the value is the round and the payload carries the diagnostics
+    Stalled,
+    // The node could not move to the membership of a RESET; the value is
+    // the epoch and the payload carries the reason as text
+    ReconfigFailed,
 }
 
 #[derive(Debug,Serialize,Deserialize,Clone)]
 pub struct SyncMsg{
     pub sender:Replica,
     pub state:SyncState,
-    pub value: u64
+    pub value: u64,
+    /// State-specific data, empty for most states
+    pub payload: Vec<u8>,
 }
 
 impl WireReady for SyncMsg{
-    fn from_bytes(bytes: &[u8]) -> Self {
-        let c:Self = bincode::deserialize(bytes)
-            .expect("failed to decode the protocol message");
-        c.init()
+    fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        let c:Self = bincode::deserialize(bytes)?;
+        Ok(c.init())
     }
 
     fn to_bytes(&self) -> Vec<u8> {
@@ -39,4 +53,46 @@ impl WireReady for SyncMsg{
             _x=>_x
         }
     }
-}
\ No newline at end of file
+}
+
+/// Sent with `SyncState::RESET`: what a node's next run looks like
+#[derive(Debug,Serialize,Deserialize,Clone,PartialEq)]
+pub struct RunSpec{
+    pub run: usize,
+    /// Session nonce for the run, so stragglers from the last run are dropped
+    pub session: u64,
+    pub input: u64,
+    /// Set when the membership changes with this run
+    pub membership: Option<Membership>,
+}
+
+impl RunSpec{
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize run spec")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// The replicas taking part from an epoch on. Ids are never reused: a
+/// replica that leaves is revoked and keeps its id.
+#[derive(Debug,Serialize,Deserialize,Clone,PartialEq,Eq)]
+pub struct Membership{
+    pub epoch: u64,
+    /// Replicas 0..num_nodes, revoked ones included
+    pub num_nodes: usize,
+    pub num_faults: usize,
+    pub revoked: Vec<Replica>,
+}
+
+impl Membership{
+    pub fn is_member(&self, replica: Replica) -> bool {
+        replica < self.num_nodes && !self.revoked.contains(&replica)
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = Replica> + '_ {
+        (0..self.num_nodes).filter(move |rep| !self.revoked.contains(rep))
+    }
+}
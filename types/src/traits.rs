@@ -1,5 +1,5 @@
 use crypto::hash::Hash;
-use crate::{Height, Replica};
+use crate::{Height, Replica, WireError};
 use std::sync::Arc;
 
 /// Block trait, anything that claims itself to be a block must satisfy these traits
@@ -25,8 +25,8 @@ pub trait TxTrait
 /// A wire trait tells us that the object can be encoded to/decoded from the
 /// network.
 pub trait WireReady: Send + Sync + Clone {
-    /// How to decode from bytes
-    fn from_bytes(data: &[u8]) -> Self;
+    /// How to decode from bytes; a malformed frame is an error, never a panic
+    fn from_bytes(data: &[u8]) -> Result<Self, WireError>;
 
     /// How to initialize self
     fn init(self) -> Self;
@@ -38,9 +38,9 @@ pub trait WireReady: Send + Sync + Clone {
 impl<A> WireReady for Arc<A> 
 where A:WireReady,
 {
-    fn from_bytes(data: &[u8]) -> Arc<A> {
-        let a = A::from_bytes(data);
-        Arc::new(a)
+    fn from_bytes(data: &[u8]) -> Result<Arc<A>, WireError> {
+        let a = A::from_bytes(data)?;
+        Ok(Arc::new(a))
     }
 
     fn to_bytes(&self) -> Vec<u8> {